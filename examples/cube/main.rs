@@ -63,7 +63,7 @@ fn main() {
     let renderer = Renderer::new(scene, canvas, s);
 
     let start = std::time::Instant::now();
-    let image = renderer.multi_thread_render(64, 64, 16, 32);
+    let image = renderer.multi_thread_render(64, 64, 16, 32).unwrap();
 
     let end = std::time::Instant::now();
     println!("coast time: {:?}", end.duration_since(start));
@@ -57,13 +57,14 @@ impl AABB {
 }
 
 /// xₘᵢₙ - α < at < xₘₐₓ - α
+/// - return the ray parameters entering and leaving the slab
 #[inline]
 fn compute(origin: f32, direction: f32, min: f32,max :f32) -> (f32,f32) {
     let d = 1f32 / direction;
     let t0 = (min - origin) * d;
     let t1 = (max - origin) * d;
 
-    (t0,t1)
+    if d < 0.0 { (t1,t0) } else { (t0,t1) }
 }
 
 #[inline]
@@ -83,13 +84,14 @@ impl Bound for AABB {
         let (ty0,ty1) = compute(ray.origin.y, ray.direction.y, self.y_min, self.y_max);
         let (tz0,tz1) = compute(ray.origin.z, ray.direction.z, self.z_min, self.z_max);
 
-        let min = min(min(tx1,ty1),tz1);
-        let max = max(max(tx0,ty0),tz0);
+        let t_enter = max(max(tx0,ty0),tz0);
+        let t_exit = min(min(tx1,ty1),tz1);
 
-        if min >= max {
+        // a flat box is hit where entering and leaving meet, a box behind the ray is missed
+        if t_enter > t_exit || t_exit < 0.0 {
             None
         } else {
-            Some(min)
+            Some(t_enter)
         }
     }
 
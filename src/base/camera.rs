@@ -3,6 +3,22 @@ use std::f32::consts::PI;
 use num::Float;
 use super::ray::Ray;
 
+/// Importance of the camera arriving at a point, sampled on the lens
+pub struct ImportanceSample {
+    /// unit direction from the point to the lens
+    pub wi: Vec3,
+    /// the importance of the camera ray reaching the point
+    pub we: f32,
+    /// the density of the lens position by solid angle at the point
+    pub pdf: f32,
+    /// the sampled position on the lens
+    pub lens: Vec3,
+    /// the distance from the point to the lens
+    pub distance: f32,
+    /// the position of the point on the image, as the (u, v) of `get_ray`
+    pub uv: (f32, f32),
+}
+
 pub struct Camera{
     pub origin: Vec3,
     pub horizontal: Vec3,
@@ -12,6 +28,12 @@ pub struct Camera{
     pub u: Vec3,
     pub w: Vec3,
     pub v: Vec3,
+    // the parameters the camera is built from
+    pub look_at: Vec3,
+    pub vup: Vec3,
+    pub vfov: f32,
+    pub aspect: f32,
+    pub focus_dist: f32,
 }
 
 impl Camera {
@@ -32,20 +54,103 @@ impl Camera {
             w: w,
             u: u,
             v: v,
+            look_at,
+            vup,
+            vfov,
+            aspect,
+            focus_dist,
         }
     }
 
-    pub fn get_ray(&self, u: f32, v: f32) -> Ray {
-        let rd = self.lens_radius * random_in_unit_disk();
+    /// the camera is built by `new`, the default camera is not and its parameters only come close to it
+    pub fn has_parameters(&self) -> bool {
+        self.w.length_squared() > 0.0
+    }
+
+    /// Rebuild the camera for another aspect ratio, keep the other parameters
+    pub fn with_aspect(self, aspect: f32) -> Self {
+        Camera::new(self.origin, self.look_at, self.vup, self.vfov, aspect, self.lens_radius * 2.0, self.focus_dist)
+    }
+
+    /// the ray through (u, v) of the image, the lens position is drawn from `sampler`
+    pub fn get_ray(&self, u: f32, v: f32, sampler: &mut dyn Sampler) -> Ray {
+        let rd = self.lens_radius * random_in_unit_disk(sampler);
         let offset = self.u * rd.x + self.v * rd.y;
         Ray::new(self.origin + offset,
                  self.upper_left_corner + u * self.horizontal - v * self.vertical  - self.origin - offset,
                  0.0)
     }
+
+    /// The importance of the ray leaving the lens at `lens` along the unit `direction` and its (u, v) on the image,
+    /// `None` if the ray misses the image. It integrates to 1 over the lens and the directions of the image.
+    /// The default camera has no view direction (see `has_parameters`), every ray misses its image.
+    pub fn importance(&self, lens: Vec3, direction: Vec3) -> Option<(f32, (f32, f32))> {
+        let cos_theta = -direction.dot(&self.w);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+
+        // every ray through a point of the focus plane comes from the same (u, v)
+        let focus = lens + direction * (self.focus_dist / cos_theta);
+        let offset = focus - self.upper_left_corner;
+        let u = offset.dot(&self.horizontal) / self.horizontal.length_squared();
+        let v = -offset.dot(&self.vertical) / self.vertical.length_squared();
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return None;
+        }
+
+        let cos2_theta = cos_theta * cos_theta;
+        Some((1.0 / (self.image_area() * self.lens_area() * cos2_theta * cos2_theta), (u, v)))
+    }
+
+    /// the densities of `get_ray` returning a ray from the lens position `lens` along the unit `direction`,
+    /// of the lens position by area and of the direction by solid angle
+    pub fn pdf_we(&self, lens: Vec3, direction: Vec3) -> (f32, f32) {
+        let cos_theta = -direction.dot(&self.w);
+        if self.importance(lens, direction).is_none() {
+            return (0.0, 0.0);
+        }
+        (1.0 / self.lens_area(), 1.0 / (self.image_area() * cos_theta * cos_theta * cos_theta))
+    }
+
+    /// sample a lens position seen from `point` with two uniform numbers, to connect the point to the camera
+    pub fn sample_wi(&self, point: Vec3, u: (f32, f32)) -> Option<ImportanceSample> {
+        let (x, y) = concentric_sample_disk(u);
+        let lens = self.origin + (self.u * x + self.v * y) * self.lens_radius;
+        let to_lens = lens - point;
+        let distance = to_lens.length();
+        if distance == 0.0 {
+            return None;
+        }
+
+        let wi = to_lens / distance;
+        let (we, uv) = self.importance(lens, -wi)?;
+        let pdf = distance * distance / (wi.dot(&self.w).abs() * self.lens_area());
+        Some(ImportanceSample {
+            wi,
+            we,
+            pdf,
+            lens,
+            distance,
+            uv,
+        })
+    }
+
+    // the area of the image at distance 1 from the lens
+    fn image_area(&self) -> f32 {
+        self.horizontal.length() * self.vertical.length() / (self.focus_dist * self.focus_dist)
+    }
+
+    // a pinhole has no area, it counts as 1
+    fn lens_area(&self) -> f32 {
+        if self.lens_radius > 0.0 { PI * self.lens_radius * self.lens_radius } else { 1.0 }
+    }
 }
 
 impl Default for Camera {
     fn default() -> Self {
+        let look_at = Vec3::new(0.0, 0.0, 2.0);
+        let focus_dist = (Vec3::new(10.0, 2.0, 0.0) - look_at).length();
         Camera {
             origin: Vec3::new(10.0, 2.0, 0.0),
             horizontal: Vec3::new(0.0, 4.0, 0.0),
@@ -55,19 +160,20 @@ impl Default for Camera {
             u: Vec3::new(-1.0,0.0,0.0),
             v: Vec3::new(0.0,0.0,0.0),
             w: Vec3::new(0.0,0.0,0.0),
+            // it is not built from parameters, these are of the look at camera closest to it
+            look_at,
+            vup: Vec3::new(0.0, 0.0, -1.0),
+            vfov: 2.0 * (1.0 / focus_dist).atan().to_degrees(),
+            aspect: 2.0,
+            focus_dist,
         }
     }
 }
 
-use crate::base::random::*;
-
-fn random_in_unit_disk() -> Vec3 {
-    let mut rng = PCG32::new();
-    let mut p = Vec3::new(1., 1., 1.);
-
-    while Vec3::dot(&p,&p) >= 1.0 {
-        p = 2.0 * Vec3::new(rng.rand(), rng.rand(), 0.0) - Vec3::new(1.0, 1.0, 0.0);
-    }
+use crate::base::sampler::Sampler;
+use crate::base::sampling::concentric_sample_disk;
 
-    p
+fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Vec3 {
+    let (x, y) = concentric_sample_disk(sampler.get_2d());
+    Vec3::new(x, y, 0.0)
 }
@@ -0,0 +1,188 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use gk_math::base::f32::Vec3;
+
+/// An item stored in a kd-tree at a point
+pub trait KdPoint {
+    fn position(&self) -> Vec3;
+}
+
+/// Balanced kd-tree of points, for nearest neighbour lookups.
+/// The items are reordered so the median of every range is its node, the tree needs no pointers.
+pub struct KdTree<T> {
+    items: Vec<T>,
+    // the split axis of the node of every item
+    axes: Vec<u8>,
+}
+
+impl<T: KdPoint> KdTree<T> {
+    pub fn new(mut items: Vec<T>) -> Self {
+        let mut axes = vec![0; items.len()];
+        build(&mut items, &mut axes);
+        Self {
+            items,
+            axes,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// The `k` items nearest to `point` no further than `max_distance`, with their squared distances.
+    /// They are ordered from the furthest to the nearest.
+    pub fn nearest(&self, point: Vec3, k: usize, max_distance: f32) -> Vec<(f32, &T)> {
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        if k > 0 {
+            self.search(0, self.items.len(), point, k, max_distance * max_distance, &mut heap);
+        }
+        heap.into_sorted_vec().into_iter().rev().map(|neighbour| (neighbour.distance2, &self.items[neighbour.idx])).collect()
+    }
+
+    fn search(&self, start: usize, end: usize, point: Vec3, k: usize, max_distance2: f32, heap: &mut BinaryHeap<Neighbour>) {
+        if start >= end {
+            return;
+        }
+        let mid = (start + end) / 2;
+        let position = self.items[mid].position();
+
+        let distance2 = (position - point).length_squared();
+        if distance2 <= max_distance2 && (heap.len() < k || distance2 < heap.peek().map_or(f32::MAX, |n| n.distance2)) {
+            heap.push(Neighbour { distance2, idx: mid });
+            if heap.len() > k {
+                heap.pop();
+            }
+        }
+
+        let axis = self.axes[mid] as usize;
+        let offset = component(point, axis) - component(position, axis);
+        let (near, far) = if offset < 0.0 { ((start, mid), (mid + 1, end)) } else { ((mid + 1, end), (start, mid)) };
+        self.search(near.0, near.1, point, k, max_distance2, heap);
+
+        // the far side can only hold nearer items if the split plane is nearer than the furthest found
+        let bound2 = if heap.len() < k { max_distance2 } else { heap.peek().map_or(max_distance2, |n| n.distance2) };
+        if offset * offset < bound2 {
+            self.search(far.0, far.1, point, k, max_distance2, heap);
+        }
+    }
+}
+
+// put the median along the longest side of the bounds at the middle of the range, then build both halves
+fn build<T: KdPoint>(items: &mut [T], axes: &mut [u8]) {
+    if items.len() <= 1 {
+        return;
+    }
+
+    let mut min = items[0].position();
+    let mut max = min;
+    for item in items.iter() {
+        let p = item.position();
+        min = Vec3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+        max = Vec3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+    }
+    let extent = max - min;
+    let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+
+    let mid = items.len() / 2;
+    items.select_nth_unstable_by(mid, |a, b| component(a.position(), axis).total_cmp(&component(b.position(), axis)));
+    axes[mid] = axis as u8;
+
+    let (left_items, right_items) = items.split_at_mut(mid);
+    let (left_axes, right_axes) = axes.split_at_mut(mid);
+    build(left_items, left_axes);
+    build(&mut right_items[1..], &mut right_axes[1..]);
+}
+
+#[inline]
+fn component(v: Vec3, axis: usize) -> f32 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+// a found item, the heap keeps the furthest on top
+struct Neighbour {
+    distance2: f32,
+    idx: usize,
+}
+
+impl PartialEq for Neighbour {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Neighbour {}
+
+impl PartialOrd for Neighbour {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Neighbour {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance2.total_cmp(&other.distance2).then(self.idx.cmp(&other.idx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::random::hash_u64;
+
+    impl KdPoint for Vec3 {
+        fn position(&self) -> Vec3 {
+            *self
+        }
+    }
+
+    fn random_point(i: u64) -> Vec3 {
+        let u = |axis: u64| (hash_u64(&[i, axis]) >> 40) as f32 / (1 << 24) as f32;
+        Vec3::new(u(0) * 4.0 - 2.0, u(1) * 2.0, u(2) * 0.5)
+    }
+
+    #[test]
+    fn nearest_finds_the_points_of_a_brute_force_search() {
+        let points: Vec<Vec3> = (0..2000).map(random_point).collect();
+        let tree = KdTree::new(points.clone());
+        assert_eq!(tree.len(), points.len());
+
+        for query in 0..50 {
+            let point = random_point(10_000 + query);
+            for (k, max_distance) in [(1, f32::MAX), (8, 0.3), (64, 0.2), (64, f32::MAX), (3000, 0.5)] {
+                // from the furthest to the nearest, like the tree
+                let mut expect: Vec<(f32, Vec3)> = points.iter()
+                    .map(|p| ((*p - point).length_squared(), *p))
+                    .filter(|(distance2, _)| *distance2 <= max_distance * max_distance)
+                    .collect();
+                expect.sort_by(|a, b| a.0.total_cmp(&b.0));
+                expect.truncate(k);
+                expect.reverse();
+
+                let found = tree.nearest(point, k, max_distance);
+                assert_eq!(found.len(), expect.len(), "query {} k {} radius {}", query, k, max_distance);
+                for ((distance2, p), (expect_distance2, expect_p)) in found.iter().zip(expect.iter()) {
+                    assert_eq!(distance2.to_bits(), expect_distance2.to_bits(), "query {} k {} radius {}", query, k, max_distance);
+                    assert_eq!([p.x, p.y, p.z].map(f32::to_bits), [expect_p.x, expect_p.y, expect_p.z].map(f32::to_bits));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn an_empty_tree_finds_nothing() {
+        let tree: KdTree<Vec3> = KdTree::new(Vec::new());
+        assert!(tree.is_empty());
+        assert!(tree.nearest(Vec3::new(0.0, 0.0, 0.0), 4, f32::MAX).is_empty());
+        let tree = KdTree::new(vec![Vec3::new(1.0, 0.0, 0.0)]);
+        assert!(tree.nearest(Vec3::new(0.0, 0.0, 0.0), 0, f32::MAX).is_empty());
+    }
+}
@@ -1,10 +1,74 @@
 use gk_math::base::f32::Vec3;
 use gk_math::color::RGB as Color;
 use super::ray::Ray;
+use super::parameter::*;
+use super::sampling::{uniform_sample_sphere, uniform_sphere_pdf};
 
-pub trait Light {
+/// Light arriving at a point, sampled from a light
+pub struct LightSample {
+    /// unit direction from the point to the light
+    pub wi: Vec3,
+    /// the incident radiance, or the irradiance of a delta light
+    pub li: Color,
+    /// the density of `wi` by solid angle, 1 for a delta light
+    pub pdf: f32,
+    /// the distance to the light, `f32::MAX` for a light at infinity
+    pub distance: f32,
+    /// the light is a point or direction, BSDF sampling can never hit it
+    pub delta: bool,
+}
+
+impl LightSample {
+    #[inline]
+    pub fn new(wi: Vec3, li: Color, pdf: f32, distance: f32, delta: bool) -> Self {
+        Self {
+            wi,
+            li,
+            pdf,
+            distance,
+            delta,
+        }
+    }
+}
+
+/// Light leaving a light, the start of a light path
+pub struct EmissionSample {
+    /// the ray leaving the light, with a unit direction
+    pub ray: Ray,
+    /// the emitted radiance, or the intensity of a point light
+    pub le: Color,
+    /// the density of the ray origin by area, 1 for a delta position
+    pub pdf_position: f32,
+    /// the density of the ray direction by solid angle
+    pub pdf_direction: f32,
+    /// the light is a point, a path can only reach it by connecting to it
+    pub delta_position: bool,
+}
+
+impl EmissionSample {
+    #[inline]
+    pub fn new(ray: Ray, le: Color, pdf_position: f32, pdf_direction: f32, delta_position: bool) -> Self {
+        Self {
+            ray,
+            le,
+            pdf_position,
+            pdf_direction,
+            delta_position,
+        }
+    }
+}
+
+pub trait Light: Parameters {
     fn radiation(&self,ray: &Ray) -> Color;
     fn get_ray(&self, to_point: Vec3) -> Ray;
+    /// sample the light arriving at `point` from two uniform numbers
+    fn sample_li(&self, point: Vec3, u: (f32, f32)) -> Option<LightSample>;
+    /// the density of `sample_li` returning `wi` at `point`, zero for delta lights
+    fn pdf_li(&self, point: Vec3, wi: Vec3) -> f32;
+    /// sample a ray leaving the light from four uniform numbers, for the origin and the direction
+    fn sample_le(&self, u_position: (f32, f32), u_direction: (f32, f32)) -> Option<EmissionSample>;
+    /// the densities of `sample_le` returning a ray from `origin` along `direction`, by area and by solid angle
+    fn pdf_le(&self, origin: Vec3, direction: Vec3) -> (f32, f32);
 }
 
 pub struct PointLight {
@@ -46,4 +110,44 @@ impl Light for PointLight {
     fn get_ray(&self, to_point: Vec3) -> Ray {
         Ray { origin: to_point, direction: (self.origin - to_point ).normalized(), time: 0.001 }
     }
+
+    // a point light only shines along the direction to its origin, falling off with the squared distance
+    fn sample_li(&self, point: Vec3, _u: (f32, f32)) -> Option<LightSample> {
+        let to_light = self.origin - point;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+        let li = self.brightness * self.color / distance_squared;
+        Some(LightSample::new(to_light.normalized(), li, 1.0, distance_squared.sqrt(), true))
+    }
+
+    fn pdf_li(&self, _point: Vec3, _wi: Vec3) -> f32 {
+        0.0
+    }
+
+    // a point light shines the same intensity to all directions
+    fn sample_le(&self, _u_position: (f32, f32), u_direction: (f32, f32)) -> Option<EmissionSample> {
+        let direction = uniform_sample_sphere(u_direction);
+        let ray = Ray::new(self.origin, direction, 0.0);
+        Some(EmissionSample::new(ray, self.brightness * self.color, 1.0, uniform_sphere_pdf(), true))
+    }
+
+    fn pdf_le(&self, _origin: Vec3, _direction: Vec3) -> (f32, f32) {
+        (0.0, uniform_sphere_pdf())
+    }
+}
+
+impl Parameters for PointLight {
+    fn kind(&self) -> &'static str {
+        "point_light"
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, Parameter)> {
+        vec![
+            ("origin", Parameter::Vector(self.origin)),
+            ("color", Parameter::Color(self.color)),
+            ("brightness", Parameter::Float(self.brightness)),
+        ]
+    }
 }
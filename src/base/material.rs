@@ -1,6 +1,11 @@
 use super::ray::Ray;
 use super::intersect::Hit;
+use gk_math::base::f32::Vec3;
 use gk_math::color::RGB as Color;
+use super::parameter::Parameters;
+use super::light::LightSample;
+use super::sampling::{uniform_sample_sphere, uniform_sphere_pdf};
+use super::sampler::Sampler;
 
 pub struct Scatter {
     pub attenuation: Color,
@@ -17,15 +22,85 @@ impl Scatter {
     }
 }
 
-pub trait Material {
-    /// Material's scatter property
-    fn scatter(&self, ray: &Ray, hit: &Hit) -> Scatter;
+/// A direction sampled from a BSDF
+pub struct BsdfSample {
+    /// the sampled direction, away from the surface
+    pub wi: Vec3,
+    /// the BSDF value of the direction, without the cosine
+    pub f: Color,
+    /// the density of the direction by solid angle, or the probability of a specular direction
+    pub pdf: f32,
+    /// the direction comes from a delta distribution, `eval` and `pdf` can never return it
+    pub specular: bool,
+    /// the relative index of refraction n_t / n_i of a refracted direction, 1 for a reflected one.
+    /// `f` of a refraction is scaled by 1 / eta^2 for radiance, light carried the other way undoes it
+    pub eta: f32,
+}
+
+impl BsdfSample {
+    #[inline]
+    pub fn new(wi: Vec3, f: Color, pdf: f32, specular: bool) -> Self {
+        Self {
+            wi,
+            f,
+            pdf,
+            specular,
+            eta: 1.0,
+        }
+    }
+
+    pub fn with_eta(mut self, eta: f32) -> Self {
+        self.eta = eta;
+        self
+    }
+}
+
+/// Surface scattering of an object.
+/// `wo` is the direction to the viewer and `wi` the direction to the light, both point away from the surface.
+/// The BSDF values have no cosine, a sample is weighted by `f * |cos(wi, normal)| / pdf`.
+pub trait Material: Parameters {
+    /// the BSDF value of a pair of directions, zero for specular materials
+    fn eval(&self, wo: Vec3, wi: Vec3, hit: &Hit) -> Color;
+    /// sample `wi` for `wo` from two uniform numbers, `None` if the material absorbs the ray
+    fn sample(&self, wo: Vec3, hit: &Hit, u: (f32, f32)) -> Option<BsdfSample>;
+    /// the density of `sample` returning `wi` for `wo`, zero for specular materials
+    fn pdf(&self, wo: Vec3, wi: Vec3, hit: &Hit) -> f32;
+    /// every direction `sample` returns is specular
+    fn is_specular(&self) -> bool {
+        false
+    }
+    /// the color of the surface at `hit`, for albedo layers
+    fn albedo(&self, _hit: &Hit) -> Color {
+        Color::zero()
+    }
+    /// Material's scatter property, a ray sampled by `sample` and its weight, random choices are drawn from `sampler`
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Scatter {
+        let wo = -ray.direction.normalized();
+        match self.sample(wo, hit, sampler.get_2d()) {
+            Some(sample) if sample.pdf > 0.0 => {
+                let cosine = sample.wi.dot(&hit.normal.normalized()).abs();
+                Scatter::new(sample.f * (cosine / sample.pdf), Ray::new(hit.position, sample.wi, hit.time))
+            },
+            _ => Scatter::new(Color::zero(), Ray::new(hit.position, hit.normal, hit.time)),
+        }
+    }
     /// Luminescent materials
     fn emit(&self, _ray: &Ray) -> Color {
         Color::new(0.0, 0.0, 0.0)
     }
 }
 
-pub trait SkyBox {
+/// The radiance of the rays that hit nothing, the skybox is lit like a light at infinity
+pub trait SkyBox: Parameters {
     fn ambient(&self, ray: &Ray) -> Color;
+    /// sample the sky light arriving at `point`, uniform over the sphere
+    fn sample_li(&self, point: Vec3, u: (f32, f32)) -> LightSample {
+        let wi = uniform_sample_sphere(u);
+        let li = self.ambient(&Ray::new(point, wi, 0.0));
+        LightSample::new(wi, li, uniform_sphere_pdf(), f32::MAX, false)
+    }
+    /// the density of `sample_li` returning `wi`
+    fn pdf_li(&self, _wi: Vec3) -> f32 {
+        uniform_sphere_pdf()
+    }
 }
@@ -5,10 +5,14 @@ pub mod intersect;
 pub mod object;
 pub mod bound;
 pub mod bvh;
+pub mod kd_tree;
 pub mod light;
 pub mod optics;
 pub mod tone_mapping;
 pub mod random;
+pub mod sampling;
+pub mod parameter;
+pub mod sampler;
 
 pub use camera::Camera;
 pub use material::*;
@@ -2,6 +2,7 @@ use gk_math::base::f32::Vec3;
 
 use crate::base::intersect::Intersect;
 use crate::base::bound::BoundBuilder;
+use crate::base::parameter::Parameters;
 
 pub struct Object {
     pub idx: usize,
@@ -9,7 +10,7 @@ pub struct Object {
     pub base: Box<dyn ObjectBase + Sync + Send>,
 }
 
-pub trait ObjectBase: Intersect + BoundBuilder {}
+pub trait ObjectBase: Intersect + BoundBuilder + Parameters {}
 
 pub trait ObjectTransfrom {
     fn scale(&mut self, s: f32) -> &mut Self;
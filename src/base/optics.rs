@@ -1,16 +1,10 @@
 use gk_math::base::f32::Vec3;
-use gk_math::transform::rotate::rotate3d;
-use super::random::RNG;
-use std::f32::consts::PI;
+use super::sampler::Sampler;
+use super::sampling::{Frame, uniform_sample_hemisphere};
 
-//calculate  diffuse refkection with Monte Carlo method
-pub fn hemisphere_suface_distributrion(normal: Vec3) -> Vec3 {
-
-    let mut rng = RNG::new();
-
-    let rotation = rotate3d(rng.rand() * 0.5f32 * PI, rng.rand() * 0.5f32 * PI, rng.rand() * 0.5f32 * PI);
-
-    rotation * normal
+//uniform direction of the hemisphere around the normal
+pub fn hemisphere_suface_distributrion(normal: Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+    Frame::new(normal.normalized()).to_world(uniform_sample_hemisphere(sampler.get_2d()))
 }
 
 //reflection
@@ -35,3 +29,18 @@ pub fn shlick(cosine: f32, refract_coe: f32) -> f32 {
     r = r * r;
     r + (1.0 - r) * (1.0 - cosine).powf(5.0)
 }
+
+/// Fresnel reflectance of a dielectric boundary for unpolarized light,
+/// `cos_i` is the cosine of the incident direction and `eta` is the relative index n_t / n_i
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let cos_i = cos_i.abs().min(1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        // total internal reflection
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parl = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perp = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parl * r_parl + r_perp * r_perp) / 2.0
+}
@@ -0,0 +1,19 @@
+use gk_math::base::f32::Vec3;
+use gk_math::color::RGB as Color;
+
+/// A value of a scene file field
+pub enum Parameter {
+    Float(f32),
+    Vector(Vec3),
+    Color(Color),
+    /// mesh data in obj format, written to its own file
+    Obj(String),
+}
+
+/// Expose the parameters of a scene element, so a scene can be written back to a scene file
+pub trait Parameters {
+    /// keyword of the element in a scene file
+    fn kind(&self) -> &'static str;
+    /// field name and value pairs
+    fn parameters(&self) -> Vec<(&'static str, Parameter)>;
+}
@@ -0,0 +1,138 @@
+use crate::base::random::hash_u64;
+
+pub mod independent;
+pub mod sobol;
+pub mod halton;
+pub mod stratified;
+pub use independent::*;
+pub use sobol::*;
+pub use halton::*;
+pub use stratified::*;
+
+/// Source of the random numbers of a pixel sample.
+/// The camera, materials and renderer draw their numbers one dimension after another,
+/// a sampler decides how the numbers of different samples of a pixel are spread.
+pub trait Sampler {
+    /// start drawing the numbers of sample `index` of pixel (x, y)
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize);
+    /// next number in [0, 1)
+    fn get_1d(&mut self) -> f32;
+    /// next two numbers in [0, 1)
+    fn get_2d(&mut self) -> (f32, f32) {
+        let u = self.get_1d();
+        (u, self.get_1d())
+    }
+}
+
+/// The samplers a render can use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplerKind {
+    /// independent uniform random numbers
+    Independent,
+    /// Owen scrambled Sobol sequence
+    Sobol,
+    /// Owen scrambled Halton sequence
+    Halton,
+    /// jittered grid of the given samples per pixel
+    Stratified(usize),
+}
+
+impl SamplerKind {
+    pub fn create(&self, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Stratified(samples_per_pixel) => Box::new(StratifiedSampler::new(seed, *samples_per_pixel)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Sobol => "sobol",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Stratified(_) => "stratified",
+        }
+    }
+
+    /// the sampler of a name, a stratified sampler stratifies `samples_per_pixel` samples
+    pub fn from_name(name: &str, samples_per_pixel: usize) -> Option<Self> {
+        match name {
+            "independent" => Some(SamplerKind::Independent),
+            "sobol" => Some(SamplerKind::Sobol),
+            "halton" => Some(SamplerKind::Halton),
+            "stratified" => Some(SamplerKind::Stratified(samples_per_pixel.max(1))),
+            _ => None,
+        }
+    }
+}
+
+// the pixel as one integer
+#[inline]
+fn pixel_key(x: usize, y: usize) -> u64 {
+    ((y as u64) << 32) | x as u64
+}
+
+// a random 32 bits number of (seed, pixel, sample index, dimension)
+#[inline]
+fn hash_u32(seed: u64, pixel: u64, index: u64, dimension: u64) -> u32 {
+    (hash_u64(&[seed, pixel, index, dimension]) >> 32) as u32
+}
+
+// the largest f32 below 1
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+// element `i` of a random permutation of 0..l chosen by `p`, the hash permutation of Kensler
+fn permutation_element(i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    // every step is a bijection of the bits under `w`, walk the cycle until the value is in 0..l
+    let mut i = i;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+
+    (i.wrapping_add(p)) % l
+}
+
+// 24 bits fit the f32 mantissa, so the number never rounds up to 1
+#[inline]
+fn to_unit_f32(bits: u32) -> f32 {
+    (bits >> 8) as f32 * (1.0 / 16777216.0)
+}
+
+// the Pearson correlation of two lists of numbers, for the tests of the samplers
+#[cfg(test)]
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let mean = |v: &[f32]| v.iter().sum::<f32>() / v.len() as f32;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let covariance = a.iter().zip(b.iter()).map(|(a, b)| (a - mean_a) * (b - mean_b)).sum::<f32>();
+    let variance = |v: &[f32], mean: f32| v.iter().map(|u| (u - mean) * (u - mean)).sum::<f32>();
+    covariance / (variance(a, mean_a) * variance(b, mean_b)).sqrt()
+}
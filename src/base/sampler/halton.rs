@@ -0,0 +1,158 @@
+use super::*;
+
+/// Halton sequence of the sample indices of a pixel, dimension d is the radical inverse in the d-th prime base.
+/// The digits are Owen scrambled with a seed of (seed, pixel, dimension), so every pixel uses a different point set.
+/// Dimensions past `HALTON_DIMENSIONS` fall back to independent random numbers.
+#[derive(Copy, Clone)]
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: u64,
+    index: u64,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = pixel_key(x, y);
+        self.index = index as u64;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        if dimension < HALTON_DIMENSIONS {
+            let scramble = hash_u32(self.seed, self.pixel, u64::MAX, dimension as u64);
+            owen_scrambled_radical_inverse(PRIMES[dimension], self.index, scramble)
+        } else {
+            to_unit_f32(hash_u32(self.seed, self.pixel, self.index, dimension as u64))
+        }
+    }
+}
+
+/// number of dimensions drawn from the Halton sequence
+pub const HALTON_DIMENSIONS: usize = PRIMES.len();
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+/// The digits of `index` in `base` mirrored around the radix point, 0.d0 d1 d2 ...
+pub fn radical_inverse(base: u32, index: u64) -> f32 {
+    owen_scramble_digits(base, index, None)
+}
+
+// radical inverse with every digit permuted by a permutation chosen from the digits before it,
+// the digits past the last one of `index` are zeros and get permuted too
+fn owen_scrambled_radical_inverse(base: u32, index: u64, scramble: u32) -> f32 {
+    owen_scramble_digits(base, index, Some(scramble))
+}
+
+fn owen_scramble_digits(base: u32, index: u64, scramble: Option<u32>) -> f32 {
+    let base = base as u64;
+    let mut index = index;
+    let mut reversed_digits: u64 = 0;
+    let mut limit: u64 = 1;
+
+    // enough digits for the f32 mantissa
+    while limit < 1 << 24 {
+        let next = index / base;
+        let mut digit = index - next * base;
+        if let Some(scramble) = scramble {
+            let digit_seed = (hash_u64(&[scramble as u64, reversed_digits, limit]) >> 32) as u32;
+            digit = permutation_element(digit as u32, base as u32, digit_seed) as u64;
+        }
+        reversed_digits = reversed_digits * base + digit;
+        limit *= base;
+        index = next;
+    }
+
+    ((reversed_digits as f64 / limit as f64) as f32).min(ONE_MINUS_EPSILON)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the first `dimensions` numbers of a pixel sample
+    fn numbers(seed: u64, x: usize, y: usize, index: usize, dimensions: usize) -> Vec<f32> {
+        let mut sampler = HaltonSampler::new(seed);
+        sampler.start_pixel_sample(x, y, index);
+        (0..dimensions).map(|_| sampler.get_1d()).collect()
+    }
+
+    #[test]
+    fn the_radical_inverse_mirrors_the_digits() {
+        assert_eq!(radical_inverse(2, 0).to_bits(), 0f32.to_bits());
+        assert_eq!(radical_inverse(2, 1).to_bits(), 0.5f32.to_bits());
+        assert_eq!(radical_inverse(2, 6).to_bits(), 0.375f32.to_bits());
+        // 7 is 21 in base 3, 0.12 is 1/3 + 2/9
+        assert!((radical_inverse(3, 7) - 5.0 / 9.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn samples_are_in_the_unit_interval() {
+        for (x, y) in [(0, 0), (7, 3), (1023, 511)] {
+            for index in 0..256 {
+                // past HALTON_DIMENSIONS the numbers are independent
+                for u in numbers(3, x, y, index, HALTON_DIMENSIONS + 4) {
+                    assert!((0.0..1.0).contains(&u), "{} of pixel ({}, {}) sample {}", u, x, y, index);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn the_same_pixel_sample_draws_the_same_numbers() {
+        let mut sampler = HaltonSampler::new(5);
+        for index in [9, 2, 9, 0, 2] {
+            sampler.start_pixel_sample(4, 6, index);
+            let drawn: Vec<f32> = (0..HALTON_DIMENSIONS + 4).map(|_| sampler.get_1d()).collect();
+            let expect = numbers(5, 4, 6, index, HALTON_DIMENSIONS + 4);
+            assert_eq!(drawn.iter().map(|u| u.to_bits()).collect::<Vec<_>>(),
+                       expect.iter().map(|u| u.to_bits()).collect::<Vec<_>>(), "sample {}", index);
+        }
+    }
+
+    #[test]
+    fn different_seeds_decorrelate() {
+        // both seeds scramble the same points, a single (pixel, dimension) may permute the same way,
+        // so take the numbers of many pixels and dimensions
+        let (a, b): (Vec<f32>, Vec<f32>) = (0..64 * 64)
+            .flat_map(|i| {
+                let (pixel, index) = (i / 64, i % 64);
+                numbers(1, pixel, 3, index, 8).into_iter().zip(numbers(2, pixel, 3, index, 8))
+            })
+            .unzip();
+        let correlation = correlation(&a, &b);
+        assert!(correlation.abs() < 0.1, "correlation {}", correlation);
+        assert!(a.iter().zip(b.iter()).filter(|(a, b)| a == b).count() < a.len() / 100);
+    }
+
+    #[test]
+    fn the_first_two_dimensions_put_one_point_in_every_box_of_2_by_3() {
+        // the first 2^a 3^b points fall one in each box of 2^-a by 3^-b
+        let (columns, rows) = (8, 9);
+        let mut count = [0; 8 * 9];
+        for index in 0..columns * rows {
+            let mut sampler = HaltonSampler::new(11);
+            sampler.start_pixel_sample(5, 9, index);
+            let (u, v) = sampler.get_2d();
+            count[(v * rows as f32) as usize * columns + (u * columns as f32) as usize] += 1;
+        }
+        assert!(count.iter().all(|n| *n == 1), "{:?}", count);
+    }
+}
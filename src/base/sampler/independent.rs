@@ -0,0 +1,37 @@
+use super::*;
+
+/// Reproducible random numbers of a pixel sample.
+/// Every number is a hash of (seed, pixel, sample index, dimension), so a pixel sample draws
+/// the same numbers no matter which thread, tile or pass renders it.
+#[derive(Copy, Clone)]
+pub struct IndependentSampler {
+    seed: u64,
+    pixel: u64,
+    index: u64,
+    dimension: u64,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = pixel_key(x, y);
+        self.index = index as u64;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let bits = hash_u32(self.seed, self.pixel, self.index, self.dimension);
+        self.dimension += 1;
+        to_unit_f32(bits)
+    }
+}
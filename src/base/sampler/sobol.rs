@@ -0,0 +1,211 @@
+use super::*;
+
+/// Sobol sequence of the sample indices of a pixel, Owen scrambled with a seed of (seed, pixel, dimension).
+/// The scrambling keeps the stratification of the sequence and makes every pixel use a different point set.
+/// Dimensions past `SOBOL_DIMENSIONS` fall back to independent random numbers.
+#[derive(Copy, Clone)]
+pub struct SobolSampler {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: usize,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = pixel_key(x, y);
+        self.index = index as u32;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        let scramble = hash_u32(self.seed, self.pixel, u64::MAX, dimension as u64);
+        if dimension < SOBOL_DIMENSIONS {
+            to_unit_f32(nested_uniform_scramble(sobol(self.index, dimension), scramble))
+        } else {
+            to_unit_f32(hash_u32(self.seed, self.pixel, self.index as u64, dimension as u64))
+        }
+    }
+}
+
+/// number of dimensions drawn from the Sobol sequence
+pub const SOBOL_DIMENSIONS: usize = JOE_KUO.len() + 1;
+
+/// The `dimension` coordinate of point `index` of the Sobol sequence, as 32 bits fraction
+pub fn sobol(index: u32, dimension: usize) -> u32 {
+    let matrix = &SOBOL_MATRICES[dimension];
+    let mut index = index;
+    let mut result = 0;
+    let mut bit = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= matrix[bit];
+        }
+        index >>= 1;
+        bit += 1;
+    }
+    result
+}
+
+// Owen scrambling by the hash based permutation of Laine and Karras,
+// flipping a bit depends on the higher bits only
+#[inline]
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+// degree s, coefficients a and initial direction numbers m of the primitive polynomials of dimensions 1..,
+// from the new-joe-kuo-6.21201 table of Joe and Kuo
+const JOE_KUO: [(usize, u32, [u32; 7]); 20] = [
+    (1, 0, [1, 0, 0, 0, 0, 0, 0]),
+    (2, 1, [1, 3, 0, 0, 0, 0, 0]),
+    (3, 1, [1, 3, 1, 0, 0, 0, 0]),
+    (3, 2, [1, 1, 1, 0, 0, 0, 0]),
+    (4, 1, [1, 1, 3, 3, 0, 0, 0]),
+    (4, 4, [1, 3, 5, 13, 0, 0, 0]),
+    (5, 2, [1, 1, 5, 5, 17, 0, 0]),
+    (5, 4, [1, 1, 5, 5, 5, 0, 0]),
+    (5, 7, [1, 1, 7, 11, 19, 0, 0]),
+    (5, 11, [1, 1, 5, 1, 1, 0, 0]),
+    (5, 13, [1, 1, 1, 3, 11, 0, 0]),
+    (5, 14, [1, 3, 5, 5, 31, 0, 0]),
+    (6, 1, [1, 3, 3, 9, 7, 49, 0]),
+    (6, 13, [1, 1, 1, 15, 21, 21, 0]),
+    (6, 16, [1, 3, 1, 13, 27, 49, 0]),
+    (6, 19, [1, 1, 1, 15, 7, 5, 0]),
+    (6, 22, [1, 3, 1, 15, 13, 25, 0]),
+    (6, 25, [1, 1, 5, 5, 19, 61, 0]),
+    (7, 1, [1, 3, 7, 11, 23, 15, 103]),
+    (7, 4, [1, 3, 7, 13, 13, 15, 69]),
+];
+
+// the generator matrices as 32 direction numbers per dimension, dimension 0 is the van der Corput sequence
+const SOBOL_MATRICES: [[u32; 32]; SOBOL_DIMENSIONS] = sobol_matrices();
+
+const fn sobol_matrices() -> [[u32; 32]; SOBOL_DIMENSIONS] {
+    let mut matrices = [[0u32; 32]; SOBOL_DIMENSIONS];
+
+    let mut k = 0;
+    while k < 32 {
+        matrices[0][k] = 1 << (31 - k);
+        k += 1;
+    }
+
+    let mut d = 1;
+    while d < SOBOL_DIMENSIONS {
+        let (s, a, m) = JOE_KUO[d - 1];
+        let v = &mut matrices[d];
+        let mut k = 0;
+        while k < 32 {
+            if k < s {
+                v[k] = m[k] << (31 - k);
+            } else {
+                v[k] = v[k - s] ^ (v[k - s] >> s);
+                let mut j = 1;
+                while j < s {
+                    if (a >> (s - 1 - j)) & 1 != 0 {
+                        v[k] ^= v[k - j];
+                    }
+                    j += 1;
+                }
+            }
+            k += 1;
+        }
+        d += 1;
+    }
+
+    matrices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the first `dimensions` numbers of a pixel sample
+    fn numbers(seed: u64, x: usize, y: usize, index: usize, dimensions: usize) -> Vec<f32> {
+        let mut sampler = SobolSampler::new(seed);
+        sampler.start_pixel_sample(x, y, index);
+        (0..dimensions).map(|_| sampler.get_1d()).collect()
+    }
+
+    #[test]
+    fn samples_are_in_the_unit_interval() {
+        for (x, y) in [(0, 0), (7, 3), (1023, 511)] {
+            for index in 0..256 {
+                // past SOBOL_DIMENSIONS the numbers are independent
+                for u in numbers(3, x, y, index, SOBOL_DIMENSIONS + 4) {
+                    assert!((0.0..1.0).contains(&u), "{} of pixel ({}, {}) sample {}", u, x, y, index);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn the_same_pixel_sample_draws_the_same_numbers() {
+        let mut sampler = SobolSampler::new(5);
+        for index in [9, 2, 9, 0, 2] {
+            sampler.start_pixel_sample(4, 6, index);
+            let drawn: Vec<f32> = (0..SOBOL_DIMENSIONS + 4).map(|_| sampler.get_1d()).collect();
+            let expect = numbers(5, 4, 6, index, SOBOL_DIMENSIONS + 4);
+            assert_eq!(drawn.iter().map(|u| u.to_bits()).collect::<Vec<_>>(),
+                       expect.iter().map(|u| u.to_bits()).collect::<Vec<_>>(), "sample {}", index);
+        }
+    }
+
+    #[test]
+    fn different_seeds_decorrelate() {
+        // both seeds scramble the same points, a single (pixel, dimension) may flip the same way,
+        // so take the numbers of many pixels and dimensions
+        let (a, b): (Vec<f32>, Vec<f32>) = (0..64 * 64)
+            .flat_map(|i| {
+                let (pixel, index) = (i / 64, i % 64);
+                numbers(1, pixel, 3, index, 8).into_iter().zip(numbers(2, pixel, 3, index, 8))
+            })
+            .unzip();
+        let correlation = correlation(&a, &b);
+        assert!(correlation.abs() < 0.1, "correlation {}", correlation);
+        assert!(a.iter().zip(b.iter()).filter(|(a, b)| a == b).count() < a.len() / 100);
+    }
+
+    #[test]
+    fn the_first_two_dimensions_put_one_point_in_every_elementary_interval() {
+        let m = 8;
+        let points: Vec<(f32, f32)> = (0..1 << m)
+            .map(|index| {
+                let mut sampler = SobolSampler::new(11);
+                sampler.start_pixel_sample(5, 9, index);
+                sampler.get_2d()
+            })
+            .collect();
+
+        // intervals of 2^-a by 2^-(m - a)
+        for a in 0..=m {
+            let (columns, rows) = (1usize << a, 1usize << (m - a));
+            let mut count = vec![0; columns * rows];
+            for (u, v) in points.iter() {
+                count[(v * rows as f32) as usize * columns + (u * columns as f32) as usize] += 1;
+            }
+            assert!(count.iter().all(|n| *n == 1), "intervals of 1/{} by 1/{}", columns, rows);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use super::*;
+
+/// Jittered grid sampling, every `samples_per_pixel` samples of a pixel are one stratified set.
+/// A 1d number falls in its own of `samples_per_pixel` intervals, a 2d number in its own cell of a grid,
+/// the strata are shuffled per (pixel, dimension, set) so the dimensions are not correlated.
+#[derive(Copy, Clone)]
+pub struct StratifiedSampler {
+    seed: u64,
+    samples_per_pixel: u32,
+    // the 2d grid, the cells may be less than `samples_per_pixel`
+    grid_x: u32,
+    grid_y: u32,
+    pixel: u64,
+    index: u64,
+    dimension: u64,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, samples_per_pixel: usize) -> Self {
+        let samples_per_pixel = samples_per_pixel.clamp(1, u32::MAX as usize) as u32;
+        let grid_x = (samples_per_pixel as f64).sqrt() as u32;
+        Self {
+            seed,
+            samples_per_pixel,
+            grid_x,
+            grid_y: samples_per_pixel / grid_x,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    // the stratum of the current sample in this dimension, and a random number for the jitter
+    fn next_stratum(&mut self) -> (u32, f32) {
+        let set = self.index / self.samples_per_pixel as u64;
+        let sample = (self.index % self.samples_per_pixel as u64) as u32;
+        // the extra key keeps the shuffle apart from the jitter of sample `set`
+        let shuffle = (hash_u64(&[self.seed, self.pixel, set, self.dimension, 1]) >> 32) as u32;
+        let stratum = permutation_element(sample, self.samples_per_pixel, shuffle);
+        let jitter = to_unit_f32(hash_u32(self.seed, self.pixel, self.index, self.dimension));
+        self.dimension += 1;
+        (stratum, jitter)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = pixel_key(x, y);
+        self.index = index as u64;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let (stratum, jitter) = self.next_stratum();
+        ((stratum as f32 + jitter) / self.samples_per_pixel as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let (stratum, jitter_x) = self.next_stratum();
+        let jitter_y = to_unit_f32(hash_u32(self.seed, self.pixel, self.index, self.dimension));
+        self.dimension += 1;
+
+        // strata past the grid are spread over the whole square, which keeps the samples uniform
+        if stratum >= self.grid_x * self.grid_y {
+            return (jitter_x, jitter_y);
+        }
+
+        let x = stratum % self.grid_x;
+        let y = stratum / self.grid_x;
+        (((x as f32 + jitter_x) / self.grid_x as f32).min(ONE_MINUS_EPSILON),
+         ((y as f32 + jitter_y) / self.grid_y as f32).min(ONE_MINUS_EPSILON))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the first `dimensions` numbers of a pixel sample
+    fn numbers(sampler: &mut StratifiedSampler, x: usize, y: usize, index: usize, dimensions: usize) -> Vec<f32> {
+        sampler.start_pixel_sample(x, y, index);
+        (0..dimensions).map(|_| sampler.get_1d()).collect()
+    }
+
+    #[test]
+    fn samples_are_in_the_unit_interval() {
+        for samples_per_pixel in [1, 5, 16] {
+            let mut sampler = StratifiedSampler::new(3, samples_per_pixel);
+            for (x, y) in [(0, 0), (7, 3), (1023, 511)] {
+                for index in 0..64 {
+                    let mut drawn = numbers(&mut sampler, x, y, index, 8);
+                    let (u, v) = sampler.get_2d();
+                    drawn.extend([u, v]);
+                    assert!(drawn.iter().all(|u| (0.0..1.0).contains(u)), "{:?} of sample {}", drawn, index);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn the_same_pixel_sample_draws_the_same_numbers() {
+        let mut sampler = StratifiedSampler::new(5, 16);
+        for index in [9, 2, 9, 0, 2] {
+            let drawn = numbers(&mut sampler, 4, 6, index, 8);
+            let expect = numbers(&mut StratifiedSampler::new(5, 16), 4, 6, index, 8);
+            assert_eq!(drawn.iter().map(|u| u.to_bits()).collect::<Vec<_>>(),
+                       expect.iter().map(|u| u.to_bits()).collect::<Vec<_>>(), "sample {}", index);
+        }
+    }
+
+    #[test]
+    fn different_seeds_decorrelate() {
+        let (mut one, mut two) = (StratifiedSampler::new(1, 16), StratifiedSampler::new(2, 16));
+        let (a, b): (Vec<f32>, Vec<f32>) = (0..64 * 64)
+            .flat_map(|i| {
+                let (pixel, index) = (i / 64, i % 64);
+                numbers(&mut one, pixel, 3, index, 8).into_iter().zip(numbers(&mut two, pixel, 3, index, 8))
+            })
+            .unzip();
+        let correlation = correlation(&a, &b);
+        assert!(correlation.abs() < 0.1, "correlation {}", correlation);
+        assert!(a.iter().zip(b.iter()).filter(|(a, b)| a == b).count() < a.len() / 100);
+    }
+
+    #[test]
+    fn every_set_puts_one_sample_in_every_stratum() {
+        let mut sampler = StratifiedSampler::new(7, 16);
+        // the second set too, it is shuffled on its own
+        for set in 0..2 {
+            let mut intervals = [0; 16];
+            let mut cells = [0; 16];
+            for index in set * 16..(set + 1) * 16 {
+                sampler.start_pixel_sample(2, 5, index);
+                intervals[(sampler.get_1d() * 16.0) as usize] += 1;
+                let (u, v) = sampler.get_2d();
+                cells[(v * 4.0) as usize * 4 + (u * 4.0) as usize] += 1;
+            }
+            assert_eq!(intervals, [1; 16], "set {}", set);
+            assert_eq!(cells, [1; 16], "set {}", set);
+        }
+    }
+}
@@ -1,2 +1,103 @@
-use rand::prelude::*;
-use rand::distributions::{Distribution, Uniform};
+use gk_math::base::f32::Vec3;
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+// Warp two uniform numbers in [0, 1) to directions and points.
+// Directions are in a local frame with z as the normal, `Frame` turns them to world space.
+
+/// uniform direction of the hemisphere around z
+pub fn uniform_sample_hemisphere(u: (f32, f32)) -> Vec3 {
+    let z = u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn uniform_hemisphere_pdf() -> f32 {
+    1.0 / (2.0 * PI)
+}
+
+/// direction of the hemisphere around z with a density proportional to cos theta
+pub fn cosine_sample_hemisphere(u: (f32, f32)) -> Vec3 {
+    let (x, y) = concentric_sample_disk(u);
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+    Vec3::new(x, y, z)
+}
+
+pub fn cosine_hemisphere_pdf(cos_theta: f32) -> f32 {
+    cos_theta.max(0.0) / PI
+}
+
+/// uniform direction of the sphere
+pub fn uniform_sample_sphere(u: (f32, f32)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn uniform_sphere_pdf() -> f32 {
+    1.0 / (4.0 * PI)
+}
+
+/// uniform direction of the cone around z with half angle theta_max
+pub fn uniform_sample_cone(u: (f32, f32), cos_theta_max: f32) -> Vec3 {
+    let z = 1.0 - u.0 * (1.0 - cos_theta_max);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn uniform_cone_pdf(cos_theta_max: f32) -> f32 {
+    1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+}
+
+/// Uniform point of the unit disk by the concentric mapping of Shirley and Chiu,
+/// it keeps the stratification of the input better than the polar mapping
+pub fn concentric_sample_disk(u: (f32, f32)) -> (f32, f32) {
+    let x = 2.0 * u.0 - 1.0;
+    let y = 2.0 * u.1 - 1.0;
+    if x == 0.0 && y == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let (r, theta) = if x.abs() > y.abs() {
+        (x, FRAC_PI_4 * (y / x))
+    } else {
+        (y, FRAC_PI_2 - FRAC_PI_4 * (x / y))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+pub fn concentric_disk_pdf() -> f32 {
+    1.0 / PI
+}
+
+/// Orthonormal frame around a normal
+#[derive(Debug, Clone, Copy)]
+pub struct Frame {
+    pub s: Vec3,
+    pub t: Vec3,
+    pub n: Vec3,
+}
+
+impl Frame {
+    /// the frame of a unit normal, by the branchless construction of Duff et al.
+    pub fn new(n: Vec3) -> Self {
+        let sign = 1.0f32.copysign(n.z);
+        let a = -1.0 / (sign + n.z);
+        let b = n.x * n.y * a;
+        Self {
+            s: Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+            t: Vec3::new(b, sign + n.y * n.y * a, -n.y),
+            n,
+        }
+    }
+
+    pub fn to_world(&self, v: Vec3) -> Vec3 {
+        v.x * self.s + v.y * self.t + v.z * self.n
+    }
+
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(v.dot(&self.s), v.dot(&self.t), v.dot(&self.n))
+    }
+}
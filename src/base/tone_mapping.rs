@@ -1,39 +1,317 @@
 use gk_math::color::RGB as Color;
 
+/// The curve that maps linear radiance to the displayable [0, 1]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ToneMapping {
+    /// clamp to [0, 1]
     Linear,
+    /// c / (1 + c), every radiance stays below white
     Reinhard,
+    /// Reinhard with a white point, radiance at or above it maps to white
+    ReinhardExtended(f32),
+    /// the fit of Stephen Hill to the ACES reference rendering and sRGB output transforms
+    Aces,
+    /// the filmic curve of John Hable from Uncharted 2
+    Hable,
+    /// the AgX of Troy Sobotka with its default look, it desaturates bright colors toward white
+    AgX,
+}
+
+/// The encoding of the tone mapped values for an 8 bit image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transfer {
+    /// the sRGB curve most images and screens expect
+    Srgb,
+    /// the Rec. 709 camera curve of video
+    Rec709,
+    /// no encoding, for values that are already display values, such as false colors
+    Linear,
 }
 
 impl ToneMapping {
-    pub fn tone_mapping(&self, color: Color) -> [u8;3] {
-        let [r, g, b] = match &self {
-            ToneMapping::Linear => {
-                [linear_map(color.r), linear_map(color.g), linear_map(color.b)]
-            },
-            ToneMapping::Reinhard => {
-                let color = reinhard(color);
-                [color.r, color.g, color.b]
-            }
+    pub const NAMES: [&'static str; 6] = ["linear", "reinhard", "reinhard-extended", "aces", "hable", "agx"];
+
+    /// the tone mapping of a name of `NAMES`, the white point of `reinhard-extended` is 4
+    pub fn from_name(name: &str) -> Option<ToneMapping> {
+        Some(match name {
+            "linear" => ToneMapping::Linear,
+            "reinhard" => ToneMapping::Reinhard,
+            "reinhard-extended" => ToneMapping::ReinhardExtended(4.0),
+            "aces" => ToneMapping::Aces,
+            "hable" | "filmic" => ToneMapping::Hable,
+            "agx" => ToneMapping::AgX,
+            _ => return None,
+        })
+    }
+
+    /// map the linear radiance to [0, 1]
+    pub fn map(&self, color: Color) -> Color {
+        let color = Color::new(color.r.max(0.0), color.g.max(0.0), color.b.max(0.0));
+        let color = match *self {
+            ToneMapping::Linear => color,
+            ToneMapping::Reinhard => reinhard(color),
+            ToneMapping::ReinhardExtended(white) => reinhard_extended(color, white),
+            ToneMapping::Aces => aces(color),
+            ToneMapping::Hable => hable(color),
+            ToneMapping::AgX => agx(color),
         };
+        Color::new(color.r.clamp(0.0, 1.0), color.g.clamp(0.0, 1.0), color.b.clamp(0.0, 1.0))
+    }
+
+    /// tone map to 8 bit sRGB without exposure
+    pub fn tone_mapping(&self, color: Color) -> [u8;3] {
+        ToneMapper::new(*self).tone_mapping(color)
+    }
+}
+
+impl Transfer {
+    pub const NAMES: [&'static str; 3] = ["srgb", "rec709", "linear"];
+
+    pub fn from_name(name: &str) -> Option<Transfer> {
+        Some(match name {
+            "srgb" => Transfer::Srgb,
+            "rec709" => Transfer::Rec709,
+            "linear" => Transfer::Linear,
+            _ => return None,
+        })
+    }
+
+    /// encode a value of [0, 1]
+    pub fn encode(&self, v: f32) -> f32 {
+        match self {
+            Transfer::Srgb => if v <= 0.003_130_8 { 12.92 * v } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 },
+            Transfer::Rec709 => if v < 0.018 { 4.5 * v } else { 1.099 * v.powf(0.45) - 0.099 },
+            Transfer::Linear => v,
+        }
+    }
+
+    /// the inverse of `encode`
+    pub fn decode(&self, v: f32) -> f32 {
+        match self {
+            Transfer::Srgb => if v <= 0.040_45 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) },
+            Transfer::Rec709 => if v < 0.081 { v / 4.5 } else { ((v + 0.099) / 1.099).powf(1.0 / 0.45) },
+            Transfer::Linear => v,
+        }
+    }
+}
+
+/// How an image is turned into 8 bit values: scaled by the exposure, tone mapped and encoded by the transfer function
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapper {
+    pub tone_mapping: ToneMapping,
+    /// in stops, the radiance is multiplied by 2^exposure
+    pub exposure: f32,
+    pub transfer: Transfer,
+}
+
+impl ToneMapper {
+    /// `tone_mapping` without exposure, encoded as sRGB
+    pub fn new(tone_mapping: ToneMapping) -> ToneMapper {
+        ToneMapper {
+            tone_mapping,
+            exposure: 0.0,
+            transfer: Transfer::Srgb,
+        }
+    }
+
+    pub fn with_exposure(mut self, exposure: f32) -> ToneMapper {
+        self.exposure = exposure;
+        self
+    }
+
+    pub fn with_transfer(mut self, transfer: Transfer) -> ToneMapper {
+        self.transfer = transfer;
+        self
+    }
+
+    /// the encoded display values of the linear radiance in [0, 1]
+    pub fn map(&self, color: Color) -> Color {
+        let color = self.tone_mapping.map(color * 2f32.powf(self.exposure));
+        Color::new(self.transfer.encode(color.r), self.transfer.encode(color.g), self.transfer.encode(color.b))
+    }
+
+    pub fn tone_mapping(&self, color: Color) -> [u8;3] {
+        let color = self.map(color);
+        let quantize = |v: f32| (255.0 * v + 0.5) as u8;
+        [quantize(color.r), quantize(color.g), quantize(color.b)]
+    }
+}
 
-        let r = (255f32 * r) as u8;
-        let g = (255f32 * g) as u8;
-        let b = (255f32 * b) as u8;
-        [r,g,b]
+impl Default for ToneMapper {
+    fn default() -> Self {
+        Self::new(ToneMapping::Linear)
     }
 }
 
-fn linear_map(a: f32) -> f32 {
-    if a > 1.0 {
-        1.0
-    } else if a < 0.0 {
-        0.0
-    } else {
-        a
+impl From<ToneMapping> for ToneMapper {
+    fn from(tone_mapping: ToneMapping) -> Self {
+        ToneMapper::new(tone_mapping)
     }
 }
 
 fn reinhard(color: Color) -> Color {
     color / (color + Color::one())
 }
+
+fn reinhard_extended(color: Color, white: f32) -> Color {
+    let w2 = (white * white).max(f32::MIN_POSITIVE);
+    let map = |c: f32| c * (1.0 + c / w2) / (1.0 + c);
+    Color::new(map(color.r), map(color.g), map(color.b))
+}
+
+fn multiply(m: &[[f32; 3]; 3], c: Color) -> Color {
+    Color::new(
+        m[0][0] * c.r + m[0][1] * c.g + m[0][2] * c.b,
+        m[1][0] * c.r + m[1][1] * c.g + m[1][2] * c.b,
+        m[2][0] * c.r + m[2][1] * c.g + m[2][2] * c.b,
+    )
+}
+
+fn aces(color: Color) -> Color {
+    // linear sRGB to the ACES rendering space, with the look of the reference transform
+    const INPUT: [[f32; 3]; 3] = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    const OUTPUT: [[f32; 3]; 3] = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+    let fit = |v: f32| (v * (v + 0.024_578_6) - 0.000_090_537) / (v * (0.983_729 * v + 0.432_951) + 0.238_081);
+    let c = multiply(&INPUT, color);
+    multiply(&OUTPUT, Color::new(fit(c.r), fit(c.g), fit(c.b)))
+}
+
+fn hable(color: Color) -> Color {
+    let curve = |x: f32| {
+        let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+        (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+    };
+    // the radiance that maps to white, and the exposure the curve was made for
+    let white = 11.2;
+    let scale = 1.0 / curve(white);
+    Color::new(curve(2.0 * color.r) * scale, curve(2.0 * color.g) * scale, curve(2.0 * color.b) * scale)
+}
+
+fn agx(color: Color) -> Color {
+    const INSET: [[f32; 3]; 3] = [
+        [0.842_479_1, 0.078_433_6, 0.079_223_75],
+        [0.042_328_24, 0.878_468_6, 0.079_166_13],
+        [0.042_375_65, 0.078_433_6, 0.879_143],
+    ];
+    const OUTSET: [[f32; 3]; 3] = [
+        [1.196_879, -0.098_020_88, -0.099_029_74],
+        [-0.052_896_85, 1.151_903_1, -0.098_961_18],
+        [-0.052_971_64, -0.098_043_45, 1.151_073_7],
+    ];
+    // the log2 range of the radiance around middle gray
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+
+    // a polynomial fit of the sigmoid of the default contrast, from log encoded to display values
+    let contrast = |x: f32| {
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232
+    };
+    let encode = |v: f32| contrast(((v.max(1e-10).log2() - MIN_EV) / (MAX_EV - MIN_EV)).clamp(0.0, 1.0));
+
+    let c = multiply(&INSET, color);
+    let c = multiply(&OUTSET, Color::new(encode(c.r), encode(c.g), encode(c.b)));
+    // back to linear for the transfer function
+    let linear = |v: f32| v.max(0.0).powf(2.2);
+    Color::new(linear(c.r), linear(c.g), linear(c.b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(v: f32) -> Color {
+        Color::new(v, v, v)
+    }
+
+    // every channel of `color` is `expect`
+    fn assert_gray(color: Color, expect: f32, what: &str) {
+        for (channel, v) in [("r", color.r), ("g", color.g), ("b", color.b)] {
+            assert!((v - expect).abs() < 2e-3, "{} {}: {} is not {}", what, channel, v, expect);
+        }
+    }
+
+    #[test]
+    fn the_curves_map_known_values() {
+        assert_gray(ToneMapping::Linear.map(gray(0.25)), 0.25, "linear");
+        assert_gray(ToneMapping::Linear.map(gray(3.0)), 1.0, "linear");
+        assert_gray(ToneMapping::Reinhard.map(gray(1.0)), 0.5, "reinhard");
+        assert_gray(ToneMapping::Reinhard.map(gray(3.0)), 0.75, "reinhard");
+        // c (1 + c / w^2) / (1 + c)
+        assert_gray(ToneMapping::ReinhardExtended(4.0).map(gray(1.0)), 0.53125, "reinhard-extended");
+        assert_gray(ToneMapping::ReinhardExtended(4.0).map(gray(4.0)), 1.0, "reinhard-extended");
+        // gray stays gray through the ACES matrices, the fit of middle gray and of 1
+        assert_gray(ToneMapping::Aces.map(gray(0.18)), 0.105_591, "aces");
+        assert_gray(ToneMapping::Aces.map(gray(1.0)), 0.619_115, "aces");
+        // white is 11.2 at the exposure of 2 of the curve
+        assert_gray(ToneMapping::Hable.map(gray(0.18)), 0.128_338, "hable");
+        assert_gray(ToneMapping::Hable.map(gray(1.0)), 0.492_919, "hable");
+        assert_gray(ToneMapping::Hable.map(gray(5.6)), 1.0, "hable");
+        // the sigmoid of middle gray and of 1, back to linear by 2.2
+        assert_gray(ToneMapping::AgX.map(gray(0.18)), 0.214_519, "agx");
+        assert_gray(ToneMapping::AgX.map(gray(1.0)), 0.590_159, "agx");
+
+        for tone_mapping in ToneMapping::NAMES.map(|name| ToneMapping::from_name(name).unwrap()) {
+            assert_gray(tone_mapping.map(gray(-1.0)), 0.0, &format!("{:?} of a negative", tone_mapping));
+            let mut last = 0.0;
+            for v in [0.01, 0.1, 0.5, 1.0, 2.0, 8.0] {
+                let mapped = tone_mapping.map(gray(v)).g;
+                assert!(mapped >= last && mapped <= 1.0, "{:?} is not increasing at {}", tone_mapping, v);
+                last = mapped;
+            }
+        }
+    }
+
+    #[test]
+    fn the_transfers_encode_known_values() {
+        for (transfer, v, expect) in [
+            (Transfer::Srgb, 0.0, 0.0),
+            (Transfer::Srgb, 0.003_130_8, 0.040_45),
+            (Transfer::Srgb, 0.18, 0.461_356),
+            (Transfer::Srgb, 0.5, 0.735_357),
+            (Transfer::Srgb, 1.0, 1.0),
+            (Transfer::Rec709, 0.01, 0.045),
+            (Transfer::Rec709, 0.5, 0.705_515),
+            (Transfer::Rec709, 1.0, 1.0),
+            (Transfer::Linear, 0.5, 0.5),
+        ] {
+            let encoded = transfer.encode(v);
+            assert!((encoded - expect).abs() < 1e-5, "{:?} of {} is {}, not {}", transfer, v, encoded, expect);
+        }
+    }
+
+    #[test]
+    fn decode_is_the_inverse_of_encode() {
+        for transfer in Transfer::NAMES.map(|name| Transfer::from_name(name).unwrap()) {
+            for i in 0..=1000 {
+                let v = i as f32 / 1000.0;
+                let decoded = transfer.decode(transfer.encode(v));
+                assert!((decoded - v).abs() < 1e-5, "{:?}: {} decodes to {}", transfer, v, decoded);
+            }
+        }
+    }
+
+    #[test]
+    fn the_8_bit_values_are_srgb_encoded_and_rounded() {
+        let mapper = ToneMapper::new(ToneMapping::Linear);
+        assert_eq!(mapper.tone_mapping(gray(0.0)), [0; 3]);
+        // 0.735 of 255 rounds to 188, the linear value truncated was 127
+        assert_eq!(mapper.tone_mapping(gray(0.5)), [188; 3]);
+        assert_eq!(mapper.tone_mapping(gray(1.0)), [255; 3]);
+        assert_eq!(mapper.tone_mapping(gray(2.0)), [255; 3]);
+        let linear = mapper.with_transfer(Transfer::Linear);
+        assert_eq!(linear.tone_mapping(gray(0.5)), [128; 3]);
+        assert_eq!(linear.tone_mapping(gray(0.2)), [51; 3]);
+        // one stop of exposure doubles the radiance
+        assert_eq!(linear.with_exposure(1.0).tone_mapping(gray(0.25)), [128; 3]);
+    }
+}
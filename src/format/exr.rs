@@ -0,0 +1,130 @@
+use std::path::Path;
+
+use exr::prelude::*;
+use gk_math::color::RGB as Color;
+
+use super::image_data::ImageData;
+
+/// The sample type of an OpenEXR file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExrPixel {
+    /// 16 bit floats, half the size, about 3 significant digits
+    Half,
+    /// 32 bit floats, the radiance as it was rendered
+    Float,
+}
+
+/// Write linear images of the same size to one OpenEXR file, without tone mapping.
+/// Every image is a group of R, G and B channels named `<name>.R`, ... , the image named `beauty`
+/// is the plain R, G and B channels most viewers show. The samples are compressed losslessly.
+pub fn write_exr(path: impl AsRef<Path>, layers: &[(&str, &ImageData)], pixel: ExrPixel) -> std::result::Result<(), String> {
+    let path = path.as_ref();
+    let (width, height) = match layers.first() {
+        Some((_, image)) => (image.width(), image.height()),
+        None => return Err(format!("{}: no layer to write", path.display())),
+    };
+
+    let mut channels = Vec::with_capacity(layers.len() * 3);
+    for (name, image) in layers {
+        if image.width() != width || image.height() != height {
+            return Err(format!("{}: layer `{}` is {}x{}, but the image is {}x{}", path.display(), name, image.width(), image.height(), width, height));
+        }
+        let prefix = if *name == "beauty" { String::new() } else { format!("{}.", name) };
+        for (channel, component) in ["R", "G", "B"].iter().enumerate() {
+            let samples = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| {
+                let color = image[(x, y)];
+                [color.r, color.g, color.b][channel]
+            });
+            let samples = match pixel {
+                ExrPixel::Half => FlatSamples::F16(samples.map(f16::from_f32).collect()),
+                ExrPixel::Float => FlatSamples::F32(samples.collect()),
+            };
+            channels.push(AnyChannel::new(format!("{}{}", prefix, component).as_str(), samples));
+        }
+    }
+
+    if let Some(parent) = path.parent() && !parent.as_os_str().is_empty() && !parent.exists() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+    }
+
+    let layer = Layer::new((width, height), LayerAttributes::default(), Encoding::SMALL_LOSSLESS, AnyChannels::sort(SmallVec::from_vec(channels)));
+    Image::from_layer(layer).write().to_file(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// read the R, G and B channels of the first layer of an OpenEXR file, the `beauty` layer of `write_exr`
+pub fn read_exr(path: impl AsRef<Path>) -> std::result::Result<ImageData, String> {
+    let path = path.as_ref();
+    let image = read_first_rgba_layer_from_file(
+        path,
+        |size, _| ImageData::new(size.width(), size.height()),
+        |image: &mut ImageData, position, (r, g, b, _): (f32, f32, f32, f32)| image[(position.x(), position.y())] = Color::new(r, g, b),
+    ).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(image.layer_data.channel_data.pixels)
+}
+
+/// the path names an OpenEXR file
+pub fn is_exr(path: impl AsRef<Path>) -> bool {
+    path.as_ref().extension().is_some_and(|extension| extension.eq_ignore_ascii_case("exr"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::ImageLayers;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("solas_exr_{}_{}", name, std::process::id())).join("image.exr")
+    }
+
+    // values over several magnitudes, distinct in every channel and pixel
+    fn image(offset: f32) -> ImageData {
+        let mut image = ImageData::new(7, 5);
+        for y in 0..5 {
+            for x in 0..7 {
+                let v = (x + 7 * y) as f32;
+                image[(x, y)] = Color::new(offset + v * 0.013, offset + 1.0 / (v + 1.0), offset + v * v * 1.7);
+            }
+        }
+        image
+    }
+
+    fn assert_close(a: &ImageData, b: &ImageData, relative: f32) {
+        assert_eq!((a.width(), a.height()), (b.width(), b.height()));
+        for y in 0..a.height() {
+            for x in 0..a.width() {
+                let (a, b) = (a[(x, y)], b[(x, y)]);
+                for (a, b) in [(a.r, b.r), (a.g, b.g), (a.b, b.b)] {
+                    assert!((a - b).abs() <= a.abs() * relative, "pixel ({}, {}): {} read as {}", x, y, a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn write_and_read_keep_the_radiance() {
+        // half floats keep 11 significant bits
+        for (pixel, relative) in [(ExrPixel::Float, 0.0), (ExrPixel::Half, 1.0 / 2048.0)] {
+            let path = temp_path(&format!("{:?}", pixel));
+            let _ = fs::remove_dir_all(path.parent().unwrap());
+            let original = image(0.0);
+            original.write_exr(&path, pixel).unwrap();
+            assert_close(&original, &read_exr(&path).unwrap(), relative);
+            let _ = fs::remove_dir_all(path.parent().unwrap());
+        }
+    }
+
+    #[test]
+    fn the_beauty_layer_is_read_from_a_file_of_layers() {
+        let path = temp_path("layers");
+        let mut layers = ImageLayers::new(7, 5);
+        layers.push("albedo", image(10.0)).unwrap();
+        layers.push("beauty", image(0.0)).unwrap();
+        layers.push("normal", image(20.0)).unwrap();
+        layers.write_exr(&path, ExrPixel::Float).unwrap();
+        assert_close(&image(0.0), &read_exr(&path).unwrap(), 0.0);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,295 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use gk_math::color::RGB as Color;
+
+use super::image_data::ImageData;
+
+// Radiance RGBE images: a text header, then every pixel as 8 bit red, green and blue mantissas sharing an 8 bit exponent.
+// Scanlines are written with the run length encoding of the Radiance library.
+
+/// write the linear radiance of `image` to a Radiance `.hdr` file
+pub fn write_hdr(image: &ImageData, path: impl AsRef<Path>) -> Result<(), String> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() && !parent.as_os_str().is_empty() && !parent.exists() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+    }
+    let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut writer = BufWriter::new(file);
+    let mut write = |bytes: &[u8]| writer.write_all(bytes).map_err(|e| format!("{}: {}", path.display(), e));
+
+    write(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n")?;
+    write(format!("-Y {} +X {}\n", image.height(), image.width()).as_bytes())?;
+
+    let width = image.width();
+    let mut scanline = vec![[0u8; 4]; width];
+    let mut encoded = Vec::new();
+    for y in 0..image.height() {
+        for (x, rgbe) in scanline.iter_mut().enumerate() {
+            *rgbe = to_rgbe(image[(x, y)]);
+        }
+
+        encoded.clear();
+        if (8..0x8000).contains(&width) {
+            encoded.extend_from_slice(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8]);
+            for component in 0..4 {
+                let values: Vec<u8> = scanline.iter().map(|rgbe| rgbe[component]).collect();
+                encode_runs(&values, &mut encoded);
+            }
+        } else {
+            // a scanline of this width can not be run length encoded
+            encoded.extend(scanline.iter().flatten());
+        }
+        write(&encoded)?;
+    }
+
+    writer.flush().map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// read a Radiance `.hdr` file of the usual -Y height +X width orientation
+pub fn read_hdr(path: impl AsRef<Path>) -> Result<ImageData, String> {
+    let path = path.as_ref();
+    let error = |message: &str| format!("{}: {}", path.display(), message);
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let file_len = file.metadata().map_err(|e| format!("{}: {}", path.display(), e))?.len();
+    let mut reader = BufReader::new(file);
+
+    let mut line = String::new();
+    // the bytes of the header read so far
+    let mut header_len = 0;
+    let mut read_line = |line: &mut String| {
+        line.clear();
+        let len = reader.read_line(line).map_err(|e| format!("{}: {}", path.display(), e))?;
+        header_len += len as u64;
+        Ok::<usize, String>(len)
+    };
+
+    read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(error("not a Radiance hdr file"));
+    }
+    loop {
+        if read_line(&mut line)? == 0 {
+            return Err(error("the header has no end"));
+        }
+        let field = line.trim_end();
+        if field.is_empty() {
+            break;
+        }
+        if let Some(format) = field.strip_prefix("FORMAT=") && format != "32-bit_rle_rgbe" {
+            return Err(error(&format!("unsupported format `{}`", format)));
+        }
+    }
+
+    read_line(&mut line)?;
+    let resolution: Vec<&str> = line.split_whitespace().collect();
+    let (height, width) = match resolution.as_slice() {
+        ["-Y", height, "+X", width] => (
+            height.parse::<usize>().map_err(|_| error("invalid height"))?,
+            width.parse::<usize>().map_err(|_| error("invalid width"))?,
+        ),
+        _ => return Err(error(&format!("unsupported orientation `{}`", line.trim_end()))),
+    };
+
+    // the size comes from the file, it has to fit in the rest of the file before anything is allocated
+    let data_len = file_len.saturating_sub(header_len);
+    let min_len = width.checked_mul(height).and_then(|_| height.checked_mul(min_scanline_len(width)));
+    if min_len.is_none_or(|len| len as u64 > data_len) {
+        return Err(error(&format!("the data of {}x{} pixels is cut short", width, height)));
+    }
+
+    let mut image = ImageData::new(width, height);
+    let mut scanline = vec![[0u8; 4]; width];
+    let mut read = |bytes: &mut [u8]| reader.read_exact(bytes).map_err(|e| format!("{}: {}", path.display(), e));
+    for y in 0..height {
+        if width == 0 {
+            break;
+        }
+
+        let mut first = [0u8; 4];
+        read(&mut first)?;
+        let encoded = (8..0x8000).contains(&width) && first[0] == 2 && first[1] == 2 && first[2] & 0x80 == 0;
+        if !encoded {
+            scanline[0] = first;
+            for rgbe in scanline.iter_mut().skip(1) {
+                read(rgbe)?;
+            }
+        } else {
+            if ((first[2] as usize) << 8 | first[3] as usize) != width {
+                return Err(error("scanline width mismatch"));
+            }
+            for component in 0..4 {
+                let mut x = 0;
+                while x < width {
+                    let mut count = [0u8; 1];
+                    read(&mut count)?;
+                    let (count, run) = if count[0] > 128 { ((count[0] - 128) as usize, true) } else { (count[0] as usize, false) };
+                    if count == 0 || x + count > width {
+                        return Err(error("bad scanline data"));
+                    }
+                    if run {
+                        let mut value = [0u8; 1];
+                        read(&mut value)?;
+                        for rgbe in scanline[x..x + count].iter_mut() {
+                            rgbe[component] = value[0];
+                        }
+                    } else {
+                        let mut values = vec![0u8; count];
+                        read(&mut values)?;
+                        for (rgbe, value) in scanline[x..x + count].iter_mut().zip(values) {
+                            rgbe[component] = value;
+                        }
+                    }
+                    x += count;
+                }
+            }
+        }
+
+        for (x, rgbe) in scanline.iter().enumerate() {
+            image[(x, y)] = from_rgbe(*rgbe);
+        }
+    }
+
+    Ok(image)
+}
+
+// the fewest bytes a scanline of `width` pixels takes, every component in runs of 127
+fn min_scanline_len(width: usize) -> usize {
+    if width == 0 {
+        0
+    } else if (8..0x8000).contains(&width) {
+        4 + 4 * 2 * width.div_ceil(127)
+    } else {
+        4 * width
+    }
+}
+
+// the mantissas of the largest component are in [128, 256)
+fn to_rgbe(color: Color) -> [u8; 4] {
+    let v = color.r.max(color.g).max(color.b);
+    if v.is_nan() || v < 1e-32 {
+        return [0; 4];
+    }
+    let v = v.min(f32::MAX);
+    // v = m * 2^e with m in [0.5, 1)
+    let mut e = v.log2().floor() as i32 + 1;
+    if v / 2f32.powi(e) >= 1.0 {
+        e += 1;
+    } else if v / 2f32.powi(e) < 0.5 {
+        e -= 1;
+    }
+    // the largest exponent byte, larger values saturate
+    let e = e.min(127);
+    let scale = 256.0 / 2f32.powi(e);
+    let mantissa = |c: f32| (c.max(0.0) * scale).min(255.0) as u8;
+    [mantissa(color.r), mantissa(color.g), mantissa(color.b), (e + 128) as u8]
+}
+
+fn from_rgbe(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::zero();
+    }
+    // the middle of the mantissa step
+    let f = 2f32.powi(rgbe[3] as i32 - (128 + 8));
+    Color::new((rgbe[0] as f32 + 0.5) * f, (rgbe[1] as f32 + 0.5) * f, (rgbe[2] as f32 + 0.5) * f)
+}
+
+// runs of at least 4 equal bytes as (128 + count, byte), the bytes between them as (count, bytes...)
+fn encode_runs(values: &[u8], out: &mut Vec<u8>) {
+    const MIN_RUN: usize = 4;
+    let mut start = 0;
+    while start < values.len() {
+        // find the next run long enough to encode
+        let mut run_start = start;
+        let mut run_count = 0;
+        while run_start < values.len() {
+            run_count = 1;
+            while run_count < 127 && run_start + run_count < values.len() && values[run_start + run_count] == values[run_start] {
+                run_count += 1;
+            }
+            if run_count >= MIN_RUN {
+                break;
+            }
+            run_start += run_count;
+        }
+        if run_count < MIN_RUN {
+            run_start = values.len();
+        }
+
+        // the bytes before the run
+        while start < run_start {
+            let count = (run_start - start).min(128);
+            out.push(count as u8);
+            out.extend_from_slice(&values[start..start + count]);
+            start += count;
+        }
+
+        if run_start < values.len() {
+            out.push((128 + run_count) as u8);
+            out.push(values[run_start]);
+            start = run_start + run_count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("solas_hdr_{}_{}", name, std::process::id())).join("image.hdr")
+    }
+
+    // a gradient over several magnitudes with flat runs, wide enough for the run length encoding
+    fn image(width: usize) -> ImageData {
+        let mut image = ImageData::new(width, 3);
+        for y in 0..3 {
+            for x in 0..width {
+                let v = if x < width / 2 { 0.25 } else { (x * (y + 1)) as f32 * 0.37 };
+                image[(x, y)] = Color::new(v, v * 0.5 + 0.01, 1000.0 / (x + 1) as f32);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn write_and_read_keep_the_radiance() {
+        for width in [5, 40] {
+            let path = temp_path(&format!("round_trip_{}", width));
+            let _ = fs::remove_dir_all(path.parent().unwrap());
+            let original = image(width);
+            write_hdr(&original, &path).unwrap();
+            let read = read_hdr(&path).unwrap();
+            assert_eq!((read.width(), read.height()), (width, 3));
+            for y in 0..3 {
+                for x in 0..width {
+                    let (a, b) = (original[(x, y)], read[(x, y)]);
+                    // the mantissas of 8 bits are relative to the largest component
+                    let tolerance = a.r.max(a.g).max(a.b) / 128.0;
+                    for (a, b) in [(a.r, b.r), (a.g, b.g), (a.b, b.b)] {
+                        assert!((a - b).abs() <= tolerance, "pixel ({}, {}): {} read as {}", x, y, a, b);
+                    }
+                }
+            }
+            let _ = fs::remove_dir_all(path.parent().unwrap());
+        }
+    }
+
+    #[test]
+    fn a_truncated_or_oversized_file_is_an_error() {
+        let path = temp_path("truncated");
+        write_hdr(&image(40), &path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 10]).unwrap();
+        assert!(read_hdr(&path).is_err());
+
+        let header = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", usize::MAX, usize::MAX);
+        fs::write(&path, header).unwrap();
+        assert!(read_hdr(&path).is_err());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -3,7 +3,10 @@ use std::path::Path;
 
 use gk_math::color::RGB as Color;
 
-use crate::tone_mapping::ToneMapping;
+use crate::tone_mapping::{ToneMapper, Transfer};
+use super::exr::{ExrPixel, is_exr, read_exr, write_exr};
+use super::hdr::{read_hdr, write_hdr};
+use super::pfm::{read_pfm, write_pfm};
 
 pub struct ImageData {
     width: usize,
@@ -29,7 +32,51 @@ impl ImageData {
         }
     }
 
-    pub fn write_to_file(&self, tone_mapping: ToneMapping, path: impl AsRef<Path>) -> Result<(), String> {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Read an image in the format of the extension of `path`. The `.exr`, `.hdr` and `.pfm` files are linear radiance,
+    /// the other formats are read by the image crate and decoded from sRGB to linear values in [0, 1], as `write_to_file` encodes them
+    pub fn read_from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        if is_exr(path) {
+            return read_exr(path);
+        }
+        match extension(path).as_str() {
+            "hdr" => read_hdr(path),
+            "pfm" => read_pfm(path),
+            _ => {
+                let decoded = image::open(path).map_err(|e| format!("{}: {}", path.display(), e))?.into_rgb32f();
+                let mut image = ImageData::new(decoded.width() as usize, decoded.height() as usize);
+                let decode = |v: f32| Transfer::Srgb.decode(v);
+                for (x, y, pixel) in decoded.enumerate_pixels() {
+                    image[(x as usize, y as usize)] = Color::new(decode(pixel[0]), decode(pixel[1]), decode(pixel[2]));
+                }
+                Ok(image)
+            },
+        }
+    }
+
+    /// Write the image tone mapped to 8 bits in the format of the extension of `path`,
+    /// a `ToneMapping` alone is encoded as sRGB without exposure.
+    /// The `.exr`, `.hdr` and `.pfm` files keep the linear radiance and are not tone mapped,
+    /// an `.exr` file in 32 bit floats
+    pub fn write_to_file(&self, tone_mapping: impl Into<ToneMapper>, path: impl AsRef<Path>) -> Result<(), String> {
+        if is_exr(&path) {
+            return self.write_exr(path, ExrPixel::Float);
+        }
+        match extension(path.as_ref()).as_str() {
+            "hdr" => return write_hdr(self, path),
+            "pfm" => return write_pfm(self, path),
+            _ => {},
+        }
+
+        let tone_mapping = tone_mapping.into();
         let mut img_buf = image::ImageBuffer::new(self.width as u32, self.height as u32);
         for (x, y, pixel) in img_buf.enumerate_pixels_mut() {
             let data = tone_mapping.tone_mapping(self[(x as usize,y as usize)]);
@@ -46,11 +93,33 @@ impl ImageData {
         Ok(())
     }
 
+    /// write the linear radiance to an OpenEXR file
+    pub fn write_exr(&self, path: impl AsRef<Path>, pixel: ExrPixel) -> Result<(), String> {
+        write_exr(path, &[("beauty", self)], pixel)
+    }
+
+    /// Copy the pixels of `source` in the rectangle from (x, y) of `w` x `h` to the same pixels of this image,
+    /// such as a crop window rendered into a previous render. The rectangle is clipped to both images
+    pub fn copy_region(&mut self, source: &ImageData, x: usize, y: usize, w: usize, h: usize) {
+        let x_end = (x + w).min(self.width).min(source.width);
+        let y_end = (y + h).min(self.height).min(source.height);
+        for py in y..y_end {
+            for px in x..x_end {
+                self[(px, py)] = source[(px, py)];
+            }
+        }
+    }
+
     pub fn raw(&mut self) -> *mut Color {
         self.data.as_mut_ptr()
     }
 }
 
+// the lower case extension of a path
+pub(crate) fn extension(path: &Path) -> String {
+    path.extension().map(|extension| extension.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
 impl Index<(usize, usize)> for ImageData {
     type Output = Color;
 
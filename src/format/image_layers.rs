@@ -0,0 +1,164 @@
+use std::path::{Path, PathBuf};
+
+use gk_math::color::RGB as Color;
+
+use crate::tone_mapping::{ToneMapper, ToneMapping, Transfer};
+use super::exr::{ExrPixel, is_exr, write_exr};
+use super::image_data::{ImageData, extension};
+
+/// Named images of the same size from one render, the beauty pass and its arbitrary output variables
+pub struct ImageLayers {
+    width: usize,
+    height: usize,
+    layers: Vec<(String, ImageData)>,
+}
+
+impl ImageLayers {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            layers: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// add a layer, a layer of the same name is replaced
+    pub fn push(&mut self, name: impl Into<String>, image: ImageData) -> Result<(), String> {
+        let name = name.into();
+        if image.width() != self.width || image.height() != self.height {
+            return Err(format!("layer `{}` is {}x{}, but the layers are {}x{}", name, image.width(), image.height(), self.width, self.height));
+        }
+        match self.layers.iter_mut().find(|(layer, _)| *layer == name) {
+            Some((_, layer)) => *layer = image,
+            None => self.layers.push((name, image)),
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ImageData> {
+        self.layers.iter().find(|(layer, _)| layer == name).map(|(_, image)| image)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut ImageData> {
+        self.layers.iter_mut().find(|(layer, _)| layer == name).map(|(_, image)| image)
+    }
+
+    /// remove a layer and return it
+    pub fn take(&mut self, name: &str) -> Option<ImageData> {
+        let idx = self.layers.iter().position(|(layer, _)| layer == name)?;
+        Some(self.layers.remove(idx).1)
+    }
+
+    /// the layers in the order they were added
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ImageData)> {
+        self.layers.iter().map(|(name, image)| (name.as_str(), image))
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.layers.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Write every layer to its own image, the `beauty` layer to `path`
+    /// and the others next to it as `<name>.<layer>.<extension>`.
+    /// An `.exr` path gets all the layers in one file of 32 bit floats, as written by `write_exr`.
+    /// In 8 bit images the `normal` layer is mapped from [-1, 1] and the `depth` layer from its nearest to its farthest depth
+    /// to [0, 1], both without tone mapping, the `.hdr` and `.pfm` files keep their values.
+    /// Pixels whose rays all missed have depth 0, they are left out of the nearest and farthest and written as far
+    pub fn write_to_files(&self, tone_mapping: impl Into<ToneMapper>, path: impl AsRef<Path>) -> Result<(), String> {
+        if is_exr(&path) {
+            return self.write_exr(path, ExrPixel::Float);
+        }
+        let tone_mapping = tone_mapping.into();
+        let float = matches!(extension(path.as_ref()).as_str(), "hdr" | "pfm");
+        let data = ToneMapper::new(ToneMapping::Linear).with_transfer(Transfer::Linear);
+        for (name, image) in self.iter() {
+            let layer = layer_path(path.as_ref(), name);
+            match name {
+                "normal" if !float => map_pixels(image, |n| Color::new(n.r * 0.5 + 0.5, n.g * 0.5 + 0.5, n.b * 0.5 + 0.5)).write_to_file(data, layer)?,
+                "depth" if !float => normalized_depth(image).write_to_file(data, layer)?,
+                _ => image.write_to_file(tone_mapping, layer)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// write all the layers to one OpenEXR file, the channels of a layer are named `<layer>.R`, ...
+    /// and the `beauty` layer is the plain R, G and B channels
+    pub fn write_exr(&self, path: impl AsRef<Path>, pixel: ExrPixel) -> Result<(), String> {
+        let layers: Vec<(&str, &ImageData)> = self.iter().collect();
+        write_exr(path, &layers, pixel)
+    }
+}
+
+// the depths from the nearest hit at 0 to the farthest at 1, the misses at 1
+fn normalized_depth(image: &ImageData) -> ImageData {
+    let depths = (0..image.height()).flat_map(|y| (0..image.width()).map(move |x| image[(x, y)].r));
+    let (near, far) = depths.filter(|depth| *depth > 0.0)
+        .fold((f32::MAX, f32::MIN), |(near, far), depth| (near.min(depth), far.max(depth)));
+    let scale = if far > near { 1.0 / (far - near) } else { 0.0 };
+    map_pixels(image, |d| {
+        let depth = if d.r > 0.0 { (d.r - near) * scale } else { 1.0 };
+        Color::new(depth, depth, depth)
+    })
+}
+
+// a new image of `f` of every pixel
+fn map_pixels(image: &ImageData, f: impl Fn(Color) -> Color) -> ImageData {
+    let mut mapped = ImageData::new(image.width(), image.height());
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            mapped[(x, y)] = f(image[(x, y)]);
+        }
+    }
+    mapped
+}
+
+/// the file of a layer written by `ImageLayers::write_to_files` to `path`
+pub fn layer_path(path: &Path, layer: &str) -> PathBuf {
+    if layer == "beauty" {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, layer, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, layer),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_misses_are_left_out_of_the_depth_range_and_written_far() {
+        let mut image = ImageData::new(4, 1);
+        for (x, depth) in [0.0, 2.0, 3.0, 4.0].into_iter().enumerate() {
+            image[(x, 0)] = Color::new(depth, depth, depth);
+        }
+        let normalized = normalized_depth(&image);
+        for (x, expect) in [1.0f32, 0.0, 0.5, 1.0].into_iter().enumerate() {
+            assert_eq!(normalized[(x, 0)].r.to_bits(), expect.to_bits(), "pixel {}", x);
+        }
+
+        // no hit at all is far everywhere
+        let normalized = normalized_depth(&ImageData::new(2, 1));
+        assert!((0..2).all(|x| normalized[(x, 0)].r == 1.0));
+    }
+}
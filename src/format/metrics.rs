@@ -0,0 +1,359 @@
+use std::f32::consts::PI;
+
+use gk_math::color::RGB as Color;
+
+use crate::integrator::{beat_y, false_color};
+use super::image_data::ImageData;
+
+// Errors of an image against a reference of the same size, to compare renders and catch regressions.
+// MSE and relative MSE are of the linear radiance, PSNR, SSIM and FLIP of the values clamped to [0, 1]
+// as the linear tone mapping shows them.
+
+impl ImageData {
+    /// the mean squared error of the channels
+    pub fn mse(&self, reference: &ImageData) -> Result<f64, String> {
+        same_size(self, reference)?;
+        Ok(mean_of_channels(self, reference, |x, r| {
+            let d = (x - r) as f64;
+            d * d
+        }))
+    }
+
+    /// the mean squared error of the channels divided by the squared reference, it weights dark and bright areas the same
+    pub fn relative_mse(&self, reference: &ImageData) -> Result<f64, String> {
+        same_size(self, reference)?;
+        Ok(mean_of_channels(self, reference, |x, r| {
+            let d = (x - r) as f64;
+            // keeps black pixels of the reference from dividing by 0
+            d * d / (r as f64 * r as f64 + 0.01)
+        }))
+    }
+
+    /// the peak signal to noise ratio in decibels, infinite for the same images
+    pub fn psnr(&self, reference: &ImageData) -> Result<f64, String> {
+        same_size(self, reference)?;
+        let mse = mean_of_channels(self, reference, |x, r| {
+            let d = (x.clamp(0.0, 1.0) - r.clamp(0.0, 1.0)) as f64;
+            d * d
+        });
+        Ok(if mse == 0.0 { f64::INFINITY } else { -10.0 * mse.log10() })
+    }
+
+    /// The mean structural similarity of Wang et al. of the luminance, in 11x11 Gaussian windows.
+    /// 1 for the same images, lower for less similar ones
+    pub fn ssim(&self, reference: &ImageData) -> Result<f64, String> {
+        same_size(self, reference)?;
+        let (w, h) = (self.width(), self.height());
+        let x = luminance(self);
+        let y = luminance(reference);
+        let kernel = gaussian_kernel(1.5, 5);
+        let blur = |values: &[f32]| convolve(values, w, h, &kernel, &kernel);
+        let product = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(a, b)| a * b).collect::<Vec<f32>>();
+
+        let mu_x = blur(&x);
+        let mu_y = blur(&y);
+        let xx = blur(&product(&x, &x));
+        let yy = blur(&product(&y, &y));
+        let xy = blur(&product(&x, &y));
+
+        const C1: f64 = 0.01 * 0.01;
+        const C2: f64 = 0.03 * 0.03;
+        let mut sum = 0.0;
+        for i in 0..w * h {
+            let (mx, my) = (mu_x[i] as f64, mu_y[i] as f64);
+            let var_x = xx[i] as f64 - mx * mx;
+            let var_y = yy[i] as f64 - my * my;
+            let cov = xy[i] as f64 - mx * my;
+            sum += (2.0 * mx * my + C1) * (2.0 * cov + C2) / ((mx * mx + my * my + C1) * (var_x + var_y + C2));
+        }
+        Ok(if w * h == 0 { 1.0 } else { sum / (w * h) as f64 })
+    }
+
+    /// The mean of `flip_error`, 0 for the same images and up to 1
+    pub fn flip(&self, reference: &ImageData) -> Result<f64, String> {
+        let error = self.flip_error(reference)?;
+        let n = error.len().max(1);
+        Ok(error.iter().map(|e| *e as f64).sum::<f64>() / n as f64)
+    }
+
+    /// A perceptual error of every pixel in [0, 1], after the FLIP of Andersson et al. for images seen
+    /// at 67 pixels per degree: the color difference of both images filtered by the contrast sensitivity of the eye,
+    /// raised by the difference of their edges and points
+    pub fn flip_error(&self, reference: &ImageData) -> Result<Vec<f32>, String> {
+        same_size(self, reference)?;
+        let (w, h) = (self.width(), self.height());
+        let pixels_per_degree = 67.0;
+
+        // color: spatially filtered in the opponent space YCxCz, compared in the Hunt adjusted CIELAB by HyAB
+        let filtered_test = csf_filter(self, pixels_per_degree);
+        let filtered_reference = csf_filter(reference, pixels_per_degree);
+        let max_color_error = hyab(hunt(lab([0.0, 1.0, 0.0])), hunt(lab([0.0, 0.0, 1.0]))).powf(FLIP_QC);
+
+        // features: edges and points of the lightness
+        let sigma = 0.5 * 0.082 * pixels_per_degree;
+        let test_features = features(&lightness(self), w, h, sigma);
+        let reference_features = features(&lightness(reference), w, h, sigma);
+
+        let mut error = Vec::with_capacity(w * h);
+        for i in 0..w * h {
+            let color = hyab(hunt(lab(filtered_test[i])), hunt(lab(filtered_reference[i]))).powf(FLIP_QC);
+            let color = if color < FLIP_PC * max_color_error {
+                FLIP_PT / (FLIP_PC * max_color_error) * color
+            } else {
+                FLIP_PT + (color - FLIP_PC * max_color_error) / (max_color_error - FLIP_PC * max_color_error) * (1.0 - FLIP_PT)
+            };
+
+            let (test_edge, test_point) = test_features[i];
+            let (reference_edge, reference_point) = reference_features[i];
+            let feature = ((test_edge - reference_edge).abs().max((test_point - reference_point).abs()) / 2f32.sqrt()).powf(FLIP_QF);
+
+            error.push(color.clamp(0.0, 1.0).powf(1.0 - feature.min(1.0)));
+        }
+        Ok(error)
+    }
+
+    /// `flip_error` in the false colors of `false_color`, from dark blue for no error to red for the largest
+    pub fn flip_image(&self, reference: &ImageData) -> Result<ImageData, String> {
+        let error = self.flip_error(reference)?;
+        Ok(error_image(self.width(), self.height(), error.iter().copied()))
+    }
+
+    /// The absolute difference of every pixel, the mean of its channels divided by `max_difference`, in the false colors
+    /// of `false_color`, from dark blue for no difference to red for `max_difference` or more
+    pub fn difference_image(&self, reference: &ImageData, max_difference: f32) -> Result<ImageData, String> {
+        same_size(self, reference)?;
+        let (w, h) = (self.width(), self.height());
+        let scale = if max_difference > 0.0 { 1.0 / max_difference } else { 0.0 };
+        let difference = (0..w * h).map(|i| {
+            let (x, r) = (self[(i % w, i / w)], reference[(i % w, i / w)]);
+            ((x.r - r.r).abs() + (x.g - r.g).abs() + (x.b - r.b).abs()) / 3.0 * scale
+        });
+        Ok(error_image(w, h, difference))
+    }
+}
+
+const FLIP_QC: f32 = 0.7;
+const FLIP_PC: f32 = 0.4;
+const FLIP_PT: f32 = 0.95;
+const FLIP_QF: f32 = 0.5;
+
+fn same_size(image: &ImageData, reference: &ImageData) -> Result<(), String> {
+    if image.width() != reference.width() || image.height() != reference.height() {
+        return Err(format!("the image is {}x{}, but the reference is {}x{}", image.width(), image.height(), reference.width(), reference.height()));
+    }
+    Ok(())
+}
+
+fn mean_of_channels(image: &ImageData, reference: &ImageData, error: impl Fn(f32, f32) -> f64) -> f64 {
+    let (w, h) = (image.width(), image.height());
+    if w * h == 0 {
+        return 0.0;
+    }
+    let mut sum = 0.0;
+    for y in 0..h {
+        for x in 0..w {
+            let (p, r) = (image[(x, y)], reference[(x, y)]);
+            sum += error(p.r, r.r) + error(p.g, r.g) + error(p.b, r.b);
+        }
+    }
+    sum / (3 * w * h) as f64
+}
+
+fn error_image(w: usize, h: usize, error: impl Iterator<Item = f32>) -> ImageData {
+    let mut image = ImageData::new(w, h);
+    for (i, e) in error.enumerate() {
+        image[(i % w, i / w)] = false_color(e);
+    }
+    image
+}
+
+fn clamped(color: Color) -> [f32; 3] {
+    [color.r.clamp(0.0, 1.0), color.g.clamp(0.0, 1.0), color.b.clamp(0.0, 1.0)]
+}
+
+fn luminance(image: &ImageData) -> Vec<f32> {
+    let (w, h) = (image.width(), image.height());
+    (0..w * h).map(|i| {
+        let [r, g, b] = clamped(image[(i % w, i / w)]);
+        beat_y(Color::new(r, g, b))
+    }).collect()
+}
+
+// the CIELAB lightness over 100
+fn lightness(image: &ImageData) -> Vec<f32> {
+    let (w, h) = (image.width(), image.height());
+    (0..w * h).map(|i| lab(clamped(image[(i % w, i / w)]))[0] / 100.0).collect()
+}
+
+// a normalized Gaussian of `radius` pixels on each side
+fn gaussian_kernel(sigma: f32, radius: usize) -> Vec<f32> {
+    let kernel: Vec<f32> = (0..=2 * radius).map(|i| {
+        let x = i as f32 - radius as f32;
+        (-x * x / (2.0 * sigma * sigma)).exp()
+    }).collect();
+    let sum: f32 = kernel.iter().sum();
+    kernel.iter().map(|k| k / sum).collect()
+}
+
+// convolve with the separable kernel `kernel_x` by `kernel_y`, the border pixels are repeated
+fn convolve(values: &[f32], w: usize, h: usize, kernel_x: &[f32], kernel_y: &[f32]) -> Vec<f32> {
+    let (rx, ry) = (kernel_x.len() / 2, kernel_y.len() / 2);
+    let mut rows = vec![0.0; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            rows[y * w + x] = kernel_x.iter().enumerate().map(|(i, k)| {
+                let sx = (x + i).saturating_sub(rx).min(w - 1);
+                k * values[y * w + sx]
+            }).sum();
+        }
+    }
+    let mut out = vec![0.0; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            out[y * w + x] = kernel_y.iter().enumerate().map(|(i, k)| {
+                let sy = (y + i).saturating_sub(ry).min(h - 1);
+                k * rows[sy * w + x]
+            }).sum();
+        }
+    }
+    out
+}
+
+// the white point of D65
+const WHITE: [f32; 3] = [0.950_428_5, 1.0, 1.088_900_4];
+
+fn linear_rgb_to_xyz([r, g, b]: [f32; 3]) -> [f32; 3] {
+    [
+        0.412_390_8 * r + 0.357_584_3 * g + 0.180_480_8 * b,
+        0.212_639 * r + 0.715_168_7 * g + 0.072_192_3 * b,
+        0.019_330_8 * r + 0.119_194_8 * g + 0.950_532_2 * b,
+    ]
+}
+
+fn xyz_to_linear_rgb([x, y, z]: [f32; 3]) -> [f32; 3] {
+    [
+        3.240_97 * x - 1.537_383_2 * y - 0.498_610_8 * z,
+        -0.969_243_6 * x + 1.875_967_5 * y + 0.041_555_1 * z,
+        0.055_630_1 * x - 0.203_977 * y + 1.056_971_5 * z,
+    ]
+}
+
+fn lab(rgb: [f32; 3]) -> [f32; 3] {
+    let [x, y, z] = linear_rgb_to_xyz(rgb);
+    let f = |t: f32| {
+        let delta = 6.0 / 29.0;
+        if t > delta * delta * delta { t.cbrt() } else { t / (3.0 * delta * delta) + 4.0 / 29.0 }
+    };
+    let (fx, fy, fz) = (f(x / WHITE[0]), f(y / WHITE[1]), f(z / WHITE[2]));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+// colorfulness grows with lightness
+fn hunt([l, a, b]: [f32; 3]) -> [f32; 3] {
+    [l, 0.01 * l * a, 0.01 * l * b]
+}
+
+fn hyab(x: [f32; 3], y: [f32; 3]) -> f32 {
+    (x[0] - y[0]).abs() + ((x[1] - y[1]).powi(2) + (x[2] - y[2]).powi(2)).sqrt()
+}
+
+// Filter an image by the contrast sensitivity of the achromatic and the two chromatic channels of YCxCz,
+// sums of Gaussians in degrees of visual angle. The filtered colors are clamped linear RGB
+fn csf_filter(image: &ImageData, pixels_per_degree: f32) -> Vec<[f32; 3]> {
+    let (w, h) = (image.width(), image.height());
+    let ycxcz: Vec<[f32; 3]> = (0..w * h).map(|i| {
+        let [x, y, z] = linear_rgb_to_xyz(clamped(image[(i % w, i / w)]));
+        let (x, y, z) = (x / WHITE[0], y / WHITE[1], z / WHITE[2]);
+        [116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z)]
+    }).collect();
+
+    // (a1, b1, a2, b2) of the channels
+    let parameters = [(1.0, 0.0047, 0.0, 1e-5), (1.0, 0.0053, 0.0, 1e-5), (34.1, 0.04, 13.5, 0.025)];
+    let max_b: f32 = 0.04;
+    let radius = (3.0 * (max_b / (2.0 * PI * PI)).sqrt() * pixels_per_degree).ceil() as usize;
+
+    let mut channels = Vec::with_capacity(3);
+    for (channel, (a1, b1, a2, b2)) in parameters.iter().enumerate() {
+        let kernel: Vec<f32> = (0..=2 * radius).map(|i| {
+            let x = (i as f32 - radius as f32) / pixels_per_degree;
+            a1 * (PI / b1).sqrt() * (-PI * PI * x * x / b1).exp() + a2 * (PI / b2).sqrt() * (-PI * PI * x * x / b2).exp()
+        }).collect();
+        let sum: f32 = kernel.iter().sum();
+        let kernel: Vec<f32> = kernel.iter().map(|k| k / sum).collect();
+        let values: Vec<f32> = ycxcz.iter().map(|c| c[channel]).collect();
+        channels.push(convolve(&values, w, h, &kernel, &kernel));
+    }
+
+    (0..w * h).map(|i| {
+        let y = (channels[0][i] + 16.0) / 116.0;
+        let x = channels[1][i] / 500.0 + y;
+        let z = y - channels[2][i] / 200.0;
+        let [r, g, b] = xyz_to_linear_rgb([x * WHITE[0], y * WHITE[1], z * WHITE[2]]);
+        [r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0)]
+    }).collect()
+}
+
+// The edge and point strength of every pixel, the gradient and second derivative magnitudes of a Gaussian
+// of `sigma` pixels. The positive and negative weights of the kernels sum to 1 and -1
+fn features(values: &[f32], w: usize, h: usize, sigma: f32) -> Vec<(f32, f32)> {
+    let radius = (3.0 * sigma).ceil() as usize;
+    let gaussian = gaussian_kernel(sigma, radius);
+    let offsets = || (0..=2 * radius).map(|i| i as f32 - radius as f32);
+    let normalize = |kernel: Vec<f32>| {
+        let positive: f32 = kernel.iter().filter(|k| **k > 0.0).sum();
+        let negative: f32 = -kernel.iter().filter(|k| **k < 0.0).sum::<f32>();
+        kernel.iter().map(|k| if *k > 0.0 { k / positive } else if *k < 0.0 { k / negative } else { 0.0 }).collect::<Vec<f32>>()
+    };
+    let edge = normalize(offsets().zip(gaussian.iter()).map(|(x, g)| -x * g).collect());
+    let point = normalize(offsets().zip(gaussian.iter()).map(|(x, g)| (x * x / (sigma * sigma) - 1.0) * g).collect());
+
+    let edge_x = convolve(values, w, h, &edge, &gaussian);
+    let edge_y = convolve(values, w, h, &gaussian, &edge);
+    let point_x = convolve(values, w, h, &point, &gaussian);
+    let point_y = convolve(values, w, h, &gaussian, &point);
+    (0..w * h).map(|i| (edge_x[i].hypot(edge_y[i]), point_x[i].hypot(point_y[i]))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a pattern of edges and flat areas, in steps of 1/8 so an offset of 1/4 is exact
+    fn pattern(offset: f32) -> ImageData {
+        let mut image = ImageData::new(24, 16);
+        for y in 0..16 {
+            for x in 0..24 {
+                let value = ((x / 4 + y / 3) % 7) as f32 / 8.0;
+                image[(x, y)] = Color::new(value + offset, 0.75 - value + offset, if x < 12 { offset } else { 0.5 + offset });
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn the_same_images_have_no_error() {
+        let (image, reference) = (pattern(0.0), pattern(0.0));
+        assert_eq!(image.mse(&reference).unwrap(), 0.0);
+        assert_eq!(image.relative_mse(&reference).unwrap(), 0.0);
+        assert_eq!(image.psnr(&reference).unwrap(), f64::INFINITY);
+        assert!((image.ssim(&reference).unwrap() - 1.0).abs() < 1e-9);
+        assert_eq!(image.flip(&reference).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn a_constant_offset_has_its_squared_error() {
+        let (image, reference) = (pattern(0.25), pattern(0.0));
+        assert_eq!(image.mse(&reference).unwrap(), 0.0625);
+        assert!((image.psnr(&reference).unwrap() - 10.0 * 16f64.log10()).abs() < 1e-9);
+        assert!(image.ssim(&reference).unwrap() < 1.0);
+        assert!(image.flip(&reference).unwrap() > 0.0);
+
+        let (image, reference) = (ImageData::create_with_a_color(8, 8, Color::new(0.75, 0.75, 0.75)), ImageData::create_with_a_color(8, 8, Color::new(0.5, 0.5, 0.5)));
+        assert!((image.relative_mse(&reference).unwrap() - 0.0625 / 0.26).abs() < 1e-9);
+    }
+
+    #[test]
+    fn images_of_other_sizes_are_not_compared() {
+        assert!(pattern(0.0).mse(&ImageData::new(8, 8)).is_err());
+    }
+}
@@ -1,3 +1,12 @@
 pub mod image_data;
+pub mod image_layers;
+pub mod exr;
+pub mod hdr;
+pub mod pfm;
+pub mod metrics;
 
 pub use image_data::*;
+pub use image_layers::*;
+pub use self::exr::*;
+pub use hdr::*;
+pub use pfm::*;
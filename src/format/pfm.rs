@@ -0,0 +1,162 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use gk_math::color::RGB as Color;
+
+use super::image_data::ImageData;
+
+// Portable float maps: a text header `PF` (color) or `Pf` (gray), the size and a scale whose sign is the byte order,
+// then 32 bit floats of the rows from the bottom to the top.
+
+/// write the linear radiance of `image` to a little endian color `.pfm` file
+pub fn write_pfm(image: &ImageData, path: impl AsRef<Path>) -> Result<(), String> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() && !parent.as_os_str().is_empty() && !parent.exists() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+    }
+    let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut writer = BufWriter::new(file);
+    let mut write = |bytes: &[u8]| writer.write_all(bytes).map_err(|e| format!("{}: {}", path.display(), e));
+
+    write(format!("PF\n{} {}\n-1.0\n", image.width(), image.height()).as_bytes())?;
+    let mut row = Vec::with_capacity(image.width() * 12);
+    for y in (0..image.height()).rev() {
+        row.clear();
+        for x in 0..image.width() {
+            let color = image[(x, y)];
+            for c in [color.r, color.g, color.b] {
+                row.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+        write(&row)?;
+    }
+
+    writer.flush().map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// read a color or gray `.pfm` file of either byte order, the values are multiplied by the magnitude of the scale
+pub fn read_pfm(path: impl AsRef<Path>) -> Result<ImageData, String> {
+    let path = path.as_ref();
+    let error = |message: &str| format!("{}: {}", path.display(), message);
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut bytes = Vec::new();
+    BufReader::new(file).read_to_end(&mut bytes).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    // the four header tokens, the data starts after the single whitespace that ends the last one
+    let mut tokens = Vec::with_capacity(4);
+    let mut pos = 0;
+    while tokens.len() < 4 {
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        let start = pos;
+        while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(error("the header is cut short"));
+        }
+        tokens.push(String::from_utf8_lossy(&bytes[start..pos]).into_owned());
+    }
+    pos += 1;
+
+    let channels = match tokens[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(error("not a pfm file")),
+    };
+    let width = tokens[1].parse::<usize>().map_err(|_| error("invalid width"))?;
+    let height = tokens[2].parse::<usize>().map_err(|_| error("invalid height"))?;
+    let scale = tokens[3].parse::<f32>().map_err(|_| error("invalid scale"))?;
+    let little_endian = scale < 0.0;
+    let scale = if scale == 0.0 { 1.0 } else { scale.abs() };
+
+    let data = bytes.get(pos..).unwrap_or(&[]);
+    let data_len = width.checked_mul(height).and_then(|n| n.checked_mul(channels * 4)).ok_or_else(|| error("the image is too large"))?;
+    if data.len() < data_len {
+        return Err(error("the data is cut short"));
+    }
+
+    let mut image = ImageData::new(width, height);
+    let mut values = data.chunks_exact(4).map(|b| {
+        let b = [b[0], b[1], b[2], b[3]];
+        scale * if little_endian { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) }
+    });
+    for y in (0..height).rev() {
+        for x in 0..width {
+            image[(x, y)] = if channels == 3 {
+                let r = values.next().unwrap_or(0.0);
+                let g = values.next().unwrap_or(0.0);
+                Color::new(r, g, values.next().unwrap_or(0.0))
+            } else {
+                let v = values.next().unwrap_or(0.0);
+                Color::new(v, v, v)
+            };
+        }
+    }
+
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("solas_pfm_{}_{}", name, std::process::id())).join("image.pfm")
+    }
+
+    #[test]
+    fn write_and_read_keep_the_values() {
+        let path = temp_path("round_trip");
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        let mut original = ImageData::new(5, 3);
+        for y in 0..3 {
+            for x in 0..5 {
+                original[(x, y)] = Color::new(x as f32 * 0.1, y as f32 * 1e-6, 1e6 / (x + y + 1) as f32);
+            }
+        }
+        write_pfm(&original, &path).unwrap();
+        let read = read_pfm(&path).unwrap();
+        assert_eq!((read.width(), read.height()), (5, 3));
+        for y in 0..3 {
+            for x in 0..5 {
+                let (a, b) = (original[(x, y)], read[(x, y)]);
+                assert_eq!([a.r, a.g, a.b].map(f32::to_bits), [b.r, b.g, b.b].map(f32::to_bits), "pixel ({}, {})", x, y);
+            }
+        }
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn both_byte_orders_and_the_scale_are_read() {
+        let path = temp_path("byte_order");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        // a gray 2x1 image, the rows go from the bottom
+        for (scale, bytes) in [("-2.0", [0.5f32, 1.5].map(f32::to_le_bytes)), ("2.0", [0.5f32, 1.5].map(f32::to_be_bytes))] {
+            let mut file = format!("Pf\n2 1\n{}\n", scale).into_bytes();
+            file.extend(bytes.iter().flatten());
+            fs::write(&path, file).unwrap();
+            let image = read_pfm(&path).unwrap();
+            assert_eq!([image[(0, 0)].r, image[(0, 0)].g, image[(1, 0)].b], [1.0, 1.0, 3.0], "scale {}", scale);
+        }
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn a_truncated_or_oversized_file_is_an_error() {
+        let path = temp_path("truncated");
+        write_pfm(&ImageData::new(4, 4), &path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(read_pfm(&path).is_err());
+
+        fs::write(&path, format!("PF\n{} {}\n-1.0\n", usize::MAX, usize::MAX)).unwrap();
+        assert!(read_pfm(&path).is_err());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
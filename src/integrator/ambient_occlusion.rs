@@ -0,0 +1,57 @@
+use gk_math::color::RGB as Color;
+
+use crate::base::ray::Ray;
+use crate::base::sampler::Sampler;
+use crate::base::sampling::{Frame, cosine_sample_hemisphere};
+use crate::scene::Scene;
+use super::traits::*;
+
+/// Ambient occlusion: the cosine weighted fraction of the hemisphere at the first hit
+/// that is open up to `distance`, rays that hit nothing are black
+pub struct AmbientOcclusionIntegrator {
+    pub distance: f32,
+}
+
+impl AmbientOcclusionIntegrator {
+    pub fn new() -> Self {
+        Self {
+            distance: f32::MAX,
+        }
+    }
+
+    pub fn with_distance(mut self, distance: f32) -> Self {
+        self.distance = distance;
+        self
+    }
+}
+
+impl Default for AmbientOcclusionIntegrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn name(&self) -> &'static str {
+        "ao"
+    }
+
+    fn li(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler) -> Color {
+        let u = sampler.get_2d();
+        let hit = match scene.intersect(ray, 0.001, f32::MAX) {
+            Some((hit, _)) => hit,
+            None => return Color::zero(),
+        };
+
+        // the hemisphere on the side of the camera
+        let normal = hit.normal.normalized();
+        let normal = if ray.direction.dot(&normal) > 0.0 { -normal } else { normal };
+        let wi = Frame::new(normal).to_world(cosine_sample_hemisphere(u));
+
+        if unoccluded(scene, &hit, wi, self.distance) {
+            Color::one()
+        } else {
+            Color::zero()
+        }
+    }
+}
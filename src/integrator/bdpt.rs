@@ -0,0 +1,448 @@
+use gk_math::base::f32::Vec3;
+use gk_math::color::RGB as Color;
+
+use crate::base::camera::Camera;
+use crate::base::intersect::Hit;
+use crate::base::light::Light;
+use crate::base::material::Material;
+use crate::base::ray::Ray;
+use crate::base::sampler::Sampler;
+use crate::scene::Scene;
+use super::traits::*;
+
+/// Bidirectional path tracing of Veach. Every sample traces a subpath from the camera and one from a light,
+/// then connects every prefix of the camera subpath to every prefix of the light subpath.
+/// A path can be built by several such strategies, each is weighted against the others by the power heuristic.
+/// Light subpaths connected to the camera lens land in other pixels and are splatted to the image,
+/// they find the caustics a path tracer can not.
+/// The skybox is a light at infinity, it is found by the camera subpath and sampled from its vertices
+/// the same way the path integrator does.
+/// It needs a camera built by `Camera::new`, the default camera has no importance, so light subpaths never reach it.
+pub struct BdptIntegrator {
+    pub max_depth: usize,
+}
+
+impl BdptIntegrator {
+    pub fn new(max_depth: usize) -> Self {
+        Self {
+            max_depth,
+        }
+    }
+
+    // Extend `path` from its start vertex along `ray`, sampled with density `pdf_direction` by solid angle,
+    // until it leaves the scene or has `max_depth` surface vertices. In radiance mode the light of the skybox
+    // and of emissive materials found on the way is returned.
+    fn random_walk<'a>(&self, scene: &'a Scene, ray: Ray, pdf_direction: f32, mode: Transport,
+                       sampler: &mut dyn Sampler, path: &mut Vec<Vertex<'a>>) -> Color {
+        let mut l = Color::zero();
+        let mut beta = match mode {
+            // camera rays are sampled in proportion to their importance
+            Transport::Radiance => path[0].beta,
+            // the lights are points, their emission has no cosine
+            Transport::Importance => path[0].beta / pdf_direction,
+        };
+        let mut current_ray = ray;
+        let mut pdf_fwd = pdf_direction;
+        // the last bounce was specular or the ray comes from the camera, sampling the skybox could not find it
+        let mut specular_bounce = true;
+
+        for _ in 0..self.max_depth {
+            let (hit, obj_idx) = match scene.intersect(&current_ray, 0.001, f32::MAX) {
+                Some(intersection) => intersection,
+                None => {
+                    if mode == Transport::Radiance {
+                        let le = scene.skybox.ambient(&current_ray);
+                        let weight = if specular_bounce {
+                            1.0
+                        } else {
+                            power_heuristic(pdf_fwd, scene.skybox.pdf_li(current_ray.direction.normalized()))
+                        };
+                        l = l + beta * le * weight;
+                    }
+                    break;
+                },
+            };
+
+            let material = match object_material(scene, obj_idx) {
+                Some(material) => material,
+                None => break,
+            };
+            let wo = -current_ray.direction.normalized();
+            let mut vertex = Vertex::surface(hit, material, wo, beta);
+            vertex.pdf_fwd = path[path.len() - 1].convert_density(pdf_fwd, &vertex);
+            path.push(vertex);
+
+            if mode == Transport::Radiance {
+                l = l + beta * material.emit(&current_ray);
+                l = l + beta * sample_skybox(scene, material, wo, &hit, sampler);
+            }
+
+            let sample = match material.sample(wo, &hit, sampler.get_2d()) {
+                Some(sample) if sample.pdf > 0.0 => sample,
+                _ => break,
+            };
+            let cosine = sample.wi.dot(&hit.normal.normalized()).abs();
+            beta = beta * sample.f * (cosine / sample.pdf);
+            if mode == Transport::Importance {
+                // importance is not compressed by refraction the way radiance is
+                beta = beta * (sample.eta * sample.eta);
+            }
+
+            pdf_fwd = sample.pdf;
+            let mut pdf_rev = material.pdf(sample.wi, wo, &hit);
+            let last = path.len() - 1;
+            if sample.specular {
+                path[last].delta = true;
+                pdf_fwd = 0.0;
+                pdf_rev = 0.0;
+            }
+            path[last - 1].pdf_rev = path[last].convert_density(pdf_rev, &path[last - 1]);
+
+            specular_bounce = sample.specular;
+            current_ray = Ray::new(hit.position, sample.wi, hit.time);
+        }
+
+        l
+    }
+
+    // Start a light subpath on one of the lights, chosen uniformly
+    fn light_subpath<'a>(&self, scene: &'a Scene, sampler: &mut dyn Sampler, path: &mut Vec<Vertex<'a>>) {
+        let u_light = sampler.get_1d();
+        let u_position = sampler.get_2d();
+        let u_direction = sampler.get_2d();
+        if scene.lights.is_empty() {
+            return;
+        }
+
+        let index = ((u_light * scene.lights.len() as f32) as usize).min(scene.lights.len() - 1);
+        let light = scene.lights[index].as_ref();
+        let pdf_choice = 1.0 / scene.lights.len() as f32;
+        let emission = match light.sample_le(u_position, u_direction) {
+            Some(emission) if emission.pdf_position > 0.0 && emission.pdf_direction > 0.0 => emission,
+            _ => return,
+        };
+
+        let pdf_origin = emission.pdf_position * pdf_choice;
+        let mut vertex = Vertex::light(light, emission.delta_position, emission.ray.origin, emission.le / pdf_origin);
+        vertex.pdf_fwd = pdf_origin;
+        path.push(vertex);
+        self.random_walk(scene, emission.ray, emission.pdf_direction, Transport::Importance, sampler, path);
+    }
+}
+
+impl Integrator for BdptIntegrator {
+    fn name(&self) -> &'static str {
+        "bdpt"
+    }
+
+    // without the splats, the light reaching the camera only by light tracing is missing
+    fn li(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler) -> Color {
+        self.li_with_splats(scene, ray, sampler, &mut |_, _| {})
+    }
+
+    fn li_with_splats(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler, splat: &mut dyn FnMut((f32, f32), Color)) -> Color {
+        let mut camera_path = Vec::with_capacity(self.max_depth + 1);
+        camera_path.push(Vertex::camera(&scene.camera, ray.origin));
+        let pdf_direction = scene.camera.pdf_we(ray.origin, ray.direction.normalized()).1;
+        let mut l = self.random_walk(scene, *ray, pdf_direction, Transport::Radiance, sampler, &mut camera_path);
+
+        let mut light_path = Vec::with_capacity(self.max_depth + 1);
+        self.light_subpath(scene, sampler, &mut light_path);
+
+        for t in 1..=camera_path.len() {
+            for s in 1..=light_path.len() {
+                if s + t - 2 > self.max_depth {
+                    break;
+                }
+                if t == 1 {
+                    if let Some((uv, light)) = connect_camera(scene, &light_path, s, sampler) {
+                        splat(uv, light);
+                    }
+                } else {
+                    l = l + connect(scene, &camera_path, &light_path, s, t);
+                }
+            }
+        }
+
+        l
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Transport {
+    // the path carries radiance from the lights, it starts at the camera
+    Radiance,
+    // the path carries importance from the camera, it starts at a light
+    Importance,
+}
+
+#[derive(Clone, Copy)]
+enum VertexKind<'a> {
+    Camera(&'a Camera),
+    // lights are points shining the same intensity to all directions
+    Light(&'a (dyn Light + Sync + Send), bool),
+    Surface(Hit, &'a (dyn Material + Sync + Send)),
+}
+
+#[derive(Clone, Copy)]
+struct Vertex<'a> {
+    kind: VertexKind<'a>,
+    position: Vec3,
+    // unit direction to the previous vertex of the subpath, zero at its start
+    wo: Vec3,
+    // the throughput of the subpath up to this vertex
+    beta: Color,
+    // the densities by area of sampling this vertex from the previous vertex of its subpath,
+    // and from the next one if the path was traced the other way
+    pdf_fwd: f32,
+    pdf_rev: f32,
+    // the vertex scatters by a delta distribution, no connection can go through it
+    delta: bool,
+}
+
+impl<'a> Vertex<'a> {
+    fn camera(camera: &'a Camera, position: Vec3) -> Self {
+        Self::new(VertexKind::Camera(camera), position, Vec3::new(0.0, 0.0, 0.0), Color::one())
+    }
+
+    fn light(light: &'a (dyn Light + Sync + Send), delta_position: bool, position: Vec3, beta: Color) -> Self {
+        Self::new(VertexKind::Light(light, delta_position), position, Vec3::new(0.0, 0.0, 0.0), beta)
+    }
+
+    fn surface(hit: Hit, material: &'a (dyn Material + Sync + Send), wo: Vec3, beta: Color) -> Self {
+        Self::new(VertexKind::Surface(hit, material), hit.position, wo, beta)
+    }
+
+    fn new(kind: VertexKind<'a>, position: Vec3, wo: Vec3, beta: Color) -> Self {
+        Self {
+            kind,
+            position,
+            wo,
+            beta,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            delta: false,
+        }
+    }
+
+    fn hit(&self) -> Option<&Hit> {
+        match &self.kind {
+            VertexKind::Surface(hit, _) => Some(hit),
+            _ => None,
+        }
+    }
+
+    // the light of this vertex has a delta position, no path can hit it
+    fn is_delta_light(&self) -> bool {
+        matches!(self.kind, VertexKind::Light(_, true))
+    }
+
+    // |cos| between the surface normal and the unit direction `w`, 1 for points without a surface
+    fn cosine(&self, w: Vec3) -> f32 {
+        match self.hit() {
+            Some(hit) => hit.normal.normalized().dot(&w).abs(),
+            None => 1.0,
+        }
+    }
+
+    // light scattered at this vertex toward `next`, for a light the emitted intensity is in `beta`
+    fn f(&self, next: &Vertex) -> Color {
+        match &self.kind {
+            VertexKind::Surface(hit, material) => material.eval(self.wo, (next.position - self.position).normalized(), hit),
+            // lights and the camera keep their emission and importance in `beta`
+            VertexKind::Light(..) | VertexKind::Camera(_) => Color::one(),
+        }
+    }
+
+    // a density by solid angle of sampling `next` from this vertex, as a density by area at `next`
+    fn convert_density(&self, pdf: f32, next: &Vertex) -> f32 {
+        let w = next.position - self.position;
+        let distance_squared = w.length_squared();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        pdf * next.cosine(w / distance_squared.sqrt()) / distance_squared
+    }
+
+    // the density by area of sampling `next` from this vertex, coming from `previous`
+    fn pdf(&self, previous: Option<&Vertex>, next: &Vertex) -> f32 {
+        let wi = (next.position - self.position).normalized();
+        let pdf = match (&self.kind, previous) {
+            (VertexKind::Surface(hit, material), Some(previous)) => {
+                material.pdf((previous.position - self.position).normalized(), wi, hit)
+            },
+            (VertexKind::Light(light, _), _) => light.pdf_le(self.position, wi).1,
+            (VertexKind::Camera(camera), _) => camera.pdf_we(self.position, wi).1,
+            _ => 0.0,
+        };
+        self.convert_density(pdf, next)
+    }
+}
+
+// The light of the path made of the first `t` camera vertices and the first `s` light vertices
+fn connect(scene: &Scene, camera_path: &[Vertex], light_path: &[Vertex], s: usize, t: usize) -> Color {
+    let pt = &camera_path[t - 1];
+    let qs = &light_path[s - 1];
+    let hit = match pt.hit() {
+        Some(hit) => hit,
+        None => return Color::zero(),
+    };
+    if pt.delta || qs.delta {
+        return Color::zero();
+    }
+
+    let l = qs.beta * qs.f(pt) * pt.f(qs) * pt.beta;
+    if is_black(l) {
+        return Color::zero();
+    }
+
+    let w = qs.position - pt.position;
+    let distance = w.length();
+    if distance == 0.0 {
+        return Color::zero();
+    }
+    let wi = w / distance;
+    if !unoccluded(scene, hit, wi, distance) {
+        return Color::zero();
+    }
+
+    let geometry = pt.cosine(wi) * qs.cosine(wi) / (distance * distance);
+    l * (geometry * mis_weight(camera_path, light_path, s, t))
+}
+
+// The light of the first `s` light vertices reaching the camera lens, and its (u, v) on the image
+fn connect_camera(scene: &Scene, light_path: &[Vertex], s: usize, sampler: &mut dyn Sampler) -> Option<((f32, f32), Color)> {
+    let qs = &light_path[s - 1];
+    let u = sampler.get_2d();
+    // a point light seen by the camera is not drawn, as with the other integrators
+    let hit = qs.hit()?;
+    if qs.delta {
+        return None;
+    }
+
+    let sample = scene.camera.sample_wi(qs.position, u)?;
+    if sample.pdf <= 0.0 || sample.we <= 0.0 {
+        return None;
+    }
+    let mut camera = Vertex::camera(&scene.camera, sample.lens);
+    camera.beta = Color::one() * (sample.we / sample.pdf);
+
+    let l = qs.beta * qs.f(&camera) * camera.beta * qs.cosine(sample.wi);
+    if is_black(l) || !unoccluded(scene, hit, sample.wi, sample.distance) {
+        return None;
+    }
+    Some((sample.uv, l * mis_weight(&[camera], light_path, s, 1)))
+}
+
+// The power heuristic weight of the strategy (s, t) against the other strategies building the same path.
+// The ratio of the density of a neighbouring strategy to this one is the ratio of the reverse and forward
+// densities of the vertex that moves from one subpath to the other.
+fn mis_weight(camera_path: &[Vertex], light_path: &[Vertex], s: usize, t: usize) -> f32 {
+    let pt = &camera_path[t - 1];
+    let pt_minus = if t > 1 { Some(&camera_path[t - 2]) } else { None };
+    let qs = &light_path[s - 1];
+    let qs_minus = if s > 1 { Some(&light_path[s - 2]) } else { None };
+
+    // the connection gives the vertices next to it their reverse densities
+    let pt_rev = qs.pdf(qs_minus, pt);
+    let pt_minus_rev = pt_minus.map(|pt_minus| pt.pdf(Some(qs), pt_minus)).unwrap_or(0.0);
+    let qs_rev = pt.pdf(pt_minus, qs);
+    let qs_minus_rev = qs_minus.map(|qs_minus| qs.pdf(Some(pt), qs_minus)).unwrap_or(0.0);
+
+    let camera_rev = |i: usize| {
+        if i == t - 1 { pt_rev } else if i + 2 == t { pt_minus_rev } else { camera_path[i].pdf_rev }
+    };
+    let light_rev = |i: usize| {
+        if i == s - 1 { qs_rev } else if i + 2 == s { qs_minus_rev } else { light_path[i].pdf_rev }
+    };
+
+    let mut sum = 0.0;
+
+    // moving camera vertices to the light subpath, down to connecting to the lens
+    let mut ratio = 1.0;
+    for i in (1..t).rev() {
+        ratio *= remap_zero(camera_rev(i)) / remap_zero(camera_path[i].pdf_fwd);
+        if !camera_path[i].delta && !camera_path[i - 1].delta {
+            sum += ratio * ratio;
+        }
+    }
+
+    // moving light vertices to the camera subpath, down to hitting the light
+    let mut ratio = 1.0;
+    for i in (0..s).rev() {
+        ratio *= remap_zero(light_rev(i)) / remap_zero(light_path[i].pdf_fwd);
+        let delta_before = if i > 0 { light_path[i - 1].delta } else { light_path[0].is_delta_light() };
+        if !light_path[i].delta && !delta_before {
+            sum += ratio * ratio;
+        }
+    }
+
+    1.0 / (1.0 + sum)
+}
+
+// delta distributions have zero densities, they cancel out of the ratios
+#[inline]
+fn remap_zero(pdf: f32) -> f32 {
+    if pdf == 0.0 { 1.0 } else { pdf }
+}
+
+#[cfg(test)]
+mod tests {
+    use gk_math::base::f32::Vec3;
+
+    use super::*;
+    use crate::base::camera::Camera;
+    use crate::base::light::PointLight;
+    use crate::ImageData;
+    use crate::integrator::PathIntegrator;
+    use crate::material::bsdf::Lambertian;
+    use crate::object::{Plane, Sphere};
+    use crate::renderer::Renderer;
+    use crate::scene::Canvas;
+
+    // two diffuse spheres on a diffuse plane, lit by the sky and a point light
+    fn scene() -> Scene {
+        let mut scene = Scene::new();
+        scene.set_camera(Camera::new(Vec3::new(0.0, 1.0, 5.0), Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, 1.0, 0.0), 45.0, 2.0, 0.0, 5.0));
+        let red = scene.add_material(Lambertian::new().with_albedo(Vec3::new(0.7, 0.3, 0.2)));
+        let gray = scene.add_material(Lambertian::new().with_albedo(Vec3::new(0.5, 0.5, 0.5)));
+        scene.add_object(Sphere::new().with_center(Vec3::new(-0.6, 0.5, 0.0)).with_radius(0.5)).material = red;
+        scene.add_object(Sphere::new().with_center(Vec3::new(0.6, 0.5, 0.0)).with_radius(0.5)).material = gray;
+        scene.add_object(Plane::new().with_center(Vec3::new(0.0, 0.0, 0.0)).with_normal(Vec3::new(0.0, 1.0, 0.0))
+            .with_width(10.0).with_height(10.0)).material = gray;
+        scene.add_light(PointLight::create(Vec3::new(1.0, 3.0, 2.0), Color::new(1.0, 1.0, 1.0), 100.0));
+        scene.build_bvh_tree();
+        scene
+    }
+
+    fn render(integrator: Box<dyn Integrator>, seed: u64, sample_number: usize) -> ImageData {
+        Renderer::new(scene(), Canvas::new(16, 8, 1.0, 2.0), 4).with_seed(seed).with_integrator(integrator)
+            .multi_thread_render(8, 8, 0, sample_number).unwrap()
+    }
+
+    fn mean(image: &ImageData) -> [f64; 3] {
+        let mut sum = [0.0; 3];
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                let color = image[(x, y)];
+                for (sum, c) in sum.iter_mut().zip([color.r, color.g, color.b]) {
+                    *sum += c as f64;
+                }
+            }
+        }
+        sum.map(|sum| sum / (image.width() * image.height()) as f64)
+    }
+
+    #[test]
+    fn bdpt_converges_to_the_path_tracer() {
+        // other seeds, so the images do not share their noise
+        let path = render(Box::new(PathIntegrator::new(4)), 1, 1024);
+        let bdpt = render(Box::new(BdptIntegrator::new(4)), 2, 1024);
+        let (path_mean, bdpt_mean) = (mean(&path), mean(&bdpt));
+        println!("mean path {:?}, bdpt {:?}, relative mse {}", path_mean, bdpt_mean, bdpt.relative_mse(&path).unwrap());
+        for (a, b) in path_mean.iter().zip(bdpt_mean.iter()) {
+            assert!((a - b).abs() < 0.01 * a, "the mean of bdpt {:?} is not the mean of the path tracer {:?}", bdpt_mean, path_mean);
+        }
+        assert!(bdpt.relative_mse(&path).unwrap() < 0.001);
+    }
+}
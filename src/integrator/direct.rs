@@ -0,0 +1,74 @@
+use gk_math::color::RGB as Color;
+
+use crate::base::ray::Ray;
+use crate::base::sampler::Sampler;
+use crate::scene::Scene;
+use super::traits::*;
+
+/// Direct lighting only: the light reaching the first diffuse or glossy surface straight from the lights
+/// and the skybox. Specular surfaces are followed up to `max_depth`, so mirrors and glass still show what they see.
+pub struct DirectIntegrator {
+    pub max_depth: usize,
+}
+
+impl DirectIntegrator {
+    pub fn new(max_depth: usize) -> Self {
+        Self {
+            max_depth,
+        }
+    }
+}
+
+impl Integrator for DirectIntegrator {
+    fn name(&self) -> &'static str {
+        "direct"
+    }
+
+    fn li(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler) -> Color {
+        let mut l = Color::zero();
+        let mut beta = Color::one();
+        let mut current_ray = *ray;
+
+        for _ in 0..self.max_depth {
+            let (hit, obj_idx) = match scene.intersect(&current_ray, 0.001, f32::MAX) {
+                Some(intersection) => intersection,
+                None => {
+                    l = l + beta * scene.skybox.ambient(&current_ray);
+                    break;
+                },
+            };
+
+            let material = match object_material(scene, obj_idx) {
+                Some(material) => material,
+                None => break,
+            };
+            let wo = -current_ray.direction.normalized();
+
+            l = l + beta * material.emit(&current_ray);
+            l = l + beta * sample_lights(scene, material, wo, &hit, sampler);
+
+            let sample = match material.sample(wo, &hit, sampler.get_2d()) {
+                Some(sample) if sample.pdf > 0.0 => sample,
+                _ => break,
+            };
+            let cosine = sample.wi.dot(&hit.normal.normalized()).abs();
+            let weight = sample.f * (cosine / sample.pdf);
+            let next_ray = Ray::new(hit.position, sample.wi, hit.time);
+
+            if sample.specular {
+                beta = beta * weight;
+                current_ray = next_ray;
+                continue;
+            }
+
+            // the skybox found by BSDF sampling, the other half of the skybox estimate
+            if scene.intersect(&next_ray, 0.001, f32::MAX).is_none() {
+                let mis = power_heuristic(sample.pdf, scene.skybox.pdf_li(sample.wi.normalized()));
+                l = l + beta * weight * scene.skybox.ambient(&next_ray) * mis;
+            }
+            break;
+        }
+
+        l
+    }
+}
@@ -0,0 +1,56 @@
+use gk_math::color::RGB as Color;
+
+use crate::base::ray::Ray;
+use crate::base::sampler::Sampler;
+use crate::scene::Scene;
+use super::traits::*;
+
+/// The distance from the ray origin to the first hit in false color, from blue near the camera to red far away.
+/// A distance d is mapped to d / (d + scale), the points `scale` away are green. Rays that hit nothing are black
+pub struct DistanceIntegrator {
+    /// the distance shown in the middle of the colormap, 0 for the distance from the camera to its look at point
+    pub scale: f32,
+}
+
+impl DistanceIntegrator {
+    pub fn new() -> Self {
+        Self {
+            scale: 0.0,
+        }
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+}
+
+impl Default for DistanceIntegrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Integrator for DistanceIntegrator {
+    fn name(&self) -> &'static str {
+        "distance"
+    }
+
+    fn preprocess(&mut self, scene: &Scene) {
+        if self.scale <= 0.0 {
+            self.scale = (scene.camera.look_at - scene.camera.origin).length();
+        }
+    }
+
+    fn li(&self, scene: &Scene, ray: &Ray, _sampler: &mut dyn Sampler) -> Color {
+        match scene.intersect(ray, 0.001, f32::MAX) {
+            Some((hit, _)) => {
+                // the hit time is in units of the ray direction, which is not normalized
+                let distance = (hit.position - ray.origin).length();
+                let scale = if self.scale > 0.0 { self.scale } else { 1.0 };
+                false_color(distance / (distance + scale))
+            },
+            None => Color::zero(),
+        }
+    }
+}
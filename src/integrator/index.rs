@@ -0,0 +1,65 @@
+use gk_math::color::RGB as Color;
+
+use crate::base::ray::Ray;
+use crate::base::sampler::Sampler;
+use crate::scene::Scene;
+use super::traits::*;
+
+/// Every object of the first hit in its own color, from `Object::idx`, rays that hit nothing are black
+pub struct ObjectIndexIntegrator {}
+
+impl ObjectIndexIntegrator {
+    pub fn new() -> Self {
+        Self {
+        }
+    }
+}
+
+impl Default for ObjectIndexIntegrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Integrator for ObjectIndexIntegrator {
+    fn name(&self) -> &'static str {
+        "object"
+    }
+
+    fn li(&self, scene: &Scene, ray: &Ray, _sampler: &mut dyn Sampler) -> Color {
+        match scene.intersect(ray, 0.001, f32::MAX) {
+            Some((_, obj_idx)) => index_color(scene.objects[obj_idx].idx),
+            None => Color::zero(),
+        }
+    }
+}
+
+/// Every material of the first hit in its own color, rays that hit nothing
+/// and objects referring to a missing material are black
+pub struct MaterialIndexIntegrator {}
+
+impl MaterialIndexIntegrator {
+    pub fn new() -> Self {
+        Self {
+        }
+    }
+}
+
+impl Default for MaterialIndexIntegrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Integrator for MaterialIndexIntegrator {
+    fn name(&self) -> &'static str {
+        "material"
+    }
+
+    fn li(&self, scene: &Scene, ray: &Ray, _sampler: &mut dyn Sampler) -> Color {
+        match scene.intersect(ray, 0.001, f32::MAX) {
+            Some((_, obj_idx)) if object_material(scene, obj_idx).is_some() => index_color(scene.objects[obj_idx].material),
+            _ => Color::zero(),
+        }
+    }
+}
@@ -0,0 +1,23 @@
+pub mod traits;
+pub mod path;
+pub mod direct;
+pub mod bdpt;
+pub mod photon;
+pub mod ambient_occlusion;
+pub mod normal;
+pub mod uv;
+pub mod distance;
+pub mod index;
+pub mod whitted;
+
+pub use traits::*;
+pub use path::*;
+pub use direct::*;
+pub use bdpt::*;
+pub use photon::*;
+pub use ambient_occlusion::*;
+pub use normal::*;
+pub use uv::*;
+pub use distance::*;
+pub use index::*;
+pub use whitted::*;
@@ -0,0 +1,38 @@
+use gk_math::color::RGB as Color;
+
+use crate::base::ray::Ray;
+use crate::base::sampler::Sampler;
+use crate::scene::Scene;
+use super::traits::*;
+
+/// The normal at the first hit mapped from [-1, 1] to [0, 1], rays that hit nothing are black
+pub struct NormalIntegrator {}
+
+impl NormalIntegrator {
+    pub fn new() -> Self {
+        Self {
+        }
+    }
+}
+
+impl Default for NormalIntegrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Integrator for NormalIntegrator {
+    fn name(&self) -> &'static str {
+        "normal"
+    }
+
+    fn li(&self, scene: &Scene, ray: &Ray, _sampler: &mut dyn Sampler) -> Color {
+        match scene.intersect(ray, 0.001, f32::MAX) {
+            Some((hit, _)) => {
+                let normal = hit.normal.normalized();
+                Color::new(normal.x * 0.5 + 0.5, normal.y * 0.5 + 0.5, normal.z * 0.5 + 0.5)
+            },
+            None => Color::zero(),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use gk_math::color::RGB as Color;
+
+use crate::base::ray::Ray;
+use crate::base::sampler::Sampler;
+use crate::scene::Scene;
+use super::traits::*;
+
+/// Path tracing with next event estimation. At every bounce the lights and the skybox are sampled directly
+/// and the BSDF is sampled for the next direction, light reached both ways is weighted by the power heuristic.
+/// Point lights can only be reached by light sampling and take its full weight.
+pub struct PathIntegrator {
+    pub max_depth: usize,
+}
+
+impl PathIntegrator {
+    pub fn new(max_depth: usize) -> Self {
+        Self {
+            max_depth,
+        }
+    }
+
+    // the light arriving along `ray` and its part of at most one bounce
+    fn trace(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler) -> (Color, Color) {
+        let mut l = Color::zero();
+        let mut direct = Color::zero();
+        let mut beta = Color::one();
+        let mut current_ray = *ray;
+        // the last bounce was specular or the ray comes from the camera, light sampling could not find its light
+        let mut specular_bounce = true;
+        let mut bsdf_pdf = 0.0;
+
+        for bounce in 0..self.max_depth {
+            let (hit, obj_idx) = match scene.intersect(&current_ray, 0.001, f32::MAX) {
+                Some(intersection) => intersection,
+                None => {
+                    let le = scene.skybox.ambient(&current_ray);
+                    let weight = if specular_bounce {
+                        1.0
+                    } else {
+                        power_heuristic(bsdf_pdf, scene.skybox.pdf_li(current_ray.direction.normalized()))
+                    };
+                    l = l + beta * le * weight;
+                    if bounce <= 1 {
+                        direct = direct + beta * le * weight;
+                    }
+                    break;
+                },
+            };
+
+            let material = match object_material(scene, obj_idx) {
+                Some(material) => material,
+                None => break,
+            };
+            let wo = -current_ray.direction.normalized();
+
+            let emitted = beta * material.emit(&current_ray);
+            let sampled = beta * sample_lights(scene, material, wo, &hit, sampler);
+            l = l + emitted + sampled;
+            if bounce == 0 {
+                direct = direct + emitted + sampled;
+            } else if bounce == 1 {
+                direct = direct + emitted;
+            }
+
+            let sample = match material.sample(wo, &hit, sampler.get_2d()) {
+                Some(sample) if sample.pdf > 0.0 => sample,
+                _ => break,
+            };
+            let cosine = sample.wi.dot(&hit.normal.normalized()).abs();
+            beta = beta * sample.f * (cosine / sample.pdf);
+            specular_bounce = sample.specular;
+            bsdf_pdf = sample.pdf;
+            current_ray = Ray::new(hit.position, sample.wi, hit.time);
+
+            // russian roulette, the surviving paths are scaled up so the estimate stays unbiased
+            if bounce >= 3 {
+                let survive = beat_y(beta).min(0.95);
+                if sampler.get_1d() >= survive {
+                    break;
+                }
+                beta = beta / survive;
+            }
+        }
+
+        (l, direct)
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn name(&self) -> &'static str {
+        "path"
+    }
+
+    fn li(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler) -> Color {
+        self.trace(scene, ray, sampler).0
+    }
+
+    fn li_with_direct(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler, _splat: &mut dyn FnMut((f32, f32), Color)) -> (Color, Color) {
+        self.trace(scene, ray, sampler)
+    }
+}
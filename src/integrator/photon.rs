@@ -0,0 +1,411 @@
+use std::f32::consts::PI;
+
+use gk_math::base::f32::Vec3;
+use gk_math::color::RGB as Color;
+use rayon::prelude::*;
+
+use crate::base::intersect::Hit;
+use crate::base::kd_tree::{KdPoint, KdTree};
+use crate::base::material::Material;
+use crate::base::ray::Ray;
+use crate::base::sampler::{Sampler, SobolSampler};
+use crate::base::sampling::{uniform_cone_pdf, uniform_sample_cone, Frame};
+use crate::scene::Scene;
+use super::traits::*;
+
+/// Path tracing with a caustic photon map of Jensen.
+/// Before rendering, photons are shot from the point lights at the specular objects and stored where they
+/// land on the first non-specular surface, after one or more specular bounces. At every surface a camera path
+/// hits, the light of the nearest photons is added to the path traced light.
+/// A path tracer can not reach a point light through a specular surface, so the two never count the same light.
+pub struct PhotonMapIntegrator {
+    pub max_depth: usize,
+    /// number of photons shot from the lights
+    pub photon_count: usize,
+    /// number of photons gathered for an estimate
+    pub gather_count: usize,
+    /// the largest distance a photon is gathered from,
+    /// None takes the scene scale, see `default_gather_radius`
+    pub gather_radius: Option<f32>,
+    /// seed of the photon random numbers
+    pub seed: u64,
+    caustics: KdTree<Photon>,
+    // the gather radius of the current scene
+    radius: f32,
+}
+
+impl PhotonMapIntegrator {
+    pub fn new(max_depth: usize) -> Self {
+        Self {
+            max_depth,
+            photon_count: 200_000,
+            gather_count: 64,
+            gather_radius: None,
+            seed: 0,
+            caustics: KdTree::new(Vec::new()),
+            radius: 0.0,
+        }
+    }
+
+    pub fn with_photons(mut self, photon_count: usize) -> Self {
+        self.photon_count = photon_count;
+        self
+    }
+
+    /// gather the `count` nearest photons no further than `radius`
+    pub fn with_gather(mut self, count: usize, radius: f32) -> Self {
+        self.gather_count = count;
+        self.gather_radius = Some(radius);
+        self
+    }
+
+    /// gather the photons no further than `radius`, instead of the scene scale
+    pub fn with_gather_radius(mut self, radius: f32) -> Self {
+        self.gather_radius = Some(radius);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// the number of photons in the caustic map
+    pub fn caustic_photons(&self) -> usize {
+        self.caustics.len()
+    }
+
+    // Shoot photon `index` from one of the emitters, chosen uniformly, and follow it through the specular surfaces.
+    // The photon carries the power of the light divided by the photon count.
+    fn trace_photon(&self, scene: &Scene, emitters: &[Emitter], index: usize, sampler: &mut dyn Sampler) -> Option<Photon> {
+        sampler.start_pixel_sample(0, 0, index);
+        let u_emitter = sampler.get_1d();
+        let u_cone = sampler.get_1d();
+        let u_direction = sampler.get_2d();
+
+        let emitter = &emitters[((u_emitter * emitters.len() as f32) as usize).min(emitters.len() - 1)];
+        let direction = emitter.sample(u_cone, u_direction);
+        let pdf = emitter.pdf(direction) / emitters.len() as f32;
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        let mut power = emitter.intensity / (pdf * self.photon_count as f32);
+        let mut ray = Ray::new(emitter.origin, direction, 0.0);
+        for bounce in 0..self.max_depth {
+            let (hit, obj_idx) = scene.intersect(&ray, 0.001, f32::MAX)?;
+            let material = object_material(scene, obj_idx)?;
+            let wo = -ray.direction.normalized();
+
+            if !material.is_specular() {
+                // light reaching a surface straight from a light is sampled by the path tracer
+                if bounce == 0 {
+                    return None;
+                }
+                return Some(Photon {
+                    position: hit.position,
+                    normal: hit.normal.normalized(),
+                    wi: wo,
+                    power,
+                });
+            }
+
+            let sample = material.sample(wo, &hit, sampler.get_2d())?;
+            if sample.pdf <= 0.0 {
+                return None;
+            }
+            let cosine = sample.wi.dot(&hit.normal.normalized()).abs();
+            // power is not compressed by refraction the way radiance is
+            power = power * sample.f * (cosine * sample.eta * sample.eta / sample.pdf);
+            ray = Ray::new(hit.position, sample.wi, hit.time);
+        }
+
+        None
+    }
+
+    // the light of the caustic photons near `hit` scattered to `wo`
+    fn caustics(&self, material: &(dyn Material + Sync + Send), wo: Vec3, hit: &Hit) -> Color {
+        if self.caustics.is_empty() || material.is_specular() {
+            return Color::zero();
+        }
+        let photons = self.caustics.nearest(hit.position, self.gather_count, self.radius);
+        if photons.is_empty() {
+            return Color::zero();
+        }
+
+        // with fewer photons than asked for, they were gathered from the whole radius
+        let radius2 = if photons.len() == self.gather_count {
+            photons[0].0
+        } else {
+            self.radius * self.radius
+        };
+        if radius2 <= 0.0 {
+            return Color::zero();
+        }
+
+        let normal = hit.normal.normalized();
+        let mut l = Color::zero();
+        for (_, photon) in photons {
+            // photons of another surface close by, like the other side of a corner
+            if photon.normal.dot(&normal) < 0.9 {
+                continue;
+            }
+            l = l + material.eval(wo, photon.wi, hit) * photon.power;
+        }
+        l / (PI * radius2)
+    }
+
+    // the light arriving along `ray` and its part of at most one bounce, the caustics have two or more
+    fn trace(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler) -> (Color, Color) {
+        let mut l = Color::zero();
+        let mut direct = Color::zero();
+        let mut beta = Color::one();
+        let mut current_ray = *ray;
+        // the last bounce was specular or the ray comes from the camera, light sampling could not find its light
+        let mut specular_bounce = true;
+        let mut bsdf_pdf = 0.0;
+
+        for bounce in 0..self.max_depth {
+            let (hit, obj_idx) = match scene.intersect(&current_ray, 0.001, f32::MAX) {
+                Some(intersection) => intersection,
+                None => {
+                    let le = scene.skybox.ambient(&current_ray);
+                    let weight = if specular_bounce {
+                        1.0
+                    } else {
+                        power_heuristic(bsdf_pdf, scene.skybox.pdf_li(current_ray.direction.normalized()))
+                    };
+                    l = l + beta * le * weight;
+                    if bounce <= 1 {
+                        direct = direct + beta * le * weight;
+                    }
+                    break;
+                },
+            };
+
+            let material = match object_material(scene, obj_idx) {
+                Some(material) => material,
+                None => break,
+            };
+            let wo = -current_ray.direction.normalized();
+
+            let emitted = beta * material.emit(&current_ray);
+            let sampled = beta * sample_lights(scene, material, wo, &hit, sampler);
+            l = l + emitted + sampled;
+            l = l + beta * self.caustics(material, wo, &hit);
+            if bounce == 0 {
+                direct = direct + emitted + sampled;
+            } else if bounce == 1 {
+                direct = direct + emitted;
+            }
+
+            let sample = match material.sample(wo, &hit, sampler.get_2d()) {
+                Some(sample) if sample.pdf > 0.0 => sample,
+                _ => break,
+            };
+            let cosine = sample.wi.dot(&hit.normal.normalized()).abs();
+            beta = beta * sample.f * (cosine / sample.pdf);
+            specular_bounce = sample.specular;
+            bsdf_pdf = sample.pdf;
+            current_ray = Ray::new(hit.position, sample.wi, hit.time);
+
+            // russian roulette, the surviving paths are scaled up so the estimate stays unbiased
+            if bounce >= 3 {
+                let survive = beat_y(beta).min(0.95);
+                if sampler.get_1d() >= survive {
+                    break;
+                }
+                beta = beta / survive;
+            }
+        }
+
+        (l, direct)
+    }
+}
+
+impl Integrator for PhotonMapIntegrator {
+    fn name(&self) -> &'static str {
+        "photon"
+    }
+
+    fn preprocess(&mut self, scene: &Scene) {
+        self.radius = self.gather_radius.unwrap_or_else(|| default_gather_radius(scene));
+        let emitters = emitters(scene);
+        if emitters.is_empty() || self.photon_count == 0 {
+            self.caustics = KdTree::new(Vec::new());
+            return;
+        }
+
+        // every photon draws its numbers from its own index, the map does not depend on the thread number
+        let integrator = &*self;
+        let photons: Vec<Photon> = (0..self.photon_count).into_par_iter()
+            .map_init(|| SobolSampler::new(integrator.seed), |sampler, index| integrator.trace_photon(scene, &emitters, index, sampler))
+            .flatten()
+            .collect();
+        self.caustics = KdTree::new(photons);
+    }
+
+    fn li(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler) -> Color {
+        self.trace(scene, ray, sampler).0
+    }
+
+    fn li_with_direct(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler, _splat: &mut dyn FnMut((f32, f32), Color)) -> (Color, Color) {
+        self.trace(scene, ray, sampler)
+    }
+}
+
+/// Light arriving at a surface after specular bounces from a light
+struct Photon {
+    position: Vec3,
+    // the unit normal of the surface
+    normal: Vec3,
+    // unit direction the photon came from
+    wi: Vec3,
+    power: Color,
+}
+
+impl KdPoint for Photon {
+    fn position(&self) -> Vec3 {
+        self.position
+    }
+}
+
+// A point light and the cones of directions to the bounding spheres of the specular objects,
+// every caustic starts in one of them. Photons are shot uniformly over the union of the cones.
+struct Emitter {
+    origin: Vec3,
+    intensity: Color,
+    // unit axis and cosine of the half angle
+    cones: Vec<(Vec3, f32)>,
+    // the sum of the solid angles of the cones over 2 pi
+    solid_angle: f32,
+}
+
+impl Emitter {
+    // pick a cone in proportion to its solid angle, then a direction in it
+    fn sample(&self, u_cone: f32, u_direction: (f32, f32)) -> Vec3 {
+        let mut target = u_cone * self.solid_angle;
+        for &(axis, cos_theta_max) in self.cones.iter() {
+            target -= 1.0 - cos_theta_max;
+            if target < 0.0 {
+                return Frame::new(axis).to_world(uniform_sample_cone(u_direction, cos_theta_max));
+            }
+        }
+        let (axis, cos_theta_max) = self.cones[self.cones.len() - 1];
+        Frame::new(axis).to_world(uniform_sample_cone(u_direction, cos_theta_max))
+    }
+
+    // the density of `sample` by solid angle, the cones can overlap
+    fn pdf(&self, direction: Vec3) -> f32 {
+        self.cones.iter()
+            .filter(|(axis, cos_theta_max)| direction.dot(axis) >= *cos_theta_max)
+            .map(|(_, cos_theta_max)| (1.0 - cos_theta_max) / self.solid_angle * uniform_cone_pdf(*cos_theta_max))
+            .sum()
+    }
+}
+
+/// A sixteenth of the median diagonal of the bounds of the specular objects, which cast the caustics.
+/// A caustic is about the size of the object that casts it, so the radius follows the scene scale,
+/// the median keeps a large mirror from setting it for the small objects.
+pub fn default_gather_radius(scene: &Scene) -> f32 {
+    let mut diagonals: Vec<f32> = scene.objects.iter().enumerate()
+        .filter(|(obj_idx, _)| object_material(scene, *obj_idx).is_some_and(|material| material.is_specular()))
+        .map(|(_, object)| {
+            let aabb = object.base.get_aabb();
+            Vec3::new(aabb.x_max - aabb.x_min, aabb.y_max - aabb.y_min, aabb.z_max - aabb.z_min).length()
+        })
+        .filter(|diagonal| diagonal.is_finite() && *diagonal > 0.0)
+        .collect();
+    if diagonals.is_empty() {
+        // no photon is shot without a specular object, any radius will do
+        return 0.1;
+    }
+    diagonals.sort_by(f32::total_cmp);
+    diagonals[diagonals.len() / 2] / 16.0
+}
+
+// the point lights that can light a specular object
+fn emitters(scene: &Scene) -> Vec<Emitter> {
+    let mut spheres = Vec::new();
+    for (obj_idx, object) in scene.objects.iter().enumerate() {
+        if object_material(scene, obj_idx).is_some_and(|material| material.is_specular()) {
+            let aabb = object.base.get_aabb();
+            let min = Vec3::new(aabb.x_min, aabb.y_min, aabb.z_min);
+            let max = Vec3::new(aabb.x_max, aabb.y_max, aabb.z_max);
+            spheres.push(((min + max) / 2.0, (max - min).length() / 2.0));
+        }
+    }
+    if spheres.is_empty() {
+        return Vec::new();
+    }
+
+    let mut emitters = Vec::new();
+    for light in scene.lights.iter() {
+        // point lights emit the same intensity in every direction
+        let emission = match light.sample_le((0.5, 0.5), (0.5, 0.5)) {
+            Some(emission) if emission.delta_position && !is_black(emission.le) => emission,
+            _ => continue,
+        };
+        let origin = emission.ray.origin;
+
+        let cones: Vec<(Vec3, f32)> = spheres.iter().map(|&(center, radius)| {
+            let to_center = center - origin;
+            let distance = to_center.length();
+            if distance <= radius {
+                // the light is inside the sphere, any direction can reach the object
+                (Vec3::new(0.0, 0.0, 1.0), -1.0)
+            } else {
+                let sin_theta_max = radius / distance;
+                (to_center / distance, (1.0 - sin_theta_max * sin_theta_max).max(0.0).sqrt())
+            }
+        }).collect();
+        let solid_angle = cones.iter().map(|(_, cos_theta_max)| 1.0 - cos_theta_max).sum();
+
+        emitters.push(Emitter {
+            origin,
+            intensity: emission.le,
+            cones,
+            solid_angle,
+        });
+    }
+    emitters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::bsdf::{Dielectric, Lambertian, Metal};
+    use crate::object::{Plane, Sphere};
+
+    #[test]
+    fn the_default_gather_radius_follows_the_specular_objects() {
+        let mut scene = Scene::new();
+        let diffuse = scene.add_material(Lambertian::new());
+        let glass = scene.add_material(Dielectric::new().with_refract_coe(1.5));
+        let mirror = scene.add_material(Metal::new());
+        assert_eq!(default_gather_radius(&scene).to_bits(), 0.1f32.to_bits());
+
+        // a large diffuse floor is no caustic caster
+        scene.add_object(Plane::new().with_center(Vec3::new(0.0, 0.0, 0.0)).with_normal(Vec3::new(0.0, 1.0, 0.0))
+            .with_width(100.0).with_height(100.0)).material = diffuse;
+        for (x, radius) in [(-2.0, 0.5), (0.0, 0.5)] {
+            scene.add_object(Sphere::new().with_center(Vec3::new(x, radius, 0.0)).with_radius(radius)).material = glass;
+        }
+        // the median is a sphere, not the large mirror
+        scene.add_object(Plane::new().with_center(Vec3::new(0.0, 5.0, -5.0)).with_normal(Vec3::new(0.0, 0.0, 1.0))
+            .with_width(50.0).with_height(50.0)).material = mirror;
+        let radius = default_gather_radius(&scene);
+        let expect = 3f32.sqrt() / 16.0;
+        assert!((radius - expect).abs() < 1e-3 * expect, "radius {}", radius);
+
+        // the same scene ten times larger gathers ten times further
+        let mut large = Scene::new();
+        let glass = large.add_material(Dielectric::new().with_refract_coe(1.5));
+        large.add_object(Sphere::new().with_center(Vec3::new(0.0, 5.0, 0.0)).with_radius(5.0)).material = glass;
+        let radius = default_gather_radius(&large);
+        assert!((radius - 10.0 * expect).abs() < 1e-3 * expect, "radius {}", radius);
+
+        let integrator = PhotonMapIntegrator::new(4).with_gather_radius(0.25);
+        assert_eq!(integrator.gather_radius, Some(0.25));
+    }
+}
@@ -0,0 +1,135 @@
+use gk_math::base::f32::Vec3;
+use gk_math::color::RGB as Color;
+
+use crate::base::intersect::Hit;
+use crate::base::light::LightSample;
+use crate::base::material::Material;
+use crate::base::ray::Ray;
+use crate::base::sampler::Sampler;
+use crate::scene::Scene;
+
+/// Compute the radiance arriving at the camera along a ray.
+/// The renderer owns the tiling, sampling and accumulation, an integrator only shades one camera ray.
+pub trait Integrator: Sync + Send {
+    /// the name of the integrator on the command line, a checkpoint keeps it
+    fn name(&self) -> &'static str;
+    /// called once before rendering, to build what the integrator needs from the scene
+    fn preprocess(&mut self, _scene: &Scene) {}
+    /// the radiance arriving along `ray`, random numbers are drawn from `sampler`
+    fn li(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler) -> Color;
+    /// Like `li`, light the integrator finds for other points of the image is given to `splat`
+    /// with its (u, v) on the image. The renderer shades with this, so an integrator tracing light to the camera
+    /// overrides it, its `li` alone misses that light.
+    fn li_with_splats(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler, _splat: &mut dyn FnMut((f32, f32), Color)) -> Color {
+        self.li(scene, ray, sampler)
+    }
+    /// Like `li_with_splats`, with the direct part of the light, that reached the camera after at most one bounce.
+    /// The light is the same `li_with_splats` returns, an integrator that does not tell its direct light
+    /// returns black for it.
+    fn li_with_direct(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler, splat: &mut dyn FnMut((f32, f32), Color)) -> (Color, Color) {
+        (self.li_with_splats(scene, ray, sampler, splat), Color::zero())
+    }
+}
+
+/// the material of an object, `None` if the object refers to a missing material
+pub fn object_material(scene: &Scene, obj_idx: usize) -> Option<&(dyn Material + Sync + Send)> {
+    scene.material.get(scene.objects[obj_idx].material).map(|material| material.as_ref())
+}
+
+/// Light arriving at `hit` straight from the lights and the skybox and scattered to `wo`.
+/// The skybox sample is weighted by the power heuristic against BSDF sampling,
+/// so an integrator that also adds the skybox found by BSDF sampling has to weight it the same way.
+pub fn sample_lights(scene: &Scene, material: &(dyn Material + Sync + Send), wo: Vec3, hit: &Hit, sampler: &mut dyn Sampler) -> Color {
+    sample_point_lights(scene, material, wo, hit, sampler) + sample_skybox(scene, material, wo, hit, sampler)
+}
+
+/// Light arriving at `hit` from the skybox, weighted by the power heuristic against BSDF sampling
+pub fn sample_skybox(scene: &Scene, material: &(dyn Material + Sync + Send), wo: Vec3, hit: &Hit, sampler: &mut dyn Sampler) -> Color {
+    let light_sample = scene.skybox.sample_li(hit.position, sampler.get_2d());
+    light_contribution(scene, material, wo, hit, &light_sample)
+}
+
+/// Light arriving at `hit` from the lights of the scene, without the skybox
+pub fn sample_point_lights(scene: &Scene, material: &(dyn Material + Sync + Send), wo: Vec3, hit: &Hit, sampler: &mut dyn Sampler) -> Color {
+    let mut l = Color::zero();
+    for light in scene.lights.iter() {
+        // draw the numbers first, so the dimensions of a sample do not depend on the light being visible
+        let u = sampler.get_2d();
+        if let Some(light_sample) = light.sample_li(hit.position, u) {
+            l = l + light_contribution(scene, material, wo, hit, &light_sample);
+        }
+    }
+    l
+}
+
+fn light_contribution(scene: &Scene, material: &(dyn Material + Sync + Send), wo: Vec3, hit: &Hit, light_sample: &LightSample) -> Color {
+    if light_sample.pdf <= 0.0 || is_black(light_sample.li) {
+        return Color::zero();
+    }
+    let f = material.eval(wo, light_sample.wi, hit);
+    if is_black(f) || !unoccluded(scene, hit, light_sample.wi, light_sample.distance) {
+        return Color::zero();
+    }
+
+    let weight = if light_sample.delta {
+        1.0
+    } else {
+        power_heuristic(light_sample.pdf, material.pdf(wo, light_sample.wi, hit))
+    };
+    let cosine = light_sample.wi.dot(&hit.normal.normalized()).abs();
+    f * light_sample.li * (cosine * weight / light_sample.pdf)
+}
+
+/// nothing is between `hit` and the point `distance` away along `wi`, `f32::MAX` is a point at infinity
+pub fn unoccluded(scene: &Scene, hit: &Hit, wi: Vec3, distance: f32) -> bool {
+    let shadow_ray = Ray::new(hit.position, wi, hit.time);
+    let t_max = if distance == f32::MAX { f32::MAX } else { distance * (1.0 - 1e-4) };
+    scene.intersect(&shadow_ray, 0.001, t_max).is_none()
+}
+
+/// the multiple importance sampling weight of a sample drawn with density `f_pdf` against one with `g_pdf`
+#[inline]
+pub fn power_heuristic(f_pdf: f32, g_pdf: f32) -> f32 {
+    let f = f_pdf * f_pdf;
+    let g = g_pdf * g_pdf;
+    if f + g == 0.0 { 0.0 } else { f / (f + g) }
+}
+
+#[inline]
+pub fn is_black(color: Color) -> bool {
+    color.r == 0.0 && color.g == 0.0 && color.b == 0.0
+}
+
+/// the Rec. 709 luminance of a linear color
+#[inline]
+pub fn beat_y(beta: Color) -> f32 {
+    0.2126 * beta.r+0.7152 * beta.g+0.0722 * beta.b
+}
+
+/// A false color of `t` in [0, 1] from dark blue through green to dark red,
+/// the polynomial fit of the Turbo colormap of Mikhailov
+pub fn false_color(t: f32) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    let r = 0.1357214 + t * (4.615393 + t * (-42.66032 + t * (132.1311 + t * (-152.9424 + t * 59.28638))));
+    let g = 0.09140261 + t * (2.194188 + t * (4.842967 + t * (-14.18503 + t * (4.277299 + t * 2.829566))));
+    let b = 0.1066733 + t * (12.64195 + t * (-60.58205 + t * (110.3628 + t * (-89.90311 + t * 27.34825))));
+    Color::new(r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0))
+}
+
+/// A bright color of an index, the hues of consecutive indices are far apart
+pub fn index_color(idx: usize) -> Color {
+    // the golden ratio spreads the hues evenly whatever the index number
+    let hue = (idx as f32 * 0.618034).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as usize {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    // saturation 0.7 and value 0.95
+    let low = 0.95 * 0.3;
+    Color::new(low + (0.95 - low) * r, low + (0.95 - low) * g, low + (0.95 - low) * b)
+}
@@ -0,0 +1,36 @@
+use gk_math::color::RGB as Color;
+
+use crate::base::ray::Ray;
+use crate::base::sampler::Sampler;
+use crate::scene::Scene;
+use super::traits::*;
+
+/// The texture coordinates at the first hit as red and green, wrapped to [0, 1) so tiling shows,
+/// rays that hit nothing are black
+pub struct UvIntegrator {}
+
+impl UvIntegrator {
+    pub fn new() -> Self {
+        Self {
+        }
+    }
+}
+
+impl Default for UvIntegrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Integrator for UvIntegrator {
+    fn name(&self) -> &'static str {
+        "uv"
+    }
+
+    fn li(&self, scene: &Scene, ray: &Ray, _sampler: &mut dyn Sampler) -> Color {
+        match scene.intersect(ray, 0.001, f32::MAX) {
+            Some((hit, _)) => Color::new(hit.uv.x - hit.uv.x.floor(), hit.uv.y - hit.uv.y.floor(), 0.0),
+            None => Color::zero(),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use gk_math::color::RGB as Color;
+
+use crate::base::ray::Ray;
+use crate::base::sampler::Sampler;
+use crate::scene::Scene;
+use super::traits::*;
+
+/// Whitted style ray tracing: diffuse and glossy surfaces are lit by the point lights only,
+/// specular surfaces reflect or refract up to `max_depth`, rays that hit nothing see the skybox.
+/// A dielectric picks reflection or refraction by its Fresnel reflectance.
+pub struct WhittedIntegrator {
+    pub max_depth: usize,
+}
+
+impl WhittedIntegrator {
+    pub fn new(max_depth: usize) -> Self {
+        Self {
+            max_depth,
+        }
+    }
+}
+
+impl Integrator for WhittedIntegrator {
+    fn name(&self) -> &'static str {
+        "whitted"
+    }
+
+    fn li(&self, scene: &Scene, ray: &Ray, sampler: &mut dyn Sampler) -> Color {
+        let mut l = Color::zero();
+        let mut beta = Color::one();
+        let mut current_ray = *ray;
+
+        for _ in 0..self.max_depth {
+            let (hit, obj_idx) = match scene.intersect(&current_ray, 0.001, f32::MAX) {
+                Some(intersection) => intersection,
+                None => {
+                    l = l + beta * scene.skybox.ambient(&current_ray);
+                    break;
+                },
+            };
+
+            let material = match object_material(scene, obj_idx) {
+                Some(material) => material,
+                None => break,
+            };
+            let wo = -current_ray.direction.normalized();
+
+            l = l + beta * material.emit(&current_ray);
+            l = l + beta * sample_point_lights(scene, material, wo, &hit, sampler);
+
+            match material.sample(wo, &hit, sampler.get_2d()) {
+                Some(sample) if sample.specular && sample.pdf > 0.0 => {
+                    let cosine = sample.wi.dot(&hit.normal.normalized()).abs();
+                    beta = beta * sample.f * (cosine / sample.pdf);
+                    current_ray = Ray::new(hit.position, sample.wi, hit.time);
+                },
+                _ => break,
+            }
+        }
+
+        l
+    }
+}
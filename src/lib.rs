@@ -5,9 +5,11 @@ pub mod object;
 pub mod material;
 pub mod scene;
 pub mod renderer;
+pub mod integrator;
 
 pub use base::*;
 pub use object::*;
 pub use scene::*;
 pub use renderer::*;
+pub use integrator::*;
 pub use format::*;
@@ -1,3 +1,583 @@
-fn main() {
-    println!("hello solas renderer");
-}
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::time::{Duration, Instant};
+
+use solas::base::sampler::SamplerKind;
+use solas::integrator::*;
+use solas::renderer::{AdaptiveSampling, Aov, CancelToken, Denoiser, Filter, PassOptions, Progress, RenderObserver, Renderer, Tile};
+use gk_math::color::RGB as Color;
+use solas::format::{ExrPixel, ImageData, ImageLayers, is_exr, layer_path};
+use solas::scene::{Canvas, Scene};
+use solas::tone_mapping::{ToneMapper, ToneMapping, Transfer};
+
+const USAGE: &str = "\
+Usage: solas <scene file> [options]
+
+Options:
+  -o, --output <path>         output image, default image_out/<scene name>.png
+  -W, --width <pixels>        image width, default the scene canvas or 800
+  -H, --height <pixels>       image height, default the scene canvas or 450
+  -s, --spp <number>          samples per pixel, default 32
+  -d, --depth <number>        max ray depth, default 50
+  -i, --integrator <name>     path, bdpt, photon, direct or whitted, default path,
+                              or a false color view of the first hit for debugging: ao (ambient occlusion),
+                              normal, uv, distance, object (object index) or material (material index)
+      --photons <number>      photons shot for the caustics of the photon integrator, default 200000
+      --photon-radius <distance>
+                              the largest distance the photon integrator gathers photons from,
+                              default a sixteenth of the median size of the glass and mirror objects
+      --aov <names>           also write layers of the first hit and the light next to the image,
+                              as <output name>.<layer>.png, a comma separated list of albedo, normal,
+                              depth, object, direct and indirect, or all
+      --adaptive <error>      stop sampling a pixel when the standard error of its luminance relative to it
+                              is below <error>, such as 0.02, --spp is then the most samples a pixel takes,
+                              the samples per pixel are written as <output name>.samples.png
+      --min-spp <number>      the samples every pixel takes before its error is estimated with --adaptive, default 16
+  -p, --passes <number>       render progressively, the samples are split over the passes
+                              and the image is written after every pass, default 1
+  -c, --checkpoint <path>     write a checkpoint after every pass and resume from it if it exists,
+                              samples already in the checkpoint count to --spp, it only resumes
+                              with the same --spp, --seed, --sampler, --integrator and --depth
+      --filter <name>         the pixel reconstruction filter, box, tent, gaussian, mitchell or lanczos, default box
+      --filter-radius <pixels>
+                              the radius of the filter, default 0.5 for box, 1 for tent, 1.5 for gaussian,
+                              2 for mitchell and 3 for lanczos
+      --sampler <name>        independent, sobol, halton or stratified, default sobol
+      --seed <number>         seed of the random sample streams, the same seed renders the same image, default 0
+      --tile <w>[x<h>]        tile size, default 64x64
+      --crop <x>,<y>,<w>,<h>  render only the pixels of this rectangle, the others are black,
+                              the camera still frames the whole image
+      --crop-window <x0>,<y0>,<x1>,<y1>
+                              like --crop, the rectangle as fractions of the image from 0 to 1
+      --composite <path>      paste the cropped render into this earlier render of the same size
+  -t, --threads <number>      render threads, default all cores
+      --time-limit <seconds>  stop the render after this time and write what is rendered, the tiles being rendered
+                              are finished, the others of the pass are left out and no pass follows,
+                              a --checkpoint resumes the pixels left out
+      --tone-mapping <name>   linear (clamp), reinhard, reinhard-extended, aces, hable (filmic) or agx, default linear,
+                              an .exr, .hdr or .pfm output is not tone mapped
+      --exposure <stops>      scale the radiance by 2^stops before the tone mapping, default 0
+      --white <radiance>      the radiance that maps to white for reinhard-extended, default 4
+      --transfer <name>       srgb, rec709 or linear (none), the encoding of the tone mapped image,
+                              default srgb, linear for the false color debugging integrators
+      --denoise               denoise the image, guided by the albedo, normal and depth layers it renders for it
+      --half                  write an .exr output in 16 bit half floats instead of 32 bit floats
+      --reference <path>      compare the image to a reference image of the same size, print the errors
+                              and write the FLIP error as <output name>.flip.png
+  -h, --help                  print this message";
+
+struct Options {
+    scene: PathBuf,
+    output: Option<PathBuf>,
+    width: Option<usize>,
+    height: Option<usize>,
+    spp: usize,
+    depth: usize,
+    integrator: String,
+    photons: usize,
+    photon_radius: Option<f32>,
+    aovs: Vec<Aov>,
+    adaptive: Option<f32>,
+    min_spp: usize,
+    passes: usize,
+    checkpoint: Option<PathBuf>,
+    tile_w: usize,
+    tile_h: usize,
+    threads: usize,
+    time_limit: Option<Duration>,
+    seed: u64,
+    sampler: SamplerKind,
+    filter: Filter,
+    crop: Option<Crop>,
+    composite: Option<PathBuf>,
+    tone_mapping: ToneMapper,
+    denoise: bool,
+    half: bool,
+    reference: Option<PathBuf>,
+}
+
+impl Options {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut scene = None;
+        let mut sampler = "sobol".to_string();
+        let mut white = None;
+        let mut filter_radius = None;
+        let mut transfer = None;
+        let mut options = Options {
+            scene: PathBuf::new(),
+            output: None,
+            width: None,
+            height: None,
+            spp: 32,
+            depth: 50,
+            integrator: "path".to_string(),
+            photons: 200_000,
+            photon_radius: None,
+            aovs: Vec::new(),
+            adaptive: None,
+            min_spp: 16,
+            passes: 1,
+            checkpoint: None,
+            tile_w: 64,
+            tile_h: 64,
+            threads: 0,
+            time_limit: None,
+            seed: 0,
+            sampler: SamplerKind::Sobol,
+            filter: Filter::default(),
+            crop: None,
+            composite: None,
+            tone_mapping: ToneMapper::new(ToneMapping::Linear),
+            denoise: false,
+            half: false,
+            reference: None,
+        };
+
+        let mut args = args;
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+            match arg.as_str() {
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    exit(0);
+                },
+                "-o" | "--output" => options.output = Some(PathBuf::from(value(&arg)?)),
+                "-W" | "--width" => options.width = Some(parse_number(&arg, &value(&arg)?)?),
+                "-H" | "--height" => options.height = Some(parse_number(&arg, &value(&arg)?)?),
+                "-s" | "--spp" => options.spp = parse_number(&arg, &value(&arg)?)?,
+                "-d" | "--depth" => options.depth = parse_number(&arg, &value(&arg)?)?,
+                "-i" | "--integrator" => {
+                    options.integrator = value(&arg)?;
+                    // check the name now, so a typo prints the usage
+                    create_integrator(&options)?;
+                },
+                "--photons" => options.photons = parse_number(&arg, &value(&arg)?)?,
+                "--photon-radius" => {
+                    let radius = parse_float(&arg, &value(&arg)?)?;
+                    if radius <= 0.0 {
+                        return Err("the photon radius should be greater than 0".to_string());
+                    }
+                    options.photon_radius = Some(radius);
+                },
+                "--aov" => {
+                    let names = value(&arg)?;
+                    options.aovs = if names == "all" {
+                        Aov::ALL.to_vec()
+                    } else {
+                        names.split(',').map(|name| Aov::from_name(name.trim()).ok_or_else(|| format!("unknown layer `{}`", name)))
+                            .collect::<Result<Vec<_>, _>>()?
+                    };
+                },
+                "--adaptive" => options.adaptive = Some(parse_float(&arg, &value(&arg)?)?),
+                "--min-spp" => options.min_spp = parse_number(&arg, &value(&arg)?)?,
+                "-p" | "--passes" => options.passes = parse_number(&arg, &value(&arg)?)?,
+                "-c" | "--checkpoint" => options.checkpoint = Some(PathBuf::from(value(&arg)?)),
+                "-t" | "--threads" => options.threads = parse_number(&arg, &value(&arg)?)?,
+                "--time-limit" => {
+                    let seconds = parse_float(&arg, &value(&arg)?)?;
+                    if seconds <= 0.0 {
+                        return Err("the time limit should be greater than 0".to_string());
+                    }
+                    options.time_limit = Some(Duration::from_secs_f32(seconds));
+                },
+                "--sampler" => sampler = value(&arg)?,
+                "--filter" => {
+                    let name = value(&arg)?;
+                    options.filter = Filter::from_name(&name).ok_or_else(|| format!("unknown filter `{}`", name))?;
+                },
+                "--filter-radius" => filter_radius = Some(parse_float(&arg, &value(&arg)?)?),
+                "--seed" => {
+                    let seed = value(&arg)?;
+                    options.seed = seed.parse::<u64>().map_err(|e| format!("{}: invalid number `{}`: {}", arg, seed, e))?;
+                },
+                "--tile" => {
+                    let tile = value(&arg)?;
+                    let (w, h) = match tile.split_once('x') {
+                        Some((w, h)) => (parse_number(&arg, w)?, parse_number(&arg, h)?),
+                        None => {
+                            let size = parse_number(&arg, &tile)?;
+                            (size, size)
+                        },
+                    };
+                    options.tile_w = w;
+                    options.tile_h = h;
+                },
+                "--crop" => {
+                    let crop = value(&arg)?;
+                    let [x, y, w, h] = parse_list(&arg, &crop, parse_number)?;
+                    options.crop = Some(Crop::Pixels(x, y, w, h));
+                },
+                "--crop-window" => {
+                    let crop = value(&arg)?;
+                    let [x0, y0, x1, y1] = parse_list(&arg, &crop, parse_float)?;
+                    options.crop = Some(Crop::Window(x0, y0, x1, y1));
+                },
+                "--composite" => options.composite = Some(PathBuf::from(value(&arg)?)),
+                "--denoise" => options.denoise = true,
+                "--half" => options.half = true,
+                "--reference" => options.reference = Some(PathBuf::from(value(&arg)?)),
+                "--tone-mapping" => {
+                    let name = value(&arg)?;
+                    options.tone_mapping.tone_mapping = ToneMapping::from_name(&name).ok_or_else(|| format!("unknown tone mapping `{}`", name))?;
+                },
+                "--exposure" => options.tone_mapping.exposure = parse_float(&arg, &value(&arg)?)?,
+                "--white" => white = Some(parse_float(&arg, &value(&arg)?)?),
+                "--transfer" => {
+                    let name = value(&arg)?;
+                    transfer = Some(Transfer::from_name(&name).ok_or_else(|| format!("unknown transfer function `{}`", name))?);
+                },
+                _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+                _ => {
+                    if scene.is_some() {
+                        return Err(format!("unexpected argument `{}`", arg));
+                    }
+                    scene = Some(PathBuf::from(arg));
+                },
+            }
+        }
+
+        options.scene = scene.ok_or_else(|| "missing scene file".to_string())?;
+
+        if let Some(radius) = filter_radius {
+            if radius <= 0.0 {
+                return Err("the filter radius should be greater than 0".to_string());
+            }
+            options.filter = options.filter.with_radius(radius);
+        }
+        if let Some(white) = white {
+            if white <= 0.0 {
+                return Err("the white point should be greater than 0".to_string());
+            }
+            if let ToneMapping::ReinhardExtended(w) = &mut options.tone_mapping.tone_mapping {
+                *w = white;
+            }
+        }
+        // the false colors of the debugging integrators are display values already
+        let false_color = ["ao", "normal", "uv", "distance", "object", "material"].contains(&options.integrator.as_str());
+        options.tone_mapping.transfer = transfer.unwrap_or(if false_color { Transfer::Linear } else { Transfer::Srgb });
+
+        // a stratified sampler stratifies all samples of a pixel
+        options.sampler = SamplerKind::from_name(&sampler, options.spp).ok_or_else(|| format!("unknown sampler `{}`", sampler))?;
+
+        if options.spp == 0 || options.passes == 0 || options.tile_w == 0 || options.tile_h == 0 {
+            return Err("samples per pixel, passes and tile size should be greater than 0".to_string());
+        }
+        match options.crop {
+            Some(Crop::Pixels(_, _, w, h)) if w == 0 || h == 0 => return Err("the crop size should be greater than 0".to_string()),
+            Some(Crop::Window(x0, y0, x1, y1)) if x0 >= x1 || y0 >= y1 || x0 < 0.0 || y0 < 0.0 || x1 > 1.0 || y1 > 1.0 =>
+                return Err("the crop window should be from <x0>,<y0> to greater <x1>,<y1> in [0, 1]".to_string()),
+            None if options.composite.is_some() => return Err("--composite needs --crop or --crop-window".to_string()),
+            _ => (),
+        }
+        if let Some(threshold) = options.adaptive {
+            if threshold <= 0.0 || options.min_spp == 0 {
+                return Err("the adaptive error and the min samples per pixel should be greater than 0".to_string());
+            }
+            if options.checkpoint.is_some() || options.passes > 1 {
+                return Err("--adaptive can not be combined with --checkpoint or --passes".to_string());
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn parse_number(name: &str, value: &str) -> Result<usize, String> {
+    value.parse::<usize>().map_err(|e| format!("{}: invalid number `{}`: {}", name, value, e))
+}
+
+fn parse_float(name: &str, value: &str) -> Result<f32, String> {
+    value.parse::<f32>().ok().filter(|v| v.is_finite()).ok_or_else(|| format!("{}: invalid number `{}`", name, value))
+}
+
+// `N` comma separated values
+fn parse_list<T, const N: usize>(name: &str, value: &str, parse: fn(&str, &str) -> Result<T, String>) -> Result<[T; N], String> {
+    let values = value.split(',').map(|v| parse(name, v.trim())).collect::<Result<Vec<T>, String>>()?;
+    values.try_into().map_err(|_| format!("{}: expected {} comma separated numbers, got `{}`", name, N, value))
+}
+
+// the crop window of the options, in pixels or in fractions of the image
+#[derive(Clone, Copy)]
+enum Crop {
+    Pixels(usize, usize, usize, usize),
+    Window(f32, f32, f32, f32),
+}
+
+fn create_integrator(options: &Options) -> Result<Box<dyn Integrator>, String> {
+    let depth = options.depth;
+    Ok(match options.integrator.as_str() {
+        "path" => Box::new(PathIntegrator::new(depth)),
+        "direct" => Box::new(DirectIntegrator::new(depth)),
+        "bdpt" => Box::new(BdptIntegrator::new(depth)),
+        "photon" => {
+            let mut integrator = PhotonMapIntegrator::new(depth).with_photons(options.photons).with_seed(options.seed);
+            if let Some(radius) = options.photon_radius {
+                integrator = integrator.with_gather_radius(radius);
+            }
+            Box::new(integrator)
+        },
+        "whitted" => Box::new(WhittedIntegrator::new(depth)),
+        "ao" => Box::new(AmbientOcclusionIntegrator::new()),
+        "normal" => Box::new(NormalIntegrator::new()),
+        "uv" => Box::new(UvIntegrator::new()),
+        "distance" => Box::new(DistanceIntegrator::new()),
+        "object" => Box::new(ObjectIndexIntegrator::new()),
+        "material" => Box::new(MaterialIndexIntegrator::new()),
+        other => return Err(format!("unknown integrator `{}`", other)),
+    })
+}
+
+fn default_output(scene: &Path) -> PathBuf {
+    let stem = scene.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_else(|| "image".to_string());
+    Path::new("image_out").join(format!("{}.png", stem))
+}
+
+// the layers of the first hit that guide the denoiser
+const DENOISE_GUIDES: [Aov; 3] = [Aov::Albedo, Aov::Normal, Aov::Depth];
+
+// the layers to render, the asked ones and the guides of the denoiser
+fn render_aovs(options: &Options) -> Vec<Aov> {
+    let mut aovs = options.aovs.clone();
+    if options.denoise {
+        aovs.extend(DENOISE_GUIDES.iter().filter(|aov| !options.aovs.contains(aov)));
+    }
+    aovs
+}
+
+// replace the image by its denoised version and drop the guides that were not asked for
+fn denoise(options: &Options, mut layers: ImageLayers) -> Result<ImageLayers, String> {
+    let denoised = Denoiser::new().denoise_layers(&layers)?;
+    layers.push("beauty", denoised)?;
+    for aov in DENOISE_GUIDES.iter().filter(|aov| !options.aovs.contains(aov)) {
+        layers.take(aov.name());
+    }
+    Ok(layers)
+}
+
+// paste the crop window of the image into the composite image, the layers stay cropped
+fn composite(base: &ImageData, crop: Tile, mut layers: ImageLayers) -> Result<ImageLayers, String> {
+    let mut image = ImageData::new(base.width(), base.height());
+    image.copy_region(base, 0, 0, base.width(), base.height());
+    if let Some(beauty) = layers.get("beauty") {
+        image.copy_region(beauty, crop.x, crop.y, crop.w, crop.h);
+    }
+    layers.push("beauty", image)?;
+    Ok(layers)
+}
+
+// the rendered layers as they are written, denoised and pasted into the composite image
+fn finish(options: &Options, layers: ImageLayers, base: Option<(&ImageData, Tile)>) -> Result<ImageLayers, String> {
+    let layers = if options.denoise { denoise(options, layers)? } else { layers };
+    match base {
+        Some((base, crop)) => composite(base, crop, layers),
+        None => Ok(layers),
+    }
+}
+
+// write the image and its layers, an .exr output gets them all in one file, and return what was written
+fn write_output(options: &Options, layers: ImageLayers, base: Option<(&ImageData, Tile)>, output: &Path) -> Result<ImageLayers, String> {
+    let layers = finish(options, layers, base)?;
+    if is_exr(output) {
+        layers.write_exr(output, if options.half { ExrPixel::Half } else { ExrPixel::Float })?;
+    } else {
+        layers.write_to_files(options.tone_mapping, output)?;
+    }
+    Ok(layers)
+}
+
+// print the errors of the image against the reference and write the FLIP error next to the output
+fn compare(image: &ImageData, reference: &ImageData, output: &Path) -> Result<(), String> {
+    println!("mse {:.6}, relative mse {:.6}, psnr {:.2} dB, ssim {:.4}, flip {:.4}",
+        image.mse(reference)?, image.relative_mse(reference)?, image.psnr(reference)?, image.ssim(reference)?, image.flip(reference)?);
+    let path = layer_path(&output.with_extension("png"), "flip");
+    image.flip_image(reference)?.write_to_file(ToneMapper::new(ToneMapping::Linear).with_transfer(Transfer::Linear), &path)?;
+    println!("saved {}", path.display());
+    Ok(())
+}
+
+// prints the progress of the tiles with the time left, and cancels the render at the time limit
+struct ProgressLine {
+    cancel: CancelToken,
+    time_limit: Option<Duration>,
+}
+
+impl ProgressLine {
+    fn finished(&self, start: Instant) {
+        if self.cancel.is_cancelled() {
+            println!("stopped at the time limit after {:?}, the tiles not rendered are black or have fewer samples", start.elapsed());
+        } else {
+            println!("rendered in {:?}", start.elapsed());
+        }
+    }
+}
+
+impl RenderObserver for ProgressLine {
+    fn tile_finished(&self, _tile: &Tile, _pixels: &[Color], progress: &Progress) {
+        let eta = progress.eta().map(|eta| format!("{:.1?} left", eta)).unwrap_or_default();
+        let mut stderr = std::io::stderr();
+        let _ = write!(stderr, "\r{:>3}% ({}/{} tiles), {:<16}", (progress.fraction() * 100.0) as usize, progress.finished_tiles, progress.total_tiles, eta);
+        let _ = stderr.flush();
+        if let Some(time_limit) = self.time_limit && progress.elapsed >= time_limit {
+            self.cancel.cancel();
+        }
+    }
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let start = Instant::now();
+    let mut scene = Scene::load(&options.scene).map_err(|e| e.to_string())?;
+    println!("loaded {} in {:?}: {} objects, {} materials, {} lights",
+        options.scene.display(), start.elapsed(), scene.objects.len(), scene.material.len(), scene.lights.len());
+
+    let (width, height) = match scene.canvas {
+        Some(canvas) => (options.width.unwrap_or(canvas.width), options.height.unwrap_or(canvas.height)),
+        None => (options.width.unwrap_or(800), options.height.unwrap_or(450)),
+    };
+    if width == 0 || height == 0 {
+        return Err("image size should be greater than 0".to_string());
+    }
+
+    // a missing or mismatched reference fails before rendering
+    let reference = match &options.reference {
+        Some(path) => {
+            let reference = ImageData::read_from_file(path)?;
+            if reference.width() != width || reference.height() != height {
+                return Err(format!("{}: the reference is {}x{}, but the image is {}x{}",
+                    path.display(), reference.width(), reference.height(), width, height));
+            }
+            Some(reference)
+        },
+        None => None,
+    };
+
+    let base = match &options.composite {
+        Some(path) => {
+            let base = ImageData::read_from_file(path)?;
+            if base.width() != width || base.height() != height {
+                return Err(format!("{}: the image to composite into is {}x{}, but the image is {}x{}",
+                    path.display(), base.width(), base.height(), width, height));
+            }
+            Some(base)
+        },
+        None => None,
+    };
+
+    // light tracing connects to the lens, which the default camera does not describe
+    if options.integrator == "bdpt" && !scene.camera.has_parameters() {
+        return Err("the bdpt integrator needs a [camera] section, the default camera can not be connected to".to_string());
+    }
+
+    // keep the picture undistorted when the resolution is overridden
+    let aspect = width as f32 / height as f32;
+    if scene.camera.aspect != aspect {
+        let camera = std::mem::take(&mut scene.camera);
+        scene.set_camera(camera.with_aspect(aspect));
+    }
+
+    let canvas = scene.canvas.map(|canvas| Canvas::new(width, height, canvas.vertical, canvas.horizontal))
+        .unwrap_or(Canvas::new(width, height, 1.0, 2.0));
+    let renderer = Renderer::new(scene, canvas, options.depth)
+        .with_seed(options.seed)
+        .with_sampler(options.sampler)
+        .with_filter(options.filter)
+        .with_aovs(&render_aovs(&options))
+        .with_integrator(create_integrator(&options)?);
+    let renderer = match options.crop {
+        Some(Crop::Pixels(x, y, w, h)) => renderer.with_crop(x, y, w, h),
+        Some(Crop::Window(x0, y0, x1, y1)) => renderer.with_crop_window(x0, y0, x1, y1),
+        None => renderer,
+    };
+    let crop = renderer.crop_tile();
+    if crop.w == 0 || crop.h == 0 {
+        return Err(format!("the crop window is out of the {}x{} image", width, height));
+    }
+    let base = base.as_ref().map(|base| (base, crop));
+
+    let output = options.output.clone().unwrap_or_else(|| default_output(&options.scene));
+
+    println!("rendering {}x{} with {} samples per pixel, max depth {}", width, height, options.spp, options.depth);
+    if renderer.crop.is_some() {
+        println!("crop window {}x{} from ({}, {})", crop.w, crop.h, crop.x, crop.y);
+    }
+    let passes = PassOptions::new(options.spp, options.passes).with_tile(options.tile_w, options.tile_h).with_threads(options.threads);
+    // every render prints its progress and stops at the time limit
+    let progress = ProgressLine { cancel: CancelToken::new(), time_limit: options.time_limit };
+    let start = Instant::now();
+
+    let layers = if let Some(checkpoint) = &options.checkpoint {
+        let mut result = Ok(());
+        let accumulation = renderer.render_with_checkpoint(&passes, checkpoint, &progress, &progress.cancel,
+            |accumulation| {
+                eprintln!();
+                println!("checkpoint: {} samples per pixel, {:?}", accumulation.min_sample_count(), start.elapsed());
+                result = write_output(&options, accumulation.layers(), base, &output).map(|_| ());
+                result.is_ok()
+            })?;
+        result?;
+        // a finished checkpoint has no pass left to write the image
+        let layers = write_output(&options, accumulation.layers(), base, &output)?;
+        progress.finished(start);
+        layers
+    } else if let Some(threshold) = options.adaptive {
+        let adaptive = AdaptiveSampling::new(threshold, options.spp).with_min_samples(options.min_spp);
+        let accumulation = renderer.adaptive_render(
+            options.tile_w, options.tile_h, options.threads, &adaptive, &progress, &progress.cancel,
+            |pass, active, accumulation| {
+                let window = accumulation.window();
+                let pixels = window.w * window.h;
+                eprintln!();
+                println!("pass {}: {} of {} pixels above the error, {:?}", pass + 1, active, pixels, start.elapsed());
+                true
+            })?;
+        progress.finished(start);
+        // false colors are written as they are
+        let heatmap = layer_path(&output.with_extension("png"), "samples");
+        accumulation.sample_heatmap().write_to_file(ToneMapper::new(ToneMapping::Linear).with_transfer(Transfer::Linear), &heatmap)?;
+        println!("saved {}, {} to {} samples per pixel", heatmap.display(), accumulation.min_sample_count(), accumulation.max_sample_count());
+        write_output(&options, accumulation.layers(), base, &output)?
+    } else if options.passes > 1 {
+        let mut result = Ok(());
+        let accumulation = renderer.progressive_render(&passes, &progress, &progress.cancel,
+            |pass, accumulation| {
+                eprintln!();
+                println!("pass {}/{}: {} samples per pixel, {:?}",
+                    pass + 1, options.passes.min(options.spp), accumulation.min_sample_count(), start.elapsed());
+                result = write_output(&options, accumulation.layers(), base, &output).map(|_| ());
+                result.is_ok()
+            })?;
+        result?;
+        progress.finished(start);
+        // the last pass is written already, but not returned
+        finish(&options, accumulation.layers(), base)?
+    } else {
+        let accumulation = renderer.multi_thread_render_observed(
+            options.tile_w, options.tile_h, options.threads, options.spp, &progress, &progress.cancel)?;
+        eprintln!();
+        progress.finished(start);
+        write_output(&options, accumulation.layers(), base, &output)?
+    };
+
+    println!("saved {}", output.display());
+
+    if let Some(reference) = &reference && let Some(image) = layers.get("beauty") {
+        compare(image, reference, &output)?;
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            exit(2);
+        },
+    };
+
+    if let Err(e) = run(options) {
+        eprintln!("error: {}", e);
+        exit(1);
+    }
+}
@@ -2,6 +2,7 @@ use gk_math::base::f32::Vec3;
 use gk_math::color::RGB as Color;
 use crate::base::ray::Ray;
 use crate::base::material::SkyBox;
+use crate::base::parameter::*;
 
 pub struct Background {}
 
@@ -19,3 +20,13 @@ impl SkyBox for Background {
         ((1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0)).into()
     }
 }
+
+impl Parameters for Background {
+    fn kind(&self) -> &'static str {
+        "background"
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, Parameter)> {
+        Vec::new()
+    }
+}
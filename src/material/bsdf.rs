@@ -1,8 +1,11 @@
 use gk_math::base::f32::Vec3;
+use gk_math::color::RGB as Color;
+use std::f32::consts::PI;
 use crate::base::intersect::Hit;
 use crate::base::material::*;
-use crate::base::ray::Ray;
 use crate::base::optics::*;
+use crate::base::parameter::*;
+use crate::base::sampling::{Frame, cosine_sample_hemisphere, cosine_hemisphere_pdf};
 
 pub struct Lambertian {
     pub albedo: Vec3,
@@ -22,10 +25,42 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _ray: &Ray, hit: &Hit) -> Scatter {
-        let target = hemisphere_suface_distributrion(hit.normal);
-        let scattered = Ray::new(hit.position,target, hit.time);
-        Scatter::new(self.albedo.into(), scattered)
+    fn eval(&self, wo: Vec3, wi: Vec3, hit: &Hit) -> Color {
+        let normal = hit.normal.normalized();
+        if wo.dot(&normal) * wi.dot(&normal) > 0.0 {
+            Color::from(self.albedo) / PI
+        } else {
+            Color::zero()
+        }
+    }
+
+    // cosine weighted directions on the side of wo
+    fn sample(&self, wo: Vec3, hit: &Hit, u: (f32, f32)) -> Option<BsdfSample> {
+        let normal = facing(hit.normal, wo);
+        let wi = Frame::new(normal).to_world(cosine_sample_hemisphere(u));
+        let pdf = cosine_hemisphere_pdf(wi.dot(&normal));
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample::new(wi, Color::from(self.albedo) / PI, pdf, false))
+    }
+
+    fn pdf(&self, wo: Vec3, wi: Vec3, hit: &Hit) -> f32 {
+        cosine_hemisphere_pdf(wi.dot(&facing(hit.normal, wo)))
+    }
+
+    fn albedo(&self, _hit: &Hit) -> Color {
+        Color::from(self.albedo)
+    }
+}
+
+impl Parameters for Lambertian {
+    fn kind(&self) -> &'static str {
+        "lambertian"
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, Parameter)> {
+        vec![("albedo", Parameter::Vector(self.albedo))]
     }
 }
 
@@ -51,13 +86,96 @@ impl Metal {
         self.fuzz = fuzz;
         self
     }
+
+    fn is_mirror(&self) -> bool {
+        self.fuzz <= 0.0
+    }
+
+    // a fuzz of 1 is a uniform lobe around the mirror direction
+    fn phong_exponent(&self) -> f32 {
+        (2.0 / (self.fuzz * self.fuzz) - 2.0).max(0.0)
+    }
+
+    // the density of the Phong lobe around the mirror direction of `wo`
+    fn lobe(&self, wo: Vec3, wi: Vec3, normal: Vec3) -> f32 {
+        let exponent = self.phong_exponent();
+        let cos_alpha = wi.dot(&reflect(-wo, normal)).max(0.0);
+        (exponent + 1.0) / (2.0 * PI) * cos_alpha.powf(exponent)
+    }
 }
 
+// A normalized Phong lobe around the mirror direction divided by the larger cosine of wo and wi,
+// so it stays reciprocal and turns to the mirror BSDF as `fuzz` goes to 0, a fuzz of 0 is a perfect mirror
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, hit: &Hit) -> Scatter {
-        let reflected = reflect(ray.direction.normalized(), hit.normal);
-        let scattered = Ray::new(hit.position, reflected + self.fuzz * hemisphere_suface_distributrion(hit.normal), hit.time);
-        Scatter::new(self.albedo.into(), scattered)
+    fn eval(&self, wo: Vec3, wi: Vec3, hit: &Hit) -> Color {
+        if self.is_mirror() {
+            return Color::zero();
+        }
+        let normal = facing(hit.normal, wo);
+        let cos_i = wi.dot(&normal);
+        if cos_i <= 0.0 {
+            return Color::zero();
+        }
+        let cos_o = wo.dot(&normal);
+        Color::from(self.albedo) * (self.lobe(wo, wi, normal) / cos_i.max(cos_o))
+    }
+
+    fn sample(&self, wo: Vec3, hit: &Hit, u: (f32, f32)) -> Option<BsdfSample> {
+        let normal = facing(hit.normal, wo);
+        let reflected = reflect(-wo, normal);
+
+        if self.is_mirror() {
+            let cosine = reflected.dot(&normal);
+            if cosine <= 0.0 {
+                return None;
+            }
+            return Some(BsdfSample::new(reflected, Color::from(self.albedo) / cosine, 1.0, true));
+        }
+
+        let exponent = self.phong_exponent();
+        let cos_alpha = u.0.powf(1.0 / (exponent + 1.0));
+        let sin_alpha = (1.0 - cos_alpha * cos_alpha).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.1;
+        let local = Vec3::new(sin_alpha * phi.cos(), sin_alpha * phi.sin(), cos_alpha);
+        let wi = Frame::new(reflected.normalized()).to_world(local);
+        // the part of the lobe under the surface is absorbed
+        if wi.dot(&normal) <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample::new(wi, self.eval(wo, wi, hit), self.pdf(wo, wi, hit), false))
+    }
+
+    fn pdf(&self, wo: Vec3, wi: Vec3, hit: &Hit) -> f32 {
+        if self.is_mirror() {
+            return 0.0;
+        }
+        let normal = facing(hit.normal, wo);
+        if wi.dot(&normal) <= 0.0 {
+            return 0.0;
+        }
+        self.lobe(wo, wi, normal)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.is_mirror()
+    }
+
+    fn albedo(&self, _hit: &Hit) -> Color {
+        Color::from(self.albedo)
+    }
+}
+
+impl Parameters for Metal {
+    fn kind(&self) -> &'static str {
+        "metal"
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, Parameter)> {
+        vec![
+            ("albedo", Parameter::Vector(self.albedo)),
+            ("fuzz", Parameter::Float(self.fuzz)),
+        ]
     }
 }
 
@@ -85,26 +203,74 @@ impl Dielectric {
     }
 }
 
+// Specular reflection and refraction, chosen by the Fresnel reflectance
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, hit: &Hit) -> Scatter {
+    fn eval(&self, _wo: Vec3, _wi: Vec3, _hit: &Hit) -> Color {
+        Color::zero()
+    }
 
-        let (outward_normal, ni_over_nt) = if Vec3::dot(&ray.direction, &hit.normal) > 0.0 {
-            (-hit.normal,self.refract_coe)
-        } else {
-            (hit.normal,1.0 / self.refract_coe)
-        };
+    fn sample(&self, wo: Vec3, hit: &Hit, u: (f32, f32)) -> Option<BsdfSample> {
+        let normal = hit.normal.normalized();
+        let cos_o = wo.dot(&normal);
+        if cos_o == 0.0 {
+            return None;
+        }
 
-        let scattered = if let Some(refracted) = refract(ray.direction, outward_normal, ni_over_nt) {
-            Ray::new(hit.position, refracted, hit.time)
+        // the normal on the side of wo and the relative index n_t / n_i
+        let (normal, eta) = if cos_o > 0.0 {
+            (normal, self.refract_coe)
         } else {
-            let reflected = reflect(ray.direction.normalized(), hit.normal);
-            Ray::new(hit.position, reflected, ray.time)
+            (-normal, 1.0 / self.refract_coe)
         };
+        let cos_i = cos_o.abs();
+        let reflectance = fresnel_dielectric(cos_i, eta);
+        let albedo = Color::from(self.albedo);
 
-        Scatter::new(self.albedo.into(), scattered)
+        if u.0 < reflectance {
+            let wi = reflect(-wo, normal);
+            return Some(BsdfSample::new(wi, albedo * (reflectance / cos_i), reflectance, true));
+        }
+
+        let wi = refract(-wo, normal, 1.0 / eta)?.normalized();
+        let cos_t = wi.dot(&normal).abs();
+        // radiance is squeezed into the smaller solid angle of the denser side
+        let transmittance = (1.0 - reflectance) / (eta * eta);
+        Some(BsdfSample::new(wi, albedo * (transmittance / cos_t), 1.0 - reflectance, true).with_eta(eta))
+    }
+
+    fn pdf(&self, _wo: Vec3, _wi: Vec3, _hit: &Hit) -> f32 {
+        0.0
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+
+    fn albedo(&self, _hit: &Hit) -> Color {
+        Color::from(self.albedo)
     }
 }
 
+impl Parameters for Dielectric {
+    fn kind(&self) -> &'static str {
+        "dielectric"
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, Parameter)> {
+        vec![
+            ("albedo", Parameter::Vector(self.albedo)),
+            ("refract_coe", Parameter::Float(self.refract_coe)),
+        ]
+    }
+}
+
+// the unit normal on the side of `w`
+#[inline]
+fn facing(normal: Vec3, w: Vec3) -> Vec3 {
+    let normal = normal.normalized();
+    if w.dot(&normal) < 0.0 { -normal } else { normal }
+}
+
 // use  gk_math::color::RGB as Color;
 
 // pub struct IlluminatioMat {
@@ -3,6 +3,7 @@ use std::f32;
 use crate::base::object::{ObjectBase, ObjectTransfrom};
 use crate::base::intersect::*;
 use crate::base::ray::Ray;
+use crate::base::parameter::*;
 
 pub mod obj;
 pub mod cube;
@@ -67,6 +68,16 @@ impl Mesh {
 
 impl ObjectBase for Mesh {}
 
+impl Parameters for Mesh {
+    fn kind(&self) -> &'static str {
+        "obj"
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, Parameter)> {
+        vec![("path", Parameter::Obj(self.to_obj()))]
+    }
+}
+
 impl ObjectTransfrom for Mesh {
     fn scale(&mut self, s: f32) -> &mut Self {
         for v in self.vertices.iter_mut() {
@@ -191,7 +202,7 @@ impl BoundBuilder for Mesh {
         for v in self.vertices.iter() {
             if flag {
                 if v.x < x_min {
-                    x_max = v.x
+                    x_min = v.x
                 } else if v.x > x_max {
                     x_max = v.x
                 }
@@ -203,7 +214,7 @@ impl BoundBuilder for Mesh {
                 }
 
                 if v.z < z_min {
-                    z_max = v.z;
+                    z_min = v.z;
                 } else if v.z > z_max {
                     z_max = v.z;
                 }
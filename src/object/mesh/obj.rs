@@ -1,6 +1,7 @@
 use std::path::Path;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::fmt::Write;
 use super::Mesh;
 
 impl Mesh {
@@ -102,4 +103,30 @@ impl Mesh {
 
         Ok(mesh)
     }
+
+    /// Write the mesh in obj format, every face with `v/vt/vn` indices as `load_obj` expects
+    pub fn to_obj(&self) -> String {
+        let mut obj = String::new();
+
+        for v in self.vertices.iter() {
+            let _ = writeln!(obj, "v {} {} {}", v.x, v.y, v.z);
+        }
+
+        for vt in self.texcoords.iter() {
+            let _ = writeln!(obj, "vt {} {}", vt.x, vt.y);
+        }
+
+        for vn in self.normals.iter() {
+            let _ = writeln!(obj, "vn {} {} {}", vn.x, vn.y, vn.z);
+        }
+
+        for [a, b, c, an, bn, cn, ac, bc, cc] in self.faces.iter() {
+            let _ = writeln!(obj, "f {}/{}/{} {}/{}/{} {}/{}/{}",
+                a + 1, ac + 1, an + 1,
+                b + 1, bc + 1, bn + 1,
+                c + 1, cc + 1, cn + 1);
+        }
+
+        obj
+    }
 }
@@ -3,6 +3,7 @@ use crate::base::object::*;
 use crate::base::intersect::*;
 use crate::base::bound::*;
 use crate::base::ray::*;
+use crate::base::parameter::*;
 
 pub struct Plane {
     pub center: Vec3,
@@ -78,6 +79,21 @@ impl Plane {
 
 impl ObjectBase for Plane {}
 
+impl Parameters for Plane {
+    fn kind(&self) -> &'static str {
+        "plane"
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, Parameter)> {
+        vec![
+            ("center", Parameter::Vector(self.center)),
+            ("normal", Parameter::Vector(self.normal)),
+            ("width", Parameter::Float(self.width)),
+            ("height", Parameter::Float(self.height)),
+        ]
+    }
+}
+
 impl ObjectTransfrom for Plane {
     fn scale(&mut self, s: f32) -> &mut Self {
         self.width *= s;
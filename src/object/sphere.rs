@@ -3,6 +3,7 @@ use std::f32;
 use crate::base::object::{ObjectBase, ObjectTransfrom};
 use crate::base::intersect::*;
 use crate::base::ray::Ray;
+use crate::base::parameter::*;
 
 pub struct Sphere{
     pub center: Vec3,
@@ -38,6 +39,19 @@ impl Sphere {
 
 impl ObjectBase for Sphere {}
 
+impl Parameters for Sphere {
+    fn kind(&self) -> &'static str {
+        "sphere"
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, Parameter)> {
+        vec![
+            ("center", Parameter::Vector(self.center)),
+            ("radius", Parameter::Float(self.radius)),
+        ]
+    }
+}
+
 impl ObjectTransfrom for Sphere {
     fn scale(&mut self, s: f32) -> &mut Self {
         self.radius = self.radius * s;
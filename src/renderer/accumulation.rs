@@ -0,0 +1,563 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use gk_math::color::RGB as Color;
+
+use crate::{ImageData, ImageLayers};
+use crate::base::sampler::SamplerKind;
+use crate::integrator::false_color;
+use super::aov::Aov;
+use super::film::{Film, Filter};
+use super::renderer::Tile;
+
+/// Running sum of the samples around every pixel weighted by the reconstruction filter,
+/// the image is the sum divided by the sum of the weights, for the box filter the sample number.
+/// Light splatted to the image by light tracing is summed apart and spread over the samples of the whole image.
+/// The layers of the arbitrary output variables are summed with the same sample numbers, without splats.
+/// The luminances of the samples of every pixel and their squares are summed too, without the filter, for the variance of every pixel.
+pub struct Accumulation {
+    width: usize,
+    height: usize,
+    sum: Vec<Color>,
+    // the running sums of the filter weights
+    weights: Vec<f32>,
+    filter: Filter,
+    // the pixels that are rendered, a crop window or the whole image
+    window: Tile,
+    // the running sums of the luminance and the squared luminance of the samples of a pixel, not weighted
+    luminances: Vec<f32>,
+    squares: Vec<f32>,
+    samples: Vec<usize>,
+    // fixed point sums, integer sums do not depend on the order the tiles add their splats in
+    splat: Mutex<Vec<[i128; 3]>>,
+    aovs: Vec<Aov>,
+    // the running sums of every aov
+    layers: Vec<Vec<Color>>,
+}
+
+impl Accumulation {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            sum: vec![Color::zero(); width * height],
+            weights: vec![0.0; width * height],
+            filter: Filter::default(),
+            window: Tile::new(0, 0, width, height),
+            luminances: vec![0.0; width * height],
+            squares: vec![0.0; width * height],
+            samples: vec![0; width * height],
+            splat: Mutex::new(vec![[0; 3]; width * height]),
+            aovs: Vec::new(),
+            layers: Vec::new(),
+        }
+    }
+
+    /// also sum the layers of `aovs`
+    pub fn with_aovs(mut self, aovs: &[Aov]) -> Self {
+        self.aovs = aovs.to_vec();
+        self.layers = vec![vec![Color::zero(); self.width * self.height]; aovs.len()];
+        self
+    }
+
+    /// weight the samples and splats by `filter`, the box filter by default
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn filter(&self) -> Filter {
+        self.filter
+    }
+
+    /// only the pixels of `window` are rendered, the others keep no sample and do not count to the sample numbers
+    pub fn with_window(mut self, window: Tile) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn window(&self) -> Tile {
+        self.window
+    }
+
+    /// the pixel (x, y) is in the window
+    pub fn in_window(&self, x: usize, y: usize) -> bool {
+        let window = &self.window;
+        x >= window.x && y >= window.y && x < window.x + window.w && y < window.y + window.h
+    }
+
+    // the sample numbers of the pixels in the window
+    fn window_samples(&self) -> impl Iterator<Item = usize> + '_ {
+        let window = self.window;
+        (window.y..window.y + window.h).flat_map(move |y| (window.x..window.x + window.w).map(move |x| self.samples[y * self.width + x]))
+    }
+
+    pub fn aovs(&self) -> &[Aov] {
+        &self.aovs
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// the running sum of a pixel
+    pub fn sum(&self, x: usize, y: usize) -> Color {
+        self.sum[y * self.width + x]
+    }
+
+    /// the running sum of the filter weights of a pixel
+    pub fn weight(&self, x: usize, y: usize) -> f32 {
+        self.weights[y * self.width + x]
+    }
+
+    /// Replace the running sums of the pixels of a tile by those of `film`, which started from them,
+    /// every pixel of the tile took `samples` more samples, in the order of the pixels of the tile.
+    /// Set all the tiles of a pass before `add_film_margin` adds what their samples spread beyond them
+    pub fn set_tile(&mut self, tile: &Tile, film: &Film, samples: &[usize]) {
+        for y in 0..tile.h {
+            for x in 0..tile.w {
+                let idx = (tile.y + y) * self.width + tile.x + x;
+                let (sum, weight) = film.pixel(tile.x + x, tile.y + y);
+                self.sum[idx] = sum;
+                self.weights[idx] = weight;
+                self.samples[idx] += samples[y * tile.w + x];
+            }
+        }
+    }
+
+    /// add the pixels of `film` outside of `tile`, the samples of the tile the filter spread to its neighbours
+    pub fn add_film_margin(&mut self, tile: &Tile, film: &Film) {
+        for y in film.y()..film.y() + film.height() {
+            for x in film.x()..film.x() + film.width() {
+                if x >= tile.x && x < tile.x + tile.w && y >= tile.y && y < tile.y + tile.h {
+                    continue;
+                }
+                let idx = y * self.width + x;
+                let (sum, weight) = film.pixel(x, y);
+                self.sum[idx] = self.sum[idx] + sum;
+                self.weights[idx] += weight;
+            }
+        }
+    }
+
+    /// the running sum of the sample luminances of a pixel, not weighted by the filter
+    pub fn luminance_sum(&self, x: usize, y: usize) -> f32 {
+        self.luminances[y * self.width + x]
+    }
+
+    /// the running sum of the squared sample luminances of a pixel
+    pub fn square_sum(&self, x: usize, y: usize) -> f32 {
+        self.squares[y * self.width + x]
+    }
+
+    /// replace the running sums of the luminances and the squared luminances of a tile, `set_tile` counts the samples
+    pub fn set_tile_luminances(&mut self, tile: &Tile, luminances: &[f32], squares: &[f32]) {
+        for y in 0..tile.h {
+            for x in 0..tile.w {
+                let idx = (tile.y + y) * self.width + tile.x + x;
+                self.luminances[idx] = luminances[y * tile.w + x];
+                self.squares[idx] = squares[y * tile.w + x];
+            }
+        }
+    }
+
+    /// The mean luminance of the samples of a pixel and their variance, 0 below two samples.
+    /// Both are of the samples the pixel took, the filter does not weight them, so the neighbours do not add to the variance
+    pub fn luminance_variance(&self, x: usize, y: usize) -> (f32, f32) {
+        let idx = y * self.width + x;
+        let n = self.samples[idx] as f32;
+        if self.samples[idx] == 0 {
+            return (0.0, 0.0);
+        }
+        let mean = self.luminances[idx] / n;
+        if self.samples[idx] < 2 {
+            return (mean, 0.0);
+        }
+        let variance = (self.squares[idx] / n - mean * mean).max(0.0) * n / (n - 1.0);
+        (mean, variance)
+    }
+
+    /// the standard error of the mean luminance of a pixel relative to the mean,
+    /// a dark pixel is taken as bright as 0.05 so its noise does not grow without bound
+    pub fn relative_error(&self, x: usize, y: usize) -> f32 {
+        let n = self.samples[y * self.width + x];
+        if n == 0 {
+            return f32::INFINITY;
+        }
+        let (mean, variance) = self.luminance_variance(x, y);
+        (variance / n as f32).sqrt() / mean.max(0.05)
+    }
+
+    /// the sample number of every pixel in false color, from dark blue for the fewest to red for the most,
+    /// black out of the window
+    pub fn sample_heatmap(&self) -> ImageData {
+        let min = self.min_sample_count();
+        let max = self.max_sample_count();
+        let mut image = ImageData::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !self.in_window(x, y) {
+                    continue;
+                }
+                let t = if max > min { (self.samples[y * self.width + x] - min) as f32 / (max - min) as f32 } else { 0.0 };
+                image[(x, y)] = false_color(t);
+            }
+        }
+        image
+    }
+
+    /// the running sum of a pixel in the layer of `aovs()[layer]`
+    pub fn layer_sum(&self, layer: usize, x: usize, y: usize) -> Color {
+        self.layers[layer][y * self.width + x]
+    }
+
+    /// replace the running sums of a tile in the layer of `aovs()[layer]`, `set_tile` counts the samples
+    pub fn set_tile_layer(&mut self, tile: &Tile, layer: usize, sums: &[Color]) {
+        for y in 0..tile.h {
+            for x in 0..tile.w {
+                self.layers[layer][(tile.y + y) * self.width + tile.x + x] = sums[y * tile.w + x];
+            }
+        }
+    }
+
+    /// the sample number of a pixel
+    pub fn sample_count(&self, x: usize, y: usize) -> usize {
+        self.samples[y * self.width + x]
+    }
+
+    /// add light splatted to the raster positions (x, y), it is spread by the filter divided by its integral,
+    /// tiles rendering at the same time can add their splats
+    pub fn add_splats(&self, splats: &[(f32, f32, Color)]) {
+        let scale = 1.0 / self.filter.integral();
+        let mut sums = self.splat.lock().unwrap_or_else(|e| e.into_inner());
+        for (x, y, color) in splats {
+            self.filter.for_each_pixel(*x, *y, (0, 0), (self.width, self.height), |x, y, weight| {
+                let color = *color * (weight * scale);
+                let sum = &mut sums[y * self.width + x];
+                sum[0] += to_fixed(color.r);
+                sum[1] += to_fixed(color.g);
+                sum[2] += to_fixed(color.b);
+            });
+        }
+    }
+
+    /// the least sample number of the pixels in the window
+    pub fn min_sample_count(&self) -> usize {
+        self.window_samples().min().unwrap_or(0)
+    }
+
+    /// the most sample number of the pixels in the window
+    pub fn max_sample_count(&self) -> usize {
+        self.window_samples().max().unwrap_or(0)
+    }
+
+    /// the current estimate of the image, pixels without sample are black
+    pub fn estimate(&self) -> ImageData {
+        let mut image = ImageData::new(self.width, self.height);
+        // every sample traced one light path for the whole image
+        let total_samples: usize = self.samples.iter().sum();
+        let splat_scale = if total_samples > 0 { (self.width * self.height) as f64 / total_samples as f64 } else { 0.0 };
+        let splat = self.splat.lock().unwrap_or_else(|e| e.into_inner());
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let idx = y * self.width + x;
+                if self.samples[idx] > 0 {
+                    let [r, g, b] = splat[idx].map(|sum| (from_fixed(sum) * splat_scale) as f32);
+                    // the negative lobes of a filter can leave a pixel no weight
+                    let pixel = if self.weights[idx] > 0.0 { self.sum[idx] / self.weights[idx] } else { Color::zero() };
+                    image[(x, y)] = pixel + Color::new(r, g, b);
+                }
+            }
+        }
+        image
+    }
+
+    /// the current estimate of the image as the `beauty` layer, followed by the layers of the aovs
+    pub fn layers(&self) -> ImageLayers {
+        let mut layers = ImageLayers::new(self.width, self.height);
+        // the layers have the size of the accumulation
+        let _ = layers.push("beauty", self.estimate());
+        for (aov, sums) in self.aovs.iter().zip(self.layers.iter()) {
+            let mut image = ImageData::new(self.width, self.height);
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let idx = y * self.width + x;
+                    if self.samples[idx] > 0 {
+                        image[(x, y)] = sums[idx] / (self.samples[idx] as f32);
+                    }
+                }
+            }
+            let _ = layers.push(aov.name(), image);
+        }
+        layers
+    }
+
+    /// Write the running sums, of the image, its filter weights, its luminance and squared luminance and of the aov layers, and the sample numbers to a checkpoint file,
+    /// with the `settings` of the render, the filter and the window.
+    /// The file is written next to `path` first and then renamed, a killed render keeps the previous checkpoint.
+    pub fn save_checkpoint(&self, path: impl AsRef<Path>, settings: &RenderSettings) -> Result<(), String> {
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+
+        let file = File::create(&tmp_path).map_err(|e| format!("{}: {}", tmp_path.display(), e))?;
+        let mut writer = BufWriter::new(file);
+        let mut write = |bytes: &[u8]| writer.write_all(bytes).map_err(|e| format!("{}: {}", tmp_path.display(), e));
+
+        write(CHECKPOINT_MAGIC)?;
+        write(&settings.seed.to_le_bytes())?;
+        let strata = match settings.sampler {
+            SamplerKind::Stratified(samples_per_pixel) => samples_per_pixel,
+            _ => 0,
+        };
+        for name in [settings.sampler.name(), settings.integrator.as_str()] {
+            write(&[name.len() as u8])?;
+            write(name.as_bytes())?;
+        }
+        for number in [strata, settings.depth, settings.sample_number] {
+            write(&(number as u64).to_le_bytes())?;
+        }
+        write(&(self.width as u64).to_le_bytes())?;
+        write(&(self.height as u64).to_le_bytes())?;
+        write(&(self.aovs.len() as u64).to_le_bytes())?;
+        for aov in self.aovs.iter() {
+            write(&[aov.name().len() as u8])?;
+            write(aov.name().as_bytes())?;
+        }
+        let (kind, parameters) = filter_parameters(self.filter);
+        write(&[kind])?;
+        for parameter in parameters {
+            write(&parameter.to_le_bytes())?;
+        }
+        for side in [self.window.x, self.window.y, self.window.w, self.window.h] {
+            write(&(side as u64).to_le_bytes())?;
+        }
+        let splat = self.splat.lock().unwrap_or_else(|e| e.into_inner());
+        for (idx, ((sum, samples), splat)) in self.sum.iter().zip(self.samples.iter()).zip(splat.iter()).enumerate() {
+            write(&sum.r.to_le_bytes())?;
+            write(&sum.g.to_le_bytes())?;
+            write(&sum.b.to_le_bytes())?;
+            write(&self.weights[idx].to_le_bytes())?;
+            write(&self.luminances[idx].to_le_bytes())?;
+            write(&self.squares[idx].to_le_bytes())?;
+            write(&(*samples as u64).to_le_bytes())?;
+            for channel in splat {
+                write(&channel.to_le_bytes())?;
+            }
+            for layer in self.layers.iter() {
+                write(&layer[idx].r.to_le_bytes())?;
+                write(&layer[idx].g.to_le_bytes())?;
+                write(&layer[idx].b.to_le_bytes())?;
+            }
+        }
+        writer.flush().map_err(|e| format!("{}: {}", tmp_path.display(), e))?;
+        drop(writer);
+
+        fs::rename(&tmp_path, path).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Read an accumulation and the settings of its render from a checkpoint file written by `save_checkpoint`
+    pub fn load_checkpoint(path: impl AsRef<Path>) -> Result<(Self, RenderSettings), String> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let file_len = file.metadata().map_err(|e| format!("{}: {}", path.display(), e))?.len();
+        let mut reader = BufReader::new(file);
+        let mut read = |bytes: &mut [u8]| reader.read_exact(bytes).map_err(|e| format!("{}: {}", path.display(), e));
+
+        let mut magic = [0u8; 8];
+        read(&mut magic)?;
+        if &magic != CHECKPOINT_MAGIC {
+            return Err(format!("{}: not a solas checkpoint of version 1", path.display()));
+        }
+
+        let mut u64_bytes = [0u8; 8];
+        let mut f32_bytes = [0u8; 4];
+        let mut i128_bytes = [0u8; 16];
+        read(&mut u64_bytes)?;
+        let seed = u64::from_le_bytes(u64_bytes);
+        let sampler = read_name(&mut read)?;
+        let integrator = read_name(&mut read)?;
+        let mut numbers = [0usize; 3];
+        for number in numbers.iter_mut() {
+            read(&mut u64_bytes)?;
+            *number = u64::from_le_bytes(u64_bytes) as usize;
+        }
+        let [strata, depth, sample_number] = numbers;
+        let settings = RenderSettings {
+            seed,
+            sampler: SamplerKind::from_name(&sampler, strata).ok_or_else(|| format!("{}: unknown sampler `{}`", path.display(), sampler))?,
+            integrator,
+            depth,
+            sample_number,
+        };
+        read(&mut u64_bytes)?;
+        let width = u64::from_le_bytes(u64_bytes) as usize;
+        read(&mut u64_bytes)?;
+        let height = u64::from_le_bytes(u64_bytes) as usize;
+        read(&mut u64_bytes)?;
+        let mut aovs = Vec::new();
+        // the bytes read so far, the magic, the seed, the names of the sampler and integrator,
+        // the strata, depth and samples, the size and the layer number
+        let mut header_len = 2 + settings.sampler.name().len() as u64 + settings.integrator.len() as u64 + 64;
+        for _ in 0..u64::from_le_bytes(u64_bytes) {
+            let name = read_name(&mut read)?;
+            header_len += 1 + name.len() as u64;
+            aovs.push(Aov::from_name(&name).ok_or_else(|| format!("{}: unknown layer `{}`", path.display(), name))?);
+        }
+
+        let mut kind = [0u8; 1];
+        read(&mut kind)?;
+        let mut parameters = [0f32; 3];
+        for parameter in parameters.iter_mut() {
+            read(&mut f32_bytes)?;
+            *parameter = f32::from_le_bytes(f32_bytes);
+        }
+        let filter = filter_from_parameters(kind[0], parameters).ok_or_else(|| format!("{}: unknown filter {}", path.display(), kind[0]))?;
+        let mut window = [0usize; 4];
+        for side in window.iter_mut() {
+            read(&mut u64_bytes)?;
+            *side = u64::from_le_bytes(u64_bytes) as usize;
+        }
+        let window = Tile::new(window[0], window[1], window[2], window[3]);
+        if window.x.checked_add(window.w).is_none_or(|x| x > width) || window.y.checked_add(window.h).is_none_or(|y| y > height) {
+            return Err(format!("{}: the window is outside the {}x{} image", path.display(), width, height));
+        }
+        header_len += 13 + 32;
+
+        // the size comes from the file, it has to match the file before anything is allocated
+        let pixel_len = 80 + 12 * aovs.len() as u64;
+        let pixel_number = width.checked_mul(height);
+        let expected_len = pixel_number.and_then(|n| (n as u64).checked_mul(pixel_len)).and_then(|n| n.checked_add(header_len));
+        if expected_len != Some(file_len) {
+            return Err(format!("{}: the checkpoint of {}x{} pixels is truncated or corrupt", path.display(), width, height));
+        }
+
+        let mut accumulation = Accumulation::new(width, height).with_aovs(&aovs).with_filter(filter).with_window(window);
+        let splat = accumulation.splat.get_mut().unwrap_or_else(|e| e.into_inner());
+        for idx in 0..width * height {
+            let mut rgb = [0f32; 3];
+            for c in rgb.iter_mut() {
+                read(&mut f32_bytes)?;
+                *c = f32::from_le_bytes(f32_bytes);
+            }
+            read(&mut f32_bytes)?;
+            accumulation.weights[idx] = f32::from_le_bytes(f32_bytes);
+            read(&mut f32_bytes)?;
+            accumulation.luminances[idx] = f32::from_le_bytes(f32_bytes);
+            read(&mut f32_bytes)?;
+            accumulation.squares[idx] = f32::from_le_bytes(f32_bytes);
+            read(&mut u64_bytes)?;
+            accumulation.sum[idx] = Color::new(rgb[0], rgb[1], rgb[2]);
+            accumulation.samples[idx] = u64::from_le_bytes(u64_bytes) as usize;
+            for channel in splat[idx].iter_mut() {
+                read(&mut i128_bytes)?;
+                *channel = i128::from_le_bytes(i128_bytes);
+            }
+            for layer in accumulation.layers.iter_mut() {
+                for c in rgb.iter_mut() {
+                    read(&mut f32_bytes)?;
+                    *c = f32::from_le_bytes(f32_bytes);
+                }
+                layer[idx] = Color::new(rgb[0], rgb[1], rgb[2]);
+            }
+        }
+
+        Ok((accumulation, settings))
+    }
+}
+
+/// The settings of a render that a checkpoint keeps, a render only resumes with the same,
+/// samples of other settings do not belong to the same image
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderSettings {
+    /// the seed of the sample streams
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    /// the `Integrator::name`
+    pub integrator: String,
+    pub depth: usize,
+    /// the samples per pixel of the whole render
+    pub sample_number: usize,
+}
+
+// a name of up to 255 bytes after its length
+fn read_name(read: &mut impl FnMut(&mut [u8]) -> Result<(), String>) -> Result<String, String> {
+    let mut len = [0u8; 1];
+    read(&mut len)?;
+    let mut name = vec![0u8; len[0] as usize];
+    read(&mut name)?;
+    Ok(String::from_utf8_lossy(&name).into_owned())
+}
+
+// the last byte is the version of the format
+const CHECKPOINT_MAGIC: &[u8; 8] = b"SOLASCK1";
+
+// the filter as its index in `Filter::NAMES` and its parameters
+fn filter_parameters(filter: Filter) -> (u8, [f32; 3]) {
+    match filter {
+        Filter::Box { radius } => (0, [radius, 0.0, 0.0]),
+        Filter::Tent { radius } => (1, [radius, 0.0, 0.0]),
+        Filter::Gaussian { radius, sigma } => (2, [radius, sigma, 0.0]),
+        Filter::Mitchell { radius, b, c } => (3, [radius, b, c]),
+        Filter::Lanczos { radius, tau } => (4, [radius, tau, 0.0]),
+    }
+}
+
+fn filter_from_parameters(kind: u8, [radius, a, b]: [f32; 3]) -> Option<Filter> {
+    Some(match kind {
+        0 => Filter::Box { radius },
+        1 => Filter::Tent { radius },
+        2 => Filter::Gaussian { radius, sigma: a },
+        3 => Filter::Mitchell { radius, b: a, c: b },
+        4 => Filter::Lanczos { radius, tau: a },
+        _ => return None,
+    })
+}
+
+// splats are summed as fixed point numbers with 64 fraction bits
+const SPLAT_ONE: f64 = 18446744073709551616.0;
+
+#[inline]
+fn to_fixed(value: f32) -> i128 {
+    (value as f64 * SPLAT_ONE) as i128
+}
+
+#[inline]
+fn from_fixed(value: i128) -> f64 {
+    value as f64 / SPLAT_ONE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::beat_y;
+
+    #[test]
+    fn the_variance_is_of_the_samples_of_the_pixel() {
+        // a bright pixel of 4 samples of the same light, between dark pixels the filter mixes into its mean
+        let filter = Filter::from_name("gaussian").unwrap();
+        let mut accumulation = Accumulation::new(3, 1).with_filter(filter);
+        let tile = Tile::new(0, 0, 3, 1);
+        let mut film = Film::new(0, 0, 3, 1, filter);
+        for x in 0..3 {
+            let color = if x == 1 { Color::new(1.0, 1.0, 1.0) } else { Color::zero() };
+            for jitter in [0.2, 0.4, 0.6, 0.8] {
+                film.add_sample(x as f32 + jitter, 0.5, color);
+            }
+        }
+        accumulation.set_tile(&tile, &film, &[4, 4, 4]);
+        accumulation.set_tile_luminances(&tile, &[0.0, 4.0, 0.0], &[0.0, 4.0, 0.0]);
+
+        assert!(beat_y(accumulation.estimate()[(1, 0)]) < 1.0);
+        let (mean, variance) = accumulation.luminance_variance(1, 0);
+        assert!((mean - 1.0).abs() < 1e-6);
+        assert_eq!(variance, 0.0);
+        assert_eq!(accumulation.relative_error(1, 0), 0.0);
+    }
+}
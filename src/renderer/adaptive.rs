@@ -0,0 +1,153 @@
+use super::accumulation::Accumulation;
+use super::progress::{CancelToken, RenderObserver, TileCounter};
+use super::renderer::{Renderer, with_threads};
+
+/// When to stop sampling a pixel: every pixel takes `min_samples`, then passes of `pass_samples` go to the pixels
+/// whose relative error (`Accumulation::relative_error`) is still above `threshold`, and to their neighbours,
+/// until they converge or take `max_samples`
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveSampling {
+    pub threshold: f32,
+    pub min_samples: usize,
+    pub max_samples: usize,
+    pub pass_samples: usize,
+}
+
+impl AdaptiveSampling {
+    /// sample until the relative error is below `threshold`, with 16 to `max_samples` samples per pixel
+    pub fn new(threshold: f32, max_samples: usize) -> Self {
+        Self {
+            threshold,
+            min_samples: 16.min(max_samples),
+            max_samples,
+            pass_samples: 16,
+        }
+    }
+
+    /// the samples every pixel takes before its error is estimated, too few can miss rare bright paths
+    pub fn with_min_samples(mut self, min_samples: usize) -> Self {
+        self.min_samples = min_samples;
+        self
+    }
+
+    pub fn with_pass_samples(mut self, pass_samples: usize) -> Self {
+        self.pass_samples = pass_samples;
+        self
+    }
+
+    /// the pixels of `accumulation` to sample in the next pass, their neighbours too, so a noisy pixel
+    /// that happens to look converged is not left behind. The pixels out of the crop window take no samples and are not noisy
+    pub fn active_pixels(&self, accumulation: &Accumulation) -> Vec<bool> {
+        let (w, h) = (accumulation.width(), accumulation.height());
+        let noisy: Vec<bool> = (0..w * h).map(|i| {
+            let (x, y) = (i % w, i / w);
+            accumulation.in_window(x, y) && accumulation.relative_error(x, y) > self.threshold
+        }).collect();
+        (0..w * h).map(|i| {
+            let (x, y) = (i % w, i / w);
+            if !accumulation.in_window(x, y) || accumulation.sample_count(x, y) >= self.max_samples {
+                return false;
+            }
+            (y.saturating_sub(1)..(y + 2).min(h)).any(|ny| (x.saturating_sub(1)..(x + 2).min(w)).any(|nx| noisy[ny * w + nx]))
+        }).collect()
+    }
+}
+
+impl Renderer {
+    /// Render with `adaptive` sampling, so flat and converged areas stop early and the time goes to the noisy pixels.
+    /// `on_pass(pass, active_pixels, &accumulation)` is called after every pass with the number of pixels still sampled,
+    /// return false to stop early. `Accumulation::sample_heatmap` shows where the samples went.
+    /// `observer` and `cancel` are as for `progressive_render`, the progress counts the most passes the render can take
+    #[allow(clippy::too_many_arguments)]
+    pub fn adaptive_render<F, O>(&self, tile_w: usize, tile_h: usize, thread_number: usize, adaptive: &AdaptiveSampling, observer: &O, cancel: &CancelToken,
+        mut on_pass: F) -> Result<Accumulation, String>
+    where F: FnMut(usize, usize, &Accumulation) -> bool + Send, O: RenderObserver
+    {
+        let mut accumulation = self.new_accumulation();
+        let min_samples = adaptive.min_samples.clamp(1, adaptive.max_samples.max(1));
+        let pass_samples = adaptive.pass_samples.max(1);
+        let w = accumulation.width();
+        let tiles = self.tiles(tile_w, tile_h);
+        let counter = TileCounter::new(tiles.len() * (1 + adaptive.max_samples.saturating_sub(min_samples).div_ceil(pass_samples)));
+
+        with_threads(thread_number, || {
+            self.render_pass_samples(&mut accumulation, &tiles, &|_, _| min_samples, observer, cancel, &counter);
+            let mut pass = 0;
+            loop {
+                let active = adaptive.active_pixels(&accumulation);
+                let active_count = active.iter().filter(|active| **active).count();
+                if !on_pass(pass, active_count, &accumulation) || active_count == 0 || cancel.is_cancelled() {
+                    break;
+                }
+
+                let samples = |x: usize, y: usize| {
+                    if active[y * w + x] { pass_samples.min(adaptive.max_samples - accumulation.sample_count(x, y)) } else { 0 }
+                };
+                let samples: Vec<usize> = (0..active.len()).map(|i| samples(i % w, i / w)).collect();
+                self.render_pass_samples(&mut accumulation, &tiles, &|x, y| samples[y * w + x], observer, cancel, &counter);
+                pass += 1;
+            }
+        })?;
+        Ok(accumulation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gk_math::color::RGB as Color;
+
+    use super::*;
+    use crate::base::material::SkyBox;
+    use crate::base::parameter::*;
+    use crate::base::ray::Ray;
+    use crate::renderer::Filter;
+    use crate::scene::{Canvas, Scene};
+
+    // the same light in every direction
+    struct ConstantSky;
+
+    impl SkyBox for ConstantSky {
+        fn ambient(&self, _ray: &Ray) -> Color {
+            Color::new(0.6, 0.5, 0.4)
+        }
+    }
+
+    impl Parameters for ConstantSky {
+        fn kind(&self) -> &'static str {
+            "constant sky"
+        }
+
+        fn parameters(&self) -> Vec<(&'static str, Parameter)> {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn a_constant_image_converges_after_the_min_samples() {
+        for filter in ["box", "gaussian", "lanczos"] {
+            let mut scene = Scene::new();
+            scene.skybox = Box::new(ConstantSky);
+            let renderer = Renderer::new(scene, Canvas::new(16, 8, 1.0, 2.0), 4).with_filter(Filter::from_name(filter).unwrap());
+            let adaptive = AdaptiveSampling::new(0.01, 64).with_min_samples(8);
+            let accumulation = renderer.adaptive_render(8, 8, 2, &adaptive, &(), &CancelToken::new(), |_, _, _| true).unwrap();
+            assert_eq!(accumulation.max_sample_count(), 8, "{}", filter);
+        }
+    }
+
+    #[test]
+    fn a_crop_window_converges_after_the_min_samples() {
+        let mut scene = Scene::new();
+        scene.skybox = Box::new(ConstantSky);
+        let renderer = Renderer::new(scene, Canvas::new(16, 8, 1.0, 2.0), 4).with_crop(3, 2, 9, 4);
+        let adaptive = AdaptiveSampling::new(0.01, 64).with_min_samples(8);
+        let accumulation = renderer.adaptive_render(8, 8, 2, &adaptive, &(), &CancelToken::new(), |_, _, _| true).unwrap();
+        assert_eq!(accumulation.min_sample_count(), 8);
+        assert_eq!(accumulation.max_sample_count(), 8);
+        for y in 0..8 {
+            for x in 0..16 {
+                let inside = (3..12).contains(&x) && (2..6).contains(&y);
+                assert_eq!(accumulation.sample_count(x, y), if inside { 8 } else { 0 }, "pixel ({}, {})", x, y);
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use gk_math::color::RGB as Color;
+
+use crate::base::intersect::Hit;
+use crate::base::ray::Ray;
+use crate::integrator::{index_color, object_material};
+use crate::scene::Scene;
+
+/// An arbitrary output variable, a layer rendered alongside the beauty pass.
+/// The geometric layers come from the first hit of the camera ray and are black where it hits nothing,
+/// they are averaged over the samples of a pixel like the beauty pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    /// the color of the material at the first hit
+    Albedo,
+    /// the unit normal at the first hit, in world space with components in [-1, 1]
+    Normal,
+    /// the distance from the camera to the first hit
+    Depth,
+    /// a color per object index at the first hit
+    Object,
+    /// the light that reached the camera after at most one bounce, black if the integrator does not tell it
+    Direct,
+    /// the light of the beauty pass that is not direct
+    Indirect,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 6] = [Aov::Albedo, Aov::Normal, Aov::Depth, Aov::Object, Aov::Direct, Aov::Indirect];
+
+    /// the name of the layer
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Object => "object",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Aov::ALL.iter().copied().find(|aov| aov.name() == name)
+    }
+
+    /// the value of a camera sample, `first_hit` is the first hit of `ray` and its object,
+    /// `l` the light of the sample and `direct` its direct part
+    pub fn value(&self, scene: &Scene, ray: &Ray, first_hit: Option<&(Hit, usize)>, l: Color, direct: Color) -> Color {
+        match self {
+            Aov::Direct => direct,
+            Aov::Indirect => l + direct * -1.0,
+            _ => {
+                let (hit, obj_idx) = match first_hit {
+                    Some(first_hit) => first_hit,
+                    None => return Color::zero(),
+                };
+                match self {
+                    Aov::Albedo => object_material(scene, *obj_idx).map_or(Color::zero(), |material| material.albedo(hit)),
+                    Aov::Normal => {
+                        let normal = hit.normal.normalized();
+                        Color::new(normal.x, normal.y, normal.z)
+                    },
+                    Aov::Depth => {
+                        let distance = (hit.position - ray.origin).length();
+                        Color::new(distance, distance, distance)
+                    },
+                    _ => index_color(scene.objects[*obj_idx].idx),
+                }
+            },
+        }
+    }
+}
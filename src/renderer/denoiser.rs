@@ -0,0 +1,279 @@
+use gk_math::color::RGB as Color;
+use rayon::prelude::*;
+
+use crate::format::{ImageData, ImageLayers};
+use crate::integrator::beat_y;
+
+/// An edge avoiding à-trous wavelet filter for noisy renders, after Dammertz et al. and the spatial part of SVGF.
+/// Every iteration blurs with a 5x5 B-spline kernel whose taps are spread 2^iteration pixels apart,
+/// the weight of a neighbour falls with the difference of its normal, depth and albedo, so edges and textures stay sharp,
+/// and with the difference of its luminance relative to the local noise.
+pub struct Denoiser {
+    pub iterations: usize,
+    /// how many standard deviations of the local noise a luminance difference may be,
+    /// halved without a normal or depth guide to keep the edges
+    pub sigma_luminance: f32,
+    /// the exponent of the cosine between the normals
+    pub sigma_normal: f32,
+    /// how many times the local depth gradient a depth difference may be
+    pub sigma_depth: f32,
+    pub sigma_albedo: f32,
+}
+
+/// The first hit layers that guide the denoiser, any of them may be missing
+#[derive(Default, Clone, Copy)]
+pub struct DenoiseGuides<'a> {
+    pub albedo: Option<&'a ImageData>,
+    pub normal: Option<&'a ImageData>,
+    pub depth: Option<&'a ImageData>,
+}
+
+impl Denoiser {
+    pub fn new() -> Self {
+        Self {
+            iterations: 5,
+            sigma_luminance: 4.0,
+            sigma_normal: 128.0,
+            sigma_depth: 1.0,
+            sigma_albedo: 0.1,
+        }
+    }
+
+    /// the filter width grows to 2^(iterations + 2) pixels
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// a larger sigma blurs more across differences of luminance
+    pub fn with_sigma_luminance(mut self, sigma: f32) -> Self {
+        self.sigma_luminance = sigma;
+        self
+    }
+
+    /// denoise the `beauty` layer guided by the `albedo`, `normal` and `depth` layers there are
+    pub fn denoise_layers(&self, layers: &ImageLayers) -> Result<ImageData, String> {
+        let image = layers.get("beauty").ok_or_else(|| "no beauty layer to denoise".to_string())?;
+        let guides = DenoiseGuides {
+            albedo: layers.get("albedo"),
+            normal: layers.get("normal"),
+            depth: layers.get("depth"),
+        };
+        self.denoise(image, guides)
+    }
+
+    /// denoise a linear image, such as the one of `multi_thread_render`, the guides are the layers of `Aov`
+    pub fn denoise(&self, image: &ImageData, guides: DenoiseGuides) -> Result<ImageData, String> {
+        let (w, h) = (image.width(), image.height());
+        for (name, guide) in [("albedo", guides.albedo), ("normal", guides.normal), ("depth", guides.depth)] {
+            if let Some(guide) = guide && (guide.width() != w || guide.height() != h) {
+                return Err(format!("the {} guide is {}x{}, but the image is {}x{}", name, guide.width(), guide.height(), w, h));
+            }
+        }
+        let n = w * h;
+        let pixels = |image: &ImageData| (0..n).map(|i| image[(i % w, i / w)]).collect::<Vec<Color>>();
+
+        // the guides average the first hits of a pixel, dividing the light by the albedo would blow up
+        // the pixels an edge partly covers, so the albedo only stops the filter
+        let mut light: Vec<Color> = pixels(image).iter().map(|c| {
+            let finite = |v: f32| if v.is_finite() { v.max(0.0) } else { 0.0 };
+            Color::new(finite(c.r), finite(c.g), finite(c.b))
+        }).collect();
+
+        let albedo = guides.albedo.map(pixels);
+        let normal = guides.normal.map(pixels);
+        let depth: Option<Vec<f32>> = guides.depth.map(|depth| (0..n).map(|i| depth[(i % w, i / w)].r).collect());
+        let depth_gradient = depth.as_ref().map(|depth| {
+            (0..n).map(|i| {
+                let (x, y) = (i % w, i / w);
+                let at = |x: usize, y: usize| depth[y * w + x];
+                let dx = (at((x + 1).min(w - 1), y) - at(x.saturating_sub(1), y)).abs();
+                let dy = (at(x, (y + 1).min(h - 1)) - at(x, y.saturating_sub(1))).abs();
+                0.5 * dx.max(dy)
+            }).collect::<Vec<f32>>()
+        });
+
+        let mut variance = local_variance(&light, w, h);
+        let sigma_luminance = if normal.is_some() || depth.is_some() { self.sigma_luminance } else { 0.5 * self.sigma_luminance };
+
+        const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+        for iteration in 0..self.iterations {
+            let step = 1usize << iteration;
+            let blurred_variance = blur_3x3(&variance, w, h);
+
+            let filtered: Vec<(Color, f32)> = (0..n).into_par_iter().map(|p| {
+                let (px, py) = (p % w, p / w);
+                let lp = beat_y(light[p]);
+                let luminance_scale = sigma_luminance * blurred_variance[p].max(0.0).sqrt() + 1e-4;
+
+                let mut sum = Color::zero();
+                let mut weight_sum = 0.0;
+                let mut variance_sum = 0.0;
+                for (j, ky) in KERNEL.iter().enumerate() {
+                    let qy = py as isize + (j as isize - 2) * step as isize;
+                    if qy < 0 || qy >= h as isize {
+                        continue;
+                    }
+                    for (i, kx) in KERNEL.iter().enumerate() {
+                        let qx = px as isize + (i as isize - 2) * step as isize;
+                        if qx < 0 || qx >= w as isize {
+                            continue;
+                        }
+                        let q = qy as usize * w + qx as usize;
+
+                        let mut weight = kx * ky;
+                        if q != p {
+                            weight *= (-(beat_y(light[q]) - lp).abs() / luminance_scale).exp();
+                            if let Some(normal) = &normal {
+                                weight *= normal_weight(normal[p], normal[q], self.sigma_normal);
+                            }
+                            if let (Some(depth), Some(gradient)) = (&depth, &depth_gradient) {
+                                // a depth of 0 is a miss, it only mixes with misses
+                                let (zp, zq) = (depth[p], depth[q]);
+                                weight *= if (zp == 0.0) != (zq == 0.0) {
+                                    0.0
+                                } else {
+                                    let offset = ((qx - px as isize).abs().max((qy - py as isize).abs())) as f32;
+                                    (-(zp - zq).abs() / (self.sigma_depth * gradient[p] * offset + 1e-3)).exp()
+                                };
+                            }
+                            if let Some(albedo) = &albedo {
+                                let (a, b) = (albedo[p], albedo[q]);
+                                let d = (a.r - b.r).powi(2) + (a.g - b.g).powi(2) + (a.b - b.b).powi(2);
+                                weight *= (-d / (self.sigma_albedo * self.sigma_albedo)).exp();
+                            }
+                        }
+
+                        sum = sum + light[q] * weight;
+                        weight_sum += weight;
+                        variance_sum += weight * weight * variance[q];
+                    }
+                }
+                // the center weight is never 0
+                (sum / weight_sum, variance_sum / (weight_sum * weight_sum))
+            }).collect();
+
+            for (p, (color, v)) in filtered.into_iter().enumerate() {
+                light[p] = color;
+                variance[p] = v;
+            }
+        }
+
+        let mut denoised = ImageData::new(w, h);
+        for (i, color) in light.iter().enumerate() {
+            denoised[(i % w, i / w)] = *color;
+        }
+        Ok(denoised)
+    }
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// unit normals that point apart get no weight, a pixel without a normal only mixes with others without
+fn normal_weight(a: Color, b: Color, sigma: f32) -> f32 {
+    let (a_missing, b_missing) = (a.r == 0.0 && a.g == 0.0 && a.b == 0.0, b.r == 0.0 && b.g == 0.0 && b.b == 0.0);
+    if a_missing || b_missing {
+        return if a_missing == b_missing { 1.0 } else { 0.0 };
+    }
+    let cos = (a.r * b.r + a.g * b.g + a.b * b.b) / ((a.r * a.r + a.g * a.g + a.b * a.b) * (b.r * b.r + b.g * b.g + b.b * b.b)).sqrt();
+    cos.max(0.0).powf(sigma)
+}
+
+// the variance of the luminance in the 5x5 window around every pixel, the noise estimate of a single image
+fn local_variance(light: &[Color], w: usize, h: usize) -> Vec<f32> {
+    (0..w * h).into_par_iter().map(|p| {
+        let (px, py) = (p % w, p / w);
+        let (mut sum, mut sum2, mut count) = (0.0, 0.0, 0.0);
+        for y in py.saturating_sub(2)..(py + 3).min(h) {
+            for x in px.saturating_sub(2)..(px + 3).min(w) {
+                let l = beat_y(light[y * w + x]);
+                sum += l;
+                sum2 += l * l;
+                count += 1.0;
+            }
+        }
+        let mean = sum / count;
+        (sum2 / count - mean * mean).max(0.0)
+    }).collect()
+}
+
+fn blur_3x3(values: &[f32], w: usize, h: usize) -> Vec<f32> {
+    const KERNEL: [f32; 3] = [0.25, 0.5, 0.25];
+    (0..w * h).into_par_iter().map(|p| {
+        let (px, py) = (p % w, p / w);
+        let (mut sum, mut weight) = (0.0, 0.0);
+        for (j, ky) in KERNEL.iter().enumerate() {
+            for (i, kx) in KERNEL.iter().enumerate() {
+                let (x, y) = (px as isize + i as isize - 1, py as isize + j as isize - 1);
+                if x >= 0 && x < w as isize && y >= 0 && y < h as isize {
+                    sum += kx * ky * values[y as usize * w + x as usize];
+                    weight += kx * ky;
+                }
+            }
+        }
+        sum / weight
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::random::hash_u64;
+
+    fn image(w: usize, h: usize, f: impl Fn(usize, usize) -> Color) -> ImageData {
+        let mut image = ImageData::new(w, h);
+        for y in 0..h {
+            for x in 0..w {
+                image[(x, y)] = f(x, y);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn a_constant_image_passes_unchanged() {
+        let color = Color::new(0.3, 0.6, 0.9);
+        let constant = image(20, 12, |_, _| color);
+        let normal = image(20, 12, |_, _| Color::new(0.0, 1.0, 0.0));
+        let depth = image(20, 12, |x, _| Color::new(2.0 + x as f32 * 0.1, 0.0, 0.0));
+        for guides in [DenoiseGuides::default(), DenoiseGuides { albedo: Some(&constant), normal: Some(&normal), depth: Some(&depth) }] {
+            let denoised = Denoiser::new().denoise(&constant, guides).unwrap();
+            for y in 0..12 {
+                for x in 0..20 {
+                    let c = denoised[(x, y)];
+                    for (v, expect) in [(c.r, color.r), (c.g, color.g), (c.b, color.b)] {
+                        assert!((v - expect).abs() < 1e-5, "pixel ({}, {}) is {}, not {}", x, y, v, expect);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_sharp_normal_edge_stays_sharp() {
+        // a dark left half facing the camera and a bright right half facing right, with noise of +-0.15
+        let (w, h, edge) = (32, 16, 16);
+        let noise = |x: usize, y: usize| (hash_u64(&[x as u64, y as u64]) >> 40) as f32 / (1 << 24) as f32 * 0.3 - 0.15;
+        let base = |x: usize| if x < edge { 0.2 } else { 0.8 };
+        let noisy = image(w, h, |x, y| {
+            let v = base(x) + noise(x, y);
+            Color::new(v, v, v)
+        });
+        let normal = image(w, h, |x, _| if x < edge { Color::new(0.0, 0.0, 1.0) } else { Color::new(1.0, 0.0, 0.0) });
+        let denoised = Denoiser::new().denoise(&noisy, DenoiseGuides { normal: Some(&normal), ..Default::default() }).unwrap();
+
+        // the mean squared error to the clean halves
+        let error = |image: &ImageData| (0..w * h)
+            .map(|i| (image[(i % w, i / w)].g - base(i % w)).powi(2))
+            .sum::<f32>() / (w * h) as f32;
+        assert!(error(&denoised) < 0.1 * error(&noisy), "the noise {} is not reduced from {}", error(&denoised), error(&noisy));
+        for y in 0..h {
+            let (left, right) = (denoised[(edge - 1, y)].g, denoised[(edge, y)].g);
+            assert!((left - 0.2).abs() < 0.05 && (right - 0.8).abs() < 0.05, "the edge of row {} is {} to {}", y, left, right);
+        }
+    }
+}
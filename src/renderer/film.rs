@@ -0,0 +1,217 @@
+use std::f32::consts::PI;
+
+use gk_math::color::RGB as Color;
+
+/// A pixel reconstruction filter, the weight of a sample at offset (x, y) pixels from a pixel center is `evaluate(x) * evaluate(y)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    /// a sample only counts for the pixel it falls in with radius 0.5, sharp but aliased
+    Box { radius: f32 },
+    /// weights fall linearly to 0 at the radius
+    Tent { radius: f32 },
+    /// a Gaussian that is shifted to reach 0 at the radius
+    Gaussian { radius: f32, sigma: f32 },
+    /// the cubic of Mitchell and Netravali, B = C = 1/3 is their recommendation, sharp with little ringing
+    Mitchell { radius: f32, b: f32, c: f32 },
+    /// a sinc windowed by a wider sinc of `tau` lobes, the sharpest, but it rings at edges
+    Lanczos { radius: f32, tau: f32 },
+}
+
+impl Filter {
+    pub const NAMES: [&'static str; 5] = ["box", "tent", "gaussian", "mitchell", "lanczos"];
+
+    /// the filter of a name of `NAMES` with its usual radius
+    pub fn from_name(name: &str) -> Option<Filter> {
+        Some(match name {
+            "box" => Filter::Box { radius: 0.5 },
+            "tent" => Filter::Tent { radius: 1.0 },
+            "gaussian" => Filter::Gaussian { radius: 1.5, sigma: 0.5 },
+            "mitchell" => Filter::Mitchell { radius: 2.0, b: 1.0 / 3.0, c: 1.0 / 3.0 },
+            "lanczos" => Filter::Lanczos { radius: 3.0, tau: 3.0 },
+            _ => return None,
+        })
+    }
+
+    /// the same filter with another radius in pixels, the other parameters stay
+    pub fn with_radius(self, radius: f32) -> Filter {
+        match self {
+            Filter::Box { .. } => Filter::Box { radius },
+            Filter::Tent { .. } => Filter::Tent { radius },
+            Filter::Gaussian { sigma, .. } => Filter::Gaussian { radius, sigma },
+            Filter::Mitchell { b, c, .. } => Filter::Mitchell { radius, b, c },
+            Filter::Lanczos { tau, .. } => Filter::Lanczos { radius, tau },
+        }
+    }
+
+    pub fn radius(&self) -> f32 {
+        match *self {
+            Filter::Box { radius } | Filter::Tent { radius } | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. } | Filter::Lanczos { radius, .. } => radius,
+        }
+    }
+
+    /// the pixels a sample reaches beyond the pixel it falls in
+    pub fn margin(&self) -> usize {
+        (self.radius() - 0.5).max(0.0).ceil() as usize
+    }
+
+    /// the weight at `x` pixels from the center in one dimension, 0 beyond the radius
+    pub fn evaluate(&self, x: f32) -> f32 {
+        let x = x.abs();
+        if x > self.radius() {
+            return 0.0;
+        }
+        match *self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { radius } => radius - x,
+            Filter::Gaussian { radius, sigma } => {
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            },
+            Filter::Mitchell { radius, b, c } => {
+                let x = 2.0 * x / radius;
+                let x2 = x * x;
+                let x3 = x2 * x;
+                if x > 1.0 {
+                    ((-b - 6.0 * c) * x3 + (6.0 * b + 30.0 * c) * x2 + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+                } else {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x3 + (-18.0 + 12.0 * b + 6.0 * c) * x2 + (6.0 - 2.0 * b)) / 6.0
+                }
+            },
+            Filter::Lanczos { tau, .. } => sinc(x) * sinc(x / tau),
+        }
+    }
+
+    /// Call `f(x, y, weight)` for every pixel in the rectangle from (x0, y0) of `width` x `height`
+    /// the filter at the raster position (px, py) reaches. Pixel (x, y) covers [x, x + 1) x [y, y + 1)
+    pub fn for_each_pixel(&self, px: f32, py: f32, (x0, y0): (usize, usize), (width, height): (usize, usize), mut f: impl FnMut(usize, usize, f32)) {
+        let radius = self.radius();
+        // the pixels whose center c is in (p - r, p + r], the box filter of radius 0.5 reaches only the pixel p falls in
+        let range = |p: f32, start: usize, len: usize| {
+            let first = ((p - 0.5 - radius).floor() as isize + 1).max(start as isize);
+            let last = ((p - 0.5 + radius).floor() as isize).min((start + len) as isize - 1);
+            first..=last
+        };
+        for y in range(py, y0, height) {
+            let wy = self.evaluate(y as f32 + 0.5 - py);
+            if wy == 0.0 {
+                continue;
+            }
+            for x in range(px, x0, width) {
+                let weight = wy * self.evaluate(x as f32 + 0.5 - px);
+                if weight != 0.0 {
+                    f(x as usize, y as usize, weight);
+                }
+            }
+        }
+    }
+
+    /// the integral of the filter over the plane, splats are divided by it so they keep their energy
+    pub fn integral(&self) -> f32 {
+        const STEPS: usize = 256;
+        let radius = self.radius();
+        let dx = 2.0 * radius / STEPS as f32;
+        let line: f32 = (0..STEPS).map(|i| self.evaluate(-radius + (i as f32 + 0.5) * dx)).sum::<f32>() * dx;
+        line * line
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::Box { radius: 0.5 }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// The filtered samples of a rectangle of pixels, the weighted sums of the samples around every pixel
+/// and the sums of their weights. A pixel is its sum divided by its weight.
+/// Positions are in raster space, pixel (x, y) covers [x, x + 1) x [y, y + 1)
+pub struct Film {
+    pub filter: Filter,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    sums: Vec<Color>,
+    weights: Vec<f32>,
+}
+
+impl Film {
+    /// the pixels from (x, y) of the size `width` x `height`, with no sample
+    pub fn new(x: usize, y: usize, width: usize, height: usize, filter: Filter) -> Self {
+        Self {
+            filter,
+            x,
+            y,
+            width,
+            height,
+            sums: vec![Color::zero(); width * height],
+            weights: vec![0.0; width * height],
+        }
+    }
+
+    pub fn x(&self) -> usize {
+        self.x
+    }
+
+    pub fn y(&self) -> usize {
+        self.y
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// the pixel (x, y) of the image is in the film
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+    }
+
+    /// the weighted sum and the weight sum of the pixel (x, y) of the image
+    pub fn pixel(&self, x: usize, y: usize) -> (Color, f32) {
+        let idx = (y - self.y) * self.width + x - self.x;
+        (self.sums[idx], self.weights[idx])
+    }
+
+    /// replace the sums of the pixel (x, y) of the image
+    pub fn set_pixel(&mut self, x: usize, y: usize, sum: Color, weight: f32) {
+        let idx = (y - self.y) * self.width + x - self.x;
+        self.sums[idx] = sum;
+        self.weights[idx] = weight;
+    }
+
+    /// add a sample at the raster position (px, py) to the pixels of the film the filter reaches
+    pub fn add_sample(&mut self, px: f32, py: f32, color: Color) {
+        self.add(px, py, color, true);
+    }
+
+    /// Add light at the raster position (px, py), such as light tracing finds, to the sums but not to the weights.
+    /// It is weighted by the filter divided by its integral, so the box filter adds it all to the pixel it falls in
+    pub fn add_splat(&mut self, px: f32, py: f32, color: Color) {
+        let scale = 1.0 / self.filter.integral();
+        self.add(px, py, color * scale, false);
+    }
+
+    fn add(&mut self, px: f32, py: f32, color: Color, weigh: bool) {
+        let (sums, weights, width) = (&mut self.sums, &mut self.weights, self.width);
+        let (x0, y0) = (self.x, self.y);
+        self.filter.for_each_pixel(px, py, (x0, y0), (self.width, self.height), |x, y, weight| {
+            let idx = (y - y0) * width + x - x0;
+            sums[idx] = sums[idx] + color * weight;
+            if weigh {
+                weights[idx] += weight;
+            }
+        });
+    }
+}
@@ -1,3 +1,15 @@
 pub mod renderer;
+pub mod accumulation;
+pub mod aov;
+pub mod denoiser;
+pub mod adaptive;
+pub mod film;
+pub mod progress;
 
-pub use renderer::*;
\ No newline at end of file
+pub use renderer::*;
+pub use accumulation::*;
+pub use aov::*;
+pub use denoiser::*;
+pub use adaptive::*;
+pub use film::*;
+pub use progress::*;
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use gk_math::color::RGB as Color;
+
+use super::renderer::Tile;
+
+/// Stops a render between tiles, the clones share the state, so one can be kept by a GUI or another thread
+/// while the render checks its own. The tiles being rendered are finished, the others are left out
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// How far a render is, after a tile is finished.
+/// The tiles of every pass count, an adaptive render counts the most passes it can take and may end before
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub finished_tiles: usize,
+    pub total_tiles: usize,
+    /// the time since the render started
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// the finished part of the render from 0 to 1
+    pub fn fraction(&self) -> f32 {
+        if self.total_tiles == 0 { 1.0 } else { self.finished_tiles as f32 / self.total_tiles as f32 }
+    }
+
+    /// the time the rest of the render takes at the speed so far, none before the first tile
+    pub fn eta(&self) -> Option<Duration> {
+        if self.finished_tiles == 0 {
+            return None;
+        }
+        let left = self.total_tiles.saturating_sub(self.finished_tiles) as f64;
+        Some(self.elapsed.mul_f64(left / self.finished_tiles as f64))
+    }
+}
+
+// counts the finished tiles of a render over its passes
+pub(super) struct TileCounter {
+    finished: AtomicUsize,
+    total_tiles: usize,
+    start: Instant,
+}
+
+impl TileCounter {
+    pub(super) fn new(total_tiles: usize) -> Self {
+        Self {
+            finished: AtomicUsize::new(0),
+            total_tiles,
+            start: Instant::now(),
+        }
+    }
+
+    // count a finished tile, the progress of the render with it
+    pub(super) fn finish_tile(&self) -> Progress {
+        Progress {
+            finished_tiles: self.finished.fetch_add(1, Ordering::Relaxed) + 1,
+            total_tiles: self.total_tiles,
+            elapsed: self.start.elapsed(),
+        }
+    }
+}
+
+/// Receives the tiles of a render as they are finished, from the render threads
+pub trait RenderObserver: Sync {
+    /// `pixels` are the pixels of `tile` row by row, the light other tiles spread or splat to them is not in yet
+    fn tile_finished(&self, _tile: &Tile, _pixels: &[Color], _progress: &Progress) {}
+}
+
+/// no observer
+impl RenderObserver for () {}
+
+/// a progress callback `progress(finished_tiles, total_tiles)`
+impl<F: Fn(usize, usize) + Sync> RenderObserver for F {
+    fn tile_finished(&self, _tile: &Tile, _pixels: &[Color], progress: &Progress) {
+        self(progress.finished_tiles, progress.total_tiles)
+    }
+}
@@ -1,9 +1,14 @@
 use crate::{
-    ImageData, base::ray::Ray,  scene::*
+    ImageData, ImageLayers, scene::*
 };
-use crate::base::random::*;
+use crate::base::sampler::*;
+use crate::integrator::{Integrator, PathIntegrator, beat_y};
+use super::accumulation::{Accumulation, RenderSettings};
+use super::film::{Film, Filter};
+use super::aov::Aov;
+use super::progress::{CancelToken, RenderObserver, TileCounter};
 use gk_math::color::RGB as Color;
-use std::f32::MAX;
+use std::path::Path;
 use std::sync::Arc;
 use rayon::prelude::*;
 
@@ -11,9 +16,21 @@ pub struct Renderer {
     pub scene: Arc<Scene>,
     pub canvas: Canvas,
     pub depth: usize,
+    /// seed of the sample streams, the same seed renders the same image
+    pub seed: u64,
+    /// how the random numbers of the samples of a pixel are spread
+    pub sampler: SamplerKind,
+    /// shades the camera rays, a path tracer of `depth` bounces by default
+    pub integrator: Box<dyn Integrator>,
+    /// the layers rendered alongside the beauty pass
+    pub aovs: Vec<Aov>,
+    /// how the samples are weighted for the pixels around them
+    pub filter: Filter,
+    /// only the pixels of the crop window are rendered, the whole canvas if none
+    pub crop: Option<Tile>,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Tile {
     pub x: usize,
     pub y: usize,
@@ -32,6 +49,53 @@ impl Tile {
     }
 }
 
+/// How a render in passes is run, the tiles and threads it renders on and how its samples are split over the passes
+#[derive(Debug, Clone, Copy)]
+pub struct PassOptions {
+    pub tile_w: usize,
+    pub tile_h: usize,
+    /// 0 use the rayon default thread number
+    pub thread_number: usize,
+    /// the samples per pixel of the whole render
+    pub sample_number: usize,
+    pub passes: usize,
+}
+
+impl PassOptions {
+    /// `sample_number` samples per pixel in `passes` passes, on tiles of 64x64 and the default threads
+    pub fn new(sample_number: usize, passes: usize) -> Self {
+        Self {
+            tile_w: 64,
+            tile_h: 64,
+            thread_number: 0,
+            sample_number,
+            passes,
+        }
+    }
+
+    pub fn with_tile(mut self, tile_w: usize, tile_h: usize) -> Self {
+        self.tile_w = tile_w;
+        self.tile_h = tile_h;
+        self
+    }
+
+    pub fn with_threads(mut self, thread_number: usize) -> Self {
+        self.thread_number = thread_number;
+        self
+    }
+
+    // the passes there are, no more than the samples
+    fn pass_number(&self) -> usize {
+        self.passes.clamp(1, self.sample_number.max(1))
+    }
+
+    // the samples of a pass, the first passes take one more when they do not split evenly
+    fn pass_samples(&self, pass: usize) -> usize {
+        let passes = self.pass_number();
+        self.sample_number / passes + usize::from(pass < self.sample_number % passes)
+    }
+}
+
 //  depth mean reflect number
 impl Renderer {
     pub fn new(scene: Scene, canvas: Canvas, depth: usize) -> Self {
@@ -39,34 +103,77 @@ impl Renderer {
             scene: Arc::new(scene),
             canvas,
             depth,
+            seed: 0,
+            sampler: SamplerKind::Sobol,
+            integrator: Box::new(PathIntegrator::new(depth)),
+            aovs: Vec::new(),
+            filter: Filter::default(),
+            crop: None,
         }
     }
 
-    //render a picture
-    pub fn render(&self, sample_number: usize) -> ImageData {
-        let w = self.canvas.width;
-        let h = self.canvas.height;
-        let mut image_buff = ImageData::new(w, h);
-
-        for x in 0..w {
-            for y in 0..h {
-                let mut pixel = Color::new(1.0, 1.0, 1.0);
-                let mut rng = PCG32::new();
-
-                for _ in 0..sample_number {
-                    let u = ((x as f32) + rng.rand()) / w as f32;
-                    let v = ((y as f32) + rng.rand()) / h as f32;
-                    let ray = self.scene.camera.get_ray(u, v);
-                    pixel = pixel + Renderer::shade(&self.scene, &ray, 0, self.depth);
-                }
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
 
-                pixel = pixel / (sample_number as f32);
+    pub fn with_sampler(mut self, sampler: SamplerKind) -> Self {
+        self.sampler = sampler;
+        self
+    }
 
-                image_buff[(x,y)] = pixel;
-            }
-        }
+    /// also render the layers of `aovs`, `multi_thread_render_layers` and `Accumulation::layers` return them
+    pub fn with_aovs(mut self, aovs: &[Aov]) -> Self {
+        self.aovs = aovs.to_vec();
+        self
+    }
+
+    /// reconstruct the pixels with `filter`, the box filter by default
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Render only the pixels from (x, y) of the size `w` x `h`, clipped to the canvas. The camera still projects the whole canvas,
+    /// so the pixels are the same as in a render of the whole image and can be pasted into one with `ImageData::copy_region`.
+    /// The filter only weighs the samples in the crop window, so with a filter wider than the box the pixels at its edge may differ a little
+    pub fn with_crop(mut self, x: usize, y: usize, w: usize, h: usize) -> Self {
+        let x = x.min(self.canvas.width);
+        let y = y.min(self.canvas.height);
+        self.crop = Some(Tile::new(x, y, w.min(self.canvas.width - x), h.min(self.canvas.height - y)));
+        self
+    }
+
+    /// Like `with_crop`, the window from (x0, y0) to (x1, y1) as fractions of the canvas, (0, 0) is the upper left corner.
+    /// The window covers every pixel it touches
+    pub fn with_crop_window(self, x0: f32, y0: f32, x1: f32, y1: f32) -> Self {
+        let (width, height) = (self.canvas.width as f32, self.canvas.height as f32);
+        let start = |t: f32, size: f32| (t.clamp(0.0, 1.0) * size).floor() as usize;
+        let end = |t: f32, size: f32| (t.clamp(0.0, 1.0) * size).ceil() as usize;
+        let (x, y) = (start(x0.min(x1), width), start(y0.min(y1), height));
+        let (x_end, y_end) = (end(x0.max(x1), width), end(y0.max(y1), height));
+        self.with_crop(x, y, x_end - x, y_end - y)
+    }
 
-        image_buff
+    /// the pixels that are rendered, the crop window or the whole canvas
+    pub fn crop_tile(&self) -> Tile {
+        self.crop.unwrap_or(Tile::new(0, 0, self.canvas.width, self.canvas.height))
+    }
+
+    /// shade with `integrator`, it is preprocessed with the scene here
+    pub fn with_integrator(mut self, mut integrator: Box<dyn Integrator>) -> Self {
+        integrator.preprocess(&self.scene);
+        self.integrator = integrator;
+        self
+    }
+
+    //render a picture
+    pub fn render(&self, sample_number: usize) -> ImageData {
+        let tile = self.crop_tile();
+        let mut accumulation = self.new_accumulation();
+        let sums = self.render_tile(tile, &accumulation, &|_, _| sample_number);
+        sums.set_tile(&mut accumulation);
+        accumulation.estimate()
     }
 
     // pub fn multi_thread_render(&self, tile_w: usize, tile_h: usize, thread_number: usize, sample_number: usize) -> imagedata {
@@ -131,167 +238,522 @@ impl Renderer {
     //     image_buff
     // }
 
-    pub fn multi_thread_render(&self, tile_w: usize, tile_h: usize, thread_number: usize, sample_number: usize) -> ImageData {
+    /// render on `thread_number` threads, an error if the threads can not be started
+    pub fn multi_thread_render(&self, tile_w: usize, tile_h: usize, thread_number: usize, sample_number: usize) -> Result<ImageData, String> {
+        self.multi_thread_render_with_progress(tile_w, tile_h, thread_number, sample_number, |_, _| {})
+    }
+
+    /// render on `thread_number` threads, `progress(finished_tiles, total_tiles)` is called after each tile
+    /// - thread_number 0 use the rayon default thread number
+    pub fn multi_thread_render_with_progress<F>(&self, tile_w: usize, tile_h: usize, thread_number: usize, sample_number: usize, progress: F) -> Result<ImageData, String>
+    where F: Fn(usize, usize) + Sync + Send
+    {
+        Ok(self.multi_thread_render_observed(tile_w, tile_h, thread_number, sample_number, &progress, &CancelToken::new())?.estimate())
+    }
 
+    /// Like `multi_thread_render_with_progress`, the image is the `beauty` layer followed by the layers of the aovs
+    pub fn multi_thread_render_layers<F>(&self, tile_w: usize, tile_h: usize, thread_number: usize, sample_number: usize, progress: F) -> Result<ImageLayers, String>
+    where F: Fn(usize, usize) + Sync + Send
+    {
+        Ok(self.multi_thread_render_observed(tile_w, tile_h, thread_number, sample_number, &progress, &CancelToken::new())?.layers())
+    }
+
+    /// Render on `thread_number` threads, `observer` gets every tile with its pixels as it is finished and the progress with the ETA.
+    /// `cancel` is checked before every tile, the tiles of a cancelled render that were not started stay black,
+    /// `estimate()` or `layers()` of the accumulation is the image either way
+    pub fn multi_thread_render_observed<O>(&self, tile_w: usize, tile_h: usize, thread_number: usize, sample_number: usize, observer: &O, cancel: &CancelToken) -> Result<Accumulation, String>
+    where O: RenderObserver
+    {
+        let mut accumulation = self.new_accumulation();
+        let tiles = self.tiles(tile_w, tile_h);
+        let counter = TileCounter::new(tiles.len());
+        with_threads(thread_number, || {
+            self.render_pass_inner(&mut accumulation, &tiles, sample_number, observer, cancel, &counter)
+        })?;
+        Ok(accumulation)
+    }
+
+    /// Render the crop window with `sample_number` samples per pixel and paste it into `image`, such as an earlier render
+    /// of the whole canvas, the pixels out of the window stay
+    pub fn render_region_into(&self, image: &mut ImageData, tile_w: usize, tile_h: usize, thread_number: usize, sample_number: usize) -> Result<(), String> {
+        if image.width() != self.canvas.width || image.height() != self.canvas.height {
+            return Err(format!("the image is {}x{}, but the canvas is {}x{}", image.width(), image.height(), self.canvas.width, self.canvas.height));
+        }
+        let region = self.multi_thread_render(tile_w, tile_h, thread_number, sample_number)?;
+        let crop = self.crop_tile();
+        image.copy_region(&region, crop.x, crop.y, crop.w, crop.h);
+        Ok(())
+    }
+
+    /// an empty accumulation of the canvas and the aovs, that renders the crop window
+    pub fn new_accumulation(&self) -> Accumulation {
+        Accumulation::new(self.canvas.width, self.canvas.height).with_aovs(&self.aovs).with_filter(self.filter).with_window(self.crop_tile())
+    }
+
+    /// Render the samples of `options` in its passes and accumulate them, the first passes take one sample more
+    /// when they do not split evenly, and there are no more passes than samples.
+    /// `on_pass(pass, &accumulation)` is called after every pass, `accumulation.estimate()` is the current image,
+    /// return false to stop early.
+    /// `observer` gets every tile as it is finished, with the progress of all passes.
+    /// `cancel` leaves out the tiles not started and stops after the pass, `on_pass` still gets that pass.
+    pub fn progressive_render<F, O>(&self, options: &PassOptions, observer: &O, cancel: &CancelToken, on_pass: F) -> Result<Accumulation, String>
+    where F: FnMut(usize, &Accumulation) -> bool + Send, O: RenderObserver
+    {
+        self.continue_render(self.new_accumulation(), options, observer, cancel, on_pass)
+    }
+
+    /// Like `progressive_render`, but add the passes to an existing accumulation, such as a loaded checkpoint
+    pub fn continue_render<F, O>(&self, mut accumulation: Accumulation, options: &PassOptions, observer: &O, cancel: &CancelToken, mut on_pass: F)
+        -> Result<Accumulation, String>
+    where F: FnMut(usize, &Accumulation) -> bool + Send, O: RenderObserver
+    {
+        let tiles = self.tiles(options.tile_w, options.tile_h);
+        let counter = TileCounter::new(tiles.len() * options.pass_number());
+        with_threads(options.thread_number, || {
+            for pass in 0..options.pass_number() {
+                self.render_pass_inner(&mut accumulation, &tiles, options.pass_samples(pass), observer, cancel, &counter);
+                if !on_pass(pass, &accumulation) || cancel.is_cancelled() {
+                    break;
+                }
+            }
+        })?;
+        Ok(accumulation)
+    }
+
+    /// Render until every pixel has the samples of `options`, write a checkpoint after every pass,
+    /// the passes are as large as the first pass of `progressive_render` and the last takes the rest.
+    /// If the checkpoint file exists the render resumes from it, so a killed render only loses the last pass.
+    /// A checkpoint of other settings, seed, sampler, integrator, depth, samples, size, layers, filter or crop, is an error.
+    /// `on_pass(&accumulation)` is called after every checkpoint, return false to stop early.
+    /// `observer` and `cancel` are as for `progressive_render`, the checkpoint of a cancelled pass keeps
+    /// the samples of every pixel, so the resumed render gives the pixels left out their samples.
+    pub fn render_with_checkpoint<F, O>(&self, options: &PassOptions, checkpoint: impl AsRef<Path>, observer: &O, cancel: &CancelToken, mut on_pass: F)
+        -> Result<Accumulation, String>
+    where F: FnMut(&Accumulation) -> bool + Send, O: RenderObserver
+    {
+        let checkpoint = checkpoint.as_ref();
+        let mut accumulation = if checkpoint.exists() {
+            let (accumulation, settings) = Accumulation::load_checkpoint(checkpoint)?;
+            if settings.seed != self.seed {
+                return Err(format!("{}: checkpoint was rendered with seed {}, but the seed is {}", checkpoint.display(), settings.seed, self.seed));
+            }
+            if settings.sampler != self.sampler {
+                return Err(format!("{}: checkpoint was rendered with the sampler {:?}, but the sampler is {:?}", checkpoint.display(),
+                    settings.sampler, self.sampler));
+            }
+            if settings.integrator != self.integrator.name() {
+                return Err(format!("{}: checkpoint was rendered with the {} integrator, but the integrator is {}", checkpoint.display(),
+                    settings.integrator, self.integrator.name()));
+            }
+            if settings.depth != self.depth {
+                return Err(format!("{}: checkpoint was rendered with depth {}, but the depth is {}", checkpoint.display(), settings.depth, self.depth));
+            }
+            if settings.sample_number != options.sample_number {
+                return Err(format!("{}: checkpoint renders {} samples per pixel, but the render takes {}", checkpoint.display(),
+                    settings.sample_number, options.sample_number));
+            }
+            if accumulation.width() != self.canvas.width || accumulation.height() != self.canvas.height {
+                return Err(format!("{}: checkpoint is {}x{}, but the canvas is {}x{}", checkpoint.display(),
+                    accumulation.width(), accumulation.height(), self.canvas.width, self.canvas.height));
+            }
+            if accumulation.aovs() != self.aovs.as_slice() {
+                return Err(format!("{}: checkpoint has the layers [{}], but the layers are [{}]", checkpoint.display(),
+                    aov_names(accumulation.aovs()), aov_names(&self.aovs)));
+            }
+            if accumulation.filter() != self.filter {
+                return Err(format!("{}: checkpoint was rendered with the filter {:?}, but the filter is {:?}", checkpoint.display(),
+                    accumulation.filter(), self.filter));
+            }
+            let (window, crop) = (accumulation.window(), self.crop_tile());
+            if window != crop {
+                return Err(format!("{}: checkpoint has the crop {},{},{},{}, but the crop is {},{},{},{}", checkpoint.display(),
+                    window.x, window.y, window.w, window.h, crop.x, crop.y, crop.w, crop.h));
+            }
+            accumulation
+        } else {
+            self.new_accumulation()
+        };
+
+        let (sample_number, pass_samples) = (options.sample_number, options.pass_samples(0).max(1));
+        let settings = RenderSettings {
+            seed: self.seed,
+            sampler: self.sampler,
+            integrator: self.integrator.name().to_string(),
+            depth: self.depth,
+            sample_number,
+        };
+        let tiles = self.tiles(options.tile_w, options.tile_h);
+        let counter = TileCounter::new(tiles.len() * sample_number.saturating_sub(accumulation.min_sample_count()).div_ceil(pass_samples));
+        let w = accumulation.width();
+        with_threads(options.thread_number, || {
+            while accumulation.min_sample_count() < sample_number {
+                // a cancelled pass leaves some pixels behind the others
+                let samples: Vec<usize> = (0..w * accumulation.height())
+                    .map(|i| pass_samples.min(sample_number.saturating_sub(accumulation.sample_count(i % w, i / w))))
+                    .collect();
+                self.render_pass_samples(&mut accumulation, &tiles, &|x, y| samples[y * w + x], observer, cancel, &counter);
+                accumulation.save_checkpoint(checkpoint, &settings)?;
+                if !on_pass(&accumulation) || cancel.is_cancelled() {
+                    break;
+                }
+            }
+            Ok::<(), String>(())
+        })??;
+
+        Ok(accumulation)
+    }
+
+    /// Render one pass of `sample_number` samples per pixel and add it to `accumulation`
+    pub fn render_pass(&self, accumulation: &mut Accumulation, tile_w: usize, tile_h: usize, thread_number: usize, sample_number: usize) -> Result<(), String> {
+        let tiles = self.tiles(tile_w, tile_h);
+        let counter = TileCounter::new(tiles.len());
+        with_threads(thread_number, || {
+            self.render_pass_inner(accumulation, &tiles, sample_number, &(), &CancelToken::new(), &counter)
+        })
+    }
+
+    fn render_pass_inner<O>(&self, accumulation: &mut Accumulation, tiles: &[Tile], sample_number: usize, observer: &O, cancel: &CancelToken, counter: &TileCounter)
+    where O: RenderObserver
+    {
+        self.render_pass_samples(accumulation, tiles, &|_, _| sample_number, observer, cancel, counter);
+    }
+
+    // render one pass of `samples(x, y)` samples for every pixel of `tiles`, the tiles not started when `cancel` is set are left out,
+    // `counter` counts the tiles of the whole render for the progress
+    pub(super) fn render_pass_samples<S, O>(&self, accumulation: &mut Accumulation, tiles: &[Tile], samples: &S, observer: &O, cancel: &CancelToken,
+        counter: &TileCounter)
+    where S: Fn(usize, usize) -> usize + Sync, O: RenderObserver
+    {
+        let tiled_image: Vec<TileSums> = tiles.par_iter().filter_map(|tile| {
+            if cancel.is_cancelled() {
+                return None;
+            }
+            let result = self.render_tile(*tile, accumulation, samples);
+            observer.tile_finished(tile, &result.pixels(), &counter.finish_tile());
+            Some(result)
+        }).collect();
+
+        // the filter spreads the samples of a tile over the edges of its neighbours, which are set first
+        for sums in tiled_image.iter() {
+            sums.set_tile(accumulation);
+        }
+        for sums in tiled_image.iter() {
+            accumulation.add_film_margin(&sums.tile, &sums.film);
+        }
+    }
+
+    // split the crop window to tiles, the tiles of the last column and row may be smaller
+    pub(super) fn tiles(&self, tile_w: usize, tile_h: usize) -> Vec<Tile> {
         let mut tile_queue: Vec<Tile> = Vec::new();
+        let crop = self.crop_tile();
+        let (x_end, y_end) = (crop.x + crop.w, crop.y + crop.h);
+        let (tile_w, tile_h) = (tile_w.max(1), tile_h.max(1));
 
-        let mut x: usize = 0;
-        let mut y: usize = 0;
+        let mut x: usize = crop.x;
+        let mut y: usize = crop.y;
         let mut w: usize = tile_w;
         let mut h: usize = tile_h;
 
-        while y < self.canvas.height {
-            while x < self.canvas.width {
+        while y < y_end {
+            while x < x_end {
 
-                if  (x + w) > self.canvas.width {
-                    w = self.canvas.width - x;
+                if  (x + w) > x_end {
+                    w = x_end - x;
                 }
 
-                if (y + h) > self.canvas.height {
-                    h = self.canvas.height - y;
+                if (y + h) > y_end {
+                    h = y_end - y;
                 }
 
                 tile_queue.push(Tile::new(x, y, w, h));
                 x += tile_w;
             }
-            x = 0;
+            x = crop.x;
             w = tile_w;
             h = tile_h;
             y += tile_h;
         }
 
-        let tiled_image: Vec<(Tile, Vec<Color>)> = tile_queue.par_iter().map(|tile| {
-            let scene = self.scene.clone();
-            let canvas = self.canvas.clone();
-            let max_depth =  self.depth;
-            Renderer::render_tile(&scene, canvas, tile.clone(), max_depth, sample_number)
-        }).collect();
-
-        let mut img_buf = ImageData::new(self.canvas.width, self.canvas.height);
-        for (tile, colors) in tiled_image {
-            Renderer::write_tile(&tile, &colors, &mut img_buf);
-        }
-        img_buf
+        tile_queue
     }
 
-    fn render_tile(scene: &Arc<Scene>, canvas: Canvas, tile: Tile, max_depth: usize, sample_number: usize) -> (Tile, Vec<Color>){
-        let w = canvas.width;
-        let h = canvas.height;
-        let mut result: Vec<Color> =  Vec::with_capacity(tile.h * tile.w);
+    // the running sums of every pixel (x, y) in the tile after `samples(x, y)` more samples.
+    // samples are added one by one to the sums, so a render split to passes gives the same sums for the box filter.
+    // the filter weighs the samples in a film of the tile and the pixels around it that the filter reaches,
+    // those pixels start from 0 in the film and are added to the image after the pass.
+    // a sample draws its random numbers at (pixel, sample index), whatever tile, thread or pass renders it.
+    // the light the integrator splats to other pixels is added to `accumulation` when the tile is done
+    fn render_tile<S>(&self, tile: Tile, accumulation: &Accumulation, samples: &S) -> TileSums
+    where S: Fn(usize, usize) -> usize
+    {
+        let w = self.canvas.width;
+        let h = self.canvas.height;
+        let mut sampler = self.sampler.create(self.seed);
+        let filter = accumulation.filter();
+        let margin = filter.margin();
+        let (film_x, film_y) = (tile.x.saturating_sub(margin), tile.y.saturating_sub(margin));
+        let mut film = Film::new(film_x, film_y, (tile.x + tile.w + margin).min(w) - film_x, (tile.y + tile.h + margin).min(h) - film_y, filter);
+        for y in tile.y..(tile.y + tile.h) {
+            for x in tile.x..(tile.x + tile.w) {
+                film.set_pixel(x, y, accumulation.sum(x, y), accumulation.weight(x, y));
+            }
+        }
+        let mut luminances: Vec<f32> = Vec::with_capacity(tile.h * tile.w);
+        let mut squares: Vec<f32> = Vec::with_capacity(tile.h * tile.w);
+        let mut sample_numbers: Vec<usize> = Vec::with_capacity(tile.h * tile.w);
+        let mut layers: Vec<Vec<Color>> = vec![Vec::with_capacity(tile.h * tile.w); self.aovs.len()];
+        let mut layer_pixels = vec![Color::zero(); self.aovs.len()];
+        let mut splats = Vec::new();
+        let mut splat = |(u, v): (f32, f32), color: Color| {
+            // the raster position, kept in the image
+            let x = (u * w as f32).clamp(0.0, w as f32 - 1e-3);
+            let y = (v * h as f32).clamp(0.0, h as f32 - 1e-3);
+            splats.push((x, y, color));
+        };
 
         for y in tile.y..(tile.y + tile.h) {
            for x in tile.x..(tile.x + tile.w) {
-               let mut pixel = Color::new(1.0, 1.0, 1.0);
-               let mut rng = XorShift32::new();
-
-               for _ in 0..sample_number {
-                   let u = ((x as f32) + rng.rand()) / w as f32;
-                   let v = ((y as f32) + rng.rand()) / h as f32;
-                   let ray = scene.camera.get_ray(u, v);
-                   pixel = pixel + Renderer::shade(&scene, &ray, 0, max_depth);
+               let mut luminance_sum = accumulation.luminance_sum(x, y);
+               let mut square = accumulation.square_sum(x, y);
+               let first_sample = accumulation.sample_count(x, y);
+               let sample_number = samples(x, y);
+               for (layer, layer_pixel) in layer_pixels.iter_mut().enumerate() {
+                   *layer_pixel = accumulation.layer_sum(layer, x, y);
                }
 
-               pixel = pixel / (sample_number as f32);
+               for s in 0..sample_number {
+                   sampler.start_pixel_sample(x, y, first_sample + s);
+                   let (jitter_x, jitter_y) = sampler.get_2d();
+                   let u = ((x as f32) + jitter_x) / w as f32;
+                   let v = ((y as f32) + jitter_y) / h as f32;
+                   // a jitter close to 1 can round to the next pixel
+                   let px = ((x as f32) + jitter_x).min(((x + 1) as f32).next_down());
+                   let py = ((y as f32) + jitter_y).min(((y + 1) as f32).next_down());
+                   let ray = self.scene.camera.get_ray(u, v, sampler.as_mut());
+                   if self.aovs.is_empty() {
+                       let l = self.integrator.li_with_splats(&self.scene, &ray, sampler.as_mut(), &mut splat);
+                       film.add_sample(px, py, l);
+                       luminance_sum += beat_y(l);
+                       square += beat_y(l) * beat_y(l);
+                       continue;
+                   }
+
+                   let (l, direct) = self.integrator.li_with_direct(&self.scene, &ray, sampler.as_mut(), &mut splat);
+                   film.add_sample(px, py, l);
+                   luminance_sum += beat_y(l);
+                   square += beat_y(l) * beat_y(l);
+                   let first_hit = self.scene.intersect(&ray, 0.001, f32::MAX);
+                   for (aov, layer_pixel) in self.aovs.iter().zip(layer_pixels.iter_mut()) {
+                       *layer_pixel = *layer_pixel + aov.value(&self.scene, &ray, first_hit.as_ref(), l, direct);
+                   }
+               }
 
-               result.push(pixel);
+               luminances.push(luminance_sum);
+               squares.push(square);
+               sample_numbers.push(sample_number);
+               for (layer, layer_pixel) in layers.iter_mut().zip(layer_pixels.iter()) {
+                   layer.push(*layer_pixel);
+               }
            }
        }
 
-       (tile, result)
-    }
-
-    // fn shade(scene: &Arc<Scene>, ray: &Ray, depth: usize, max_depth: usize) -> Color {
-    //     // ray tracing depth
-    //     if depth < max_depth {
-    //         if let Some((hit, obj_idx)) = scene.intersect(ray, 0.001, MAX) {
-    //             let obj_ref = &scene.objects[obj_idx];
-    //             // material
-    //             if obj_ref.material < scene.material.len() {
-    //                 let material = &scene.material[obj_ref.material];
-    //                 let scatter = material.scatter(ray, &hit);
-    //                 if beat_y(scatter.attenuation) < XorShift32::new().rand() {
-    //                     return Color::zero();
-    //                 }
-    //                 let mut l = Color::zero();
-    //                 // direct light
-    //                 for light in scene.lights.iter() {
-    //                     let nee_ray = light.get_ray(hit.position);
-    //                     if scene.intersect(&nee_ray, 0.001, MAX).is_none() {
-    //                         l = l + light.radiation(&nee_ray);
-    //                     }
-    //                 }
-    //                 return l + scatter.attenuation * Renderer::shade(scene, &scatter.scattered, depth + 1, max_depth);
-    //             }
-    //             else {
-    //                 return Color::zero();
-    //             }
-    //         } else {
-    //             let mut l = scene.skybox.ambient(ray);
-    //             // let mut l = Color::zero();
-    //             for light in scene.lights.iter() {
-    //                 l = l + light.radiation(ray);
-    //             }
-    //             return l;
-    //         }
-    //     }
-    //     else {
-    //         Color::zero()
-    //     }
-    // }
+       accumulation.add_splats(&splats);
+       TileSums { tile, film, luminances, squares, samples: sample_numbers, layers }
+    }
+}
 
-    fn shade(scene: &Arc<Scene>, ray: &Ray, depth: usize, max_depth: usize) -> Color {
-        let mut current_ray = *ray;
-        let mut a_l = Vec::with_capacity(max_depth);
-        for _ in depth..max_depth {
-            if let Some((hit, obj_idx)) = scene.intersect(&current_ray, 0.001, MAX) {
-                let obj_ref = &scene.objects[obj_idx];
-                // material
-                if obj_ref.material < scene.material.len() {
-                    let material = &scene.material[obj_ref.material];
-                    let scatter = material.scatter(&current_ray, &hit);
-                    if beat_y(scatter.attenuation) < XorShift32::new().rand() {
-                        return Color::zero();
-                    }
-                    current_ray = scatter.scattered;
-                    let mut l = material.emit(&current_ray);
-                    // direct light
-                    for light in scene.lights.iter() {
-                        let nee_ray = light.get_ray(hit.position);
-                        if scene.intersect(&nee_ray, 0.001, MAX).is_none() {
-                            l = l + light.radiation(&nee_ray);
-                        }
-                    }
-                    a_l.push((scatter.attenuation,l));
-                } else {
-                    return Color::zero();
-                }
-            }
-            else{
-                let mut l = scene.skybox.ambient(ray);
-                for light in scene.lights.iter() {
-                    l = l + light.radiation(ray);
-                }
-                a_l.reverse();
-                for (attenuation, li) in a_l.iter() {
-                    l = l * *attenuation + *li;
-                }
-                return l;
-            }
+// the running sums of a rendered tile, in the order of the pixels of the tile
+struct TileSums {
+    tile: Tile,
+    // the filtered samples of the tile and the pixels around it they reach
+    film: Film,
+    luminances: Vec<f32>,
+    squares: Vec<f32>,
+    // the samples every pixel took
+    samples: Vec<usize>,
+    // the sums of the aov layers
+    layers: Vec<Vec<Color>>,
+}
+
+impl TileSums {
+    // the pixels of the tile row by row, without the light of other tiles
+    fn pixels(&self) -> Vec<Color> {
+        let tile = &self.tile;
+        (tile.y..tile.y + tile.h).flat_map(|y| (tile.x..tile.x + tile.w).map(move |x| (x, y))).map(|(x, y)| {
+            let (sum, weight) = self.film.pixel(x, y);
+            if weight > 0.0 { sum / weight } else { Color::zero() }
+        }).collect()
+    }
+
+    // replace the sums of the pixels of the tile, `add_film_margin` adds those around it
+    fn set_tile(&self, accumulation: &mut Accumulation) {
+        accumulation.set_tile(&self.tile, &self.film, &self.samples);
+        accumulation.set_tile_luminances(&self.tile, &self.luminances, &self.squares);
+        for (layer, sums) in self.layers.iter().enumerate() {
+            accumulation.set_tile_layer(&self.tile, layer, sums);
         }
+    }
+}
+
+fn aov_names(aovs: &[Aov]) -> String {
+    aovs.iter().map(|aov| aov.name()).collect::<Vec<_>>().join(", ")
+}
 
-        return Color::zero();
+// run `f` on a pool of `thread_number` threads, 0 use the rayon global pool, an error if the pool can not be built
+pub(super) fn with_threads<R: Send>(thread_number: usize, f: impl FnOnce() -> R + Send) -> Result<R, String> {
+    if thread_number > 0 {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(thread_number).build()
+            .map_err(|e| format!("can not start {} render threads: {}", thread_number, e))?;
+        Ok(pool.install(f))
+    } else {
+        Ok(f())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use gk_math::base::f32::Vec3;
+
+    use super::*;
+    use crate::base::camera::Camera;
+    use crate::integrator::BdptIntegrator;
+    use crate::base::light::PointLight;
+    use crate::material::bsdf::{Lambertian, Metal};
+    use crate::object::{Plane, Sphere};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("solas_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // a diffuse and a metal sphere on a plane, lit by the sky and a point light
+    fn renderer() -> Renderer {
+        let mut scene = Scene::new();
+        scene.set_camera(Camera::new(Vec3::new(0.0, 1.0, 6.0), Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, 1.0, 0.0), 40.0, 2.0, 0.0, 6.0));
+        let lambertian = scene.add_material(Lambertian::new().with_albedo(Vec3::new(0.7, 0.3, 0.2)));
+        let metal = scene.add_material(Metal::new().with_albedo(Vec3::new(0.8, 0.8, 0.9)).with_fuzz(0.25));
+        scene.add_object(Sphere::new().with_center(Vec3::new(-0.6, 0.5, 0.0)).with_radius(0.5)).material = lambertian;
+        scene.add_object(Sphere::new().with_center(Vec3::new(0.6, 0.5, 0.0)).with_radius(0.5)).material = metal;
+        scene.add_object(Plane::new().with_center(Vec3::new(0.0, 0.0, 0.0)).with_normal(Vec3::new(0.0, 1.0, 0.0))
+            .with_width(10.0).with_height(10.0)).material = lambertian;
+        scene.add_light(PointLight::create(Vec3::new(0.0, 4.0, 2.0), Color::new(1.0, 0.9, 0.8), 40.0));
+        scene.build_bvh_tree();
+        Renderer::new(scene, Canvas::new(24, 12, 1.0, 2.0), 4).with_seed(11)
     }
 
-    fn write_tile(tile: &Tile, colors: &Vec<Color>, img_buf: &mut ImageData) {
-        for y in 0..tile.h {
-            for x in 0..tile.w {
-                img_buf[((x + tile.x), (tile.y + y))] = colors[y * tile.w + x];
+    fn assert_same_image(a: &ImageData, b: &ImageData, what: &str) {
+        assert_eq!((a.width(), a.height()), (b.width(), b.height()), "{}", what);
+        for y in 0..a.height() {
+            for x in 0..a.width() {
+                let (a, b) = (a[(x, y)], b[(x, y)]);
+                assert_eq!([a.r, a.g, a.b].map(f32::to_bits), [b.r, b.g, b.b].map(f32::to_bits), "{}: pixel ({}, {})", what, x, y);
             }
         }
     }
-}
 
-#[inline]
-fn beat_y(beta: Color) -> f32 {
-    0.2126 * beta.r+0.7152 * beta.g+0.0722 * beta.b
+    #[test]
+    fn the_box_filter_renders_the_same_image_on_any_threads_tiles_and_passes() {
+        for (name, renderer) in [("path", renderer()), ("bdpt", renderer().with_integrator(Box::new(BdptIntegrator::new(4))))] {
+            let image = renderer.render(16);
+            assert!((0..24 * 12).any(|i| image[(i % 24, i / 24)].g > 0.0), "{}: the render is black", name);
+            assert_same_image(&image, &renderer.multi_thread_render(4, 4, 1, 16).unwrap(), &format!("{} on 1 thread", name));
+            assert_same_image(&image, &renderer.multi_thread_render(16, 16, 4, 16).unwrap(), &format!("{} on 4 threads", name));
+            let passes = renderer.progressive_render(&PassOptions::new(16, 4).with_tile(5, 3).with_threads(3), &(), &CancelToken::new(), |_, _| true).unwrap();
+            assert_same_image(&image, &passes.estimate(), &format!("{} in 4 passes", name));
+        }
+    }
+
+    #[test]
+    fn a_resumed_checkpoint_renders_the_same_image() {
+        let dir = temp_dir("checkpoint");
+        let renderer = renderer().with_filter(Filter::from_name("gaussian").unwrap()).with_aovs(&[Aov::Normal]).with_crop(2, 1, 19, 9);
+        let options = PassOptions::new(9, 3).with_tile(8, 8).with_threads(2);
+
+        let whole = renderer.render_with_checkpoint(&options, dir.join("whole.ck"), &(), &CancelToken::new(), |_| true).unwrap();
+        let interrupted = renderer.render_with_checkpoint(&options, dir.join("resumed.ck"), &(), &CancelToken::new(), |_| false).unwrap();
+        assert_eq!(interrupted.min_sample_count(), 3);
+        let resumed = renderer.render_with_checkpoint(&options, dir.join("resumed.ck"), &(), &CancelToken::new(), |_| true).unwrap();
+
+        assert_eq!(resumed.min_sample_count(), 9);
+        let image = whole.estimate();
+        assert!((0..24 * 12).any(|i| image[(i % 24, i / 24)].g > 0.0), "the render is black");
+        assert_same_image(&image, &resumed.estimate(), "image");
+        let (whole, resumed) = (whole.layers(), resumed.layers());
+        for ((name, a), (_, b)) in whole.iter().zip(resumed.iter()) {
+            assert_same_image(a, b, name);
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_cancelled_checkpoint_render_resumes_the_pixels_left_out() {
+        let dir = temp_dir("checkpoint_cancel");
+        let path = dir.join("render.ck");
+        let renderer = renderer();
+        // 18 tiles in 4 passes of 2 samples
+        let options = PassOptions::new(8, 4).with_tile(4, 4).with_threads(2);
+
+        // cancel in the middle of the second pass
+        let cancel = CancelToken::new();
+        let total = AtomicUsize::new(0);
+        let observer = |finished: usize, total_tiles: usize| {
+            total.store(total_tiles, Ordering::Relaxed);
+            if finished == 18 + 5 {
+                cancel.cancel();
+            }
+        };
+        let mut passes = 0;
+        let cancelled = renderer.render_with_checkpoint(&options, &path, &observer, &cancel, |_| { passes += 1; true }).unwrap();
+        assert_eq!(total.load(Ordering::Relaxed), 18 * 4, "the progress counts every pass");
+        assert_eq!(passes, 2);
+        assert_eq!((cancelled.min_sample_count(), cancelled.max_sample_count()), (2, 4));
+
+        let resumed = renderer.render_with_checkpoint(&options, &path, &(), &CancelToken::new(), |_| true).unwrap();
+        assert_eq!((resumed.min_sample_count(), resumed.max_sample_count()), (8, 8));
+        assert_same_image(&renderer.multi_thread_render(4, 4, 2, 8).unwrap(), &resumed.estimate(), "resumed image");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_checkpoint_of_other_settings_is_rejected() {
+        let dir = temp_dir("checkpoint_mismatch");
+        let path = dir.join("render.ck");
+        let options = PassOptions::new(2, 2).with_tile(8, 8).with_threads(2);
+        renderer().render_with_checkpoint(&options, &path, &(), &CancelToken::new(), |_| false).unwrap();
+
+        let filter = renderer().with_filter(Filter::from_name("tent").unwrap()).render_with_checkpoint(&options, &path, &(), &CancelToken::new(), |_| true);
+        assert!(filter.err().is_some_and(|e| e.contains("filter")));
+        let crop = renderer().with_crop(0, 0, 8, 8).render_with_checkpoint(&options, &path, &(), &CancelToken::new(), |_| true);
+        assert!(crop.err().is_some_and(|e| e.contains("crop")));
+        let sampler = renderer().with_sampler(SamplerKind::Stratified(2)).render_with_checkpoint(&options, &path, &(), &CancelToken::new(), |_| true);
+        assert!(sampler.err().is_some_and(|e| e.contains("sampler")));
+        let integrator = renderer().with_integrator(Box::new(BdptIntegrator::new(4))).render_with_checkpoint(&options, &path, &(), &CancelToken::new(), |_| true);
+        assert!(integrator.err().is_some_and(|e| e.contains("integrator")));
+        let mut depth = renderer();
+        depth.depth = 5;
+        assert!(depth.render_with_checkpoint(&options, &path, &(), &CancelToken::new(), |_| true).err().is_some_and(|e| e.contains("depth")));
+        let samples = renderer().render_with_checkpoint(&PassOptions::new(4, 2).with_tile(8, 8), &path, &(), &CancelToken::new(), |_| true);
+        assert!(samples.err().is_some_and(|e| e.contains("samples per pixel")));
+        renderer().render_with_checkpoint(&options, &path, &(), &CancelToken::new(), |_| true).unwrap();
+
+        // a header that claims more pixels than the file has, the width follows the magic, the seed,
+        // the names `sobol` and `path` with their lengths, the strata, the depth and the samples
+        let width = 8 + 8 + 6 + 5 + 3 * 8;
+        let mut bytes = fs::read(&path).unwrap();
+        assert_eq!(bytes[width..width + 8], 24u64.to_le_bytes());
+        bytes[width..width + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+        assert!(Accumulation::load_checkpoint(&path).is_err());
+        bytes.truncate(bytes.len() - 1);
+        bytes[width..width + 8].copy_from_slice(&24u64.to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+        assert!(Accumulation::load_checkpoint(&path).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
 }
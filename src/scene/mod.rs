@@ -1,6 +1,8 @@
 pub mod scene;
 pub mod scene_load;
+pub mod scene_save;
 pub mod canvas;
 
 pub use scene::*;
 pub use canvas::*;
+pub use scene_load::SceneError;
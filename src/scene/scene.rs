@@ -4,6 +4,7 @@ use crate::material::background::*;
 use crate::base::object::{Object, ObjectBase};
 // use crate::base::bound::*;
 use crate::base::bvh::BVHTree;
+use super::canvas::Canvas;
 
 pub type MaterialARef = Box<dyn Material + Sync + Send>;
 
@@ -14,6 +15,7 @@ pub struct Scene {
     pub skybox: Box<dyn SkyBox + Sync + Send>,
     pub lights: Vec<Box<dyn Light + Sync + Send>>,
     pub camera: Camera,
+    pub canvas: Option<Canvas>, // the canvas declared by a scene file
 }
 
 impl Scene {
@@ -26,6 +28,7 @@ impl Scene {
             skybox: Box::new(Background::new()),
             lights: Vec::new(),
             camera: Camera::default(),
+            canvas: None,
         }
     }
 
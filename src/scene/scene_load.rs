@@ -1,9 +1,661 @@
-use std::path::Path;
-
-use super::scene::*;
-
-impl Scene {
-    pub fn load(_path: impl AsRef<Path>) -> Result<Scene, ()>{
-        todo!()
-    }
-}
+//! Scene description file
+//!
+//! A scene file is a plain text file made of sections. A section starts with
+//! a `[kind]` or `[kind name]` header and is followed by `key = value` fields,
+//! one per line. `#` starts a comment that runs to the end of the line.
+//! Vectors and colors are three numbers separated by spaces or commas.
+//!
+//! ```text
+//! [canvas]
+//! width = 800
+//! height = 400
+//!
+//! [camera]
+//! look_from = 10 10 3
+//! look_at = 0 0 0
+//! vup = 0 0 1
+//! vfov = 20
+//! aperture = 0.1
+//!
+//! [material gold]
+//! type = metal
+//! albedo = 0.8 0.6 0.2
+//! fuzz = 0.02
+//!
+//! [sphere]
+//! material = gold
+//! center = 0 0 0
+//! radius = 0.5
+//! ```
+//!
+//! Sections:
+//! - `[canvas]`: `width`, `height`, optional `vertical` (1.0) and `horizontal` (2.0)
+//! - `[camera]`: `look_from`, `look_at`, optional `vup` (0 1 0), `vfov` (90),
+//!   `aspect` (canvas width / height), `aperture` (0) and `focus_dist` (|look_from - look_at|)
+//! - `[skybox]`: `type = background`
+//! - `[material name]`: `type` is one of
+//!   `lambertian` (`albedo`), `metal` (`albedo`, `fuzz`) or `dielectric` (`albedo`, `refract_coe`)
+//! - `[sphere]`: `material`, `center`, `radius`
+//! - `[plane]`: `material`, `center`, `normal`, `width`, `height`
+//! - `[cube]`: `material`, `length`, optional `scale` and `move`
+//! - `[obj]`: `material`, `path` relative to the scene file, optional `scale` and `move`
+//! - `[point_light]`: `origin`, `color`, `brightness`
+//!
+//! Sizes, `radius`, `refract_coe`, `scale`, `aspect` and `focus_dist` should be greater than 0, `vfov` between 0 and 180,
+//! `aperture` and `brightness` 0 or greater and `fuzz` between 0 and 1, other values are an error of the field.
+//! Every number should be finite. `look_at` should differ from `look_from`, `vup` should not be
+//! parallel to the view direction and the plane `normal` should not be zero.
+//!
+//! Materials are referenced by name and may be declared anywhere in the file.
+//! Objects may carry an optional name in their header, it is only for the reader.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use gk_math::base::f32::Vec3;
+
+use super::scene::*;
+use super::canvas::Canvas;
+use crate::base::camera::Camera;
+use crate::base::light::PointLight;
+use crate::base::object::ObjectTransfrom;
+use crate::material::background::Background;
+use crate::material::bsdf::*;
+use crate::object::{Mesh, Plane, Sphere};
+
+/// Error of a scene file, points at the file, line and field
+#[derive(Debug)]
+pub struct SceneError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl SceneError {
+    pub(crate) fn new(path: &Path, line: Option<usize>, field: Option<&str>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            line,
+            field: field.map(|field| field.to_string()),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        if let Some(field) = &self.field {
+            write!(f, ": field `{}`", field)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl Error for SceneError {}
+
+struct Field {
+    key: String,
+    value: String,
+    line: usize,
+}
+
+struct Section {
+    kind: String,
+    name: Option<String>,
+    line: usize,
+    fields: Vec<Field>,
+}
+
+/// the parsed sections of a file, keep the path for error message
+struct SceneFile<'a> {
+    path: &'a Path,
+    sections: Vec<Section>,
+}
+
+impl<'a> SceneFile<'a> {
+    fn parse(path: &'a Path, text: &str) -> Result<Self, SceneError> {
+        let mut sections: Vec<Section> = Vec::new();
+
+        for (line_num, line) in text.lines().enumerate() {
+            let line_num = line_num + 1;
+            let line = match line.find('#') {
+                Some(idx) => &line[..idx],
+                None => line,
+            }.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                if !line.ends_with(']') {
+                    return Err(SceneError::new(path, Some(line_num), None, "unclosed section header"));
+                }
+                let mut parts = line[1..line.len() - 1].split_whitespace();
+                let kind = match parts.next() {
+                    Some(kind) => kind.to_string(),
+                    None => return Err(SceneError::new(path, Some(line_num), None, "empty section header")),
+                };
+                let name = parts.next().map(|name| name.to_string());
+                if parts.next().is_some() {
+                    return Err(SceneError::new(path, Some(line_num), None, "section header takes a kind and an optional name"));
+                }
+                sections.push(Section { kind, name, line: line_num, fields: Vec::new() });
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(SceneError::new(path, Some(line_num), None, "expect `key = value`")),
+            };
+
+            let section = match sections.last_mut() {
+                Some(section) => section,
+                None => return Err(SceneError::new(path, Some(line_num), Some(key), "field outside of a section")),
+            };
+
+            if section.fields.iter().any(|field| field.key == key) {
+                return Err(SceneError::new(path, Some(line_num), Some(key), "duplicate field"));
+            }
+
+            section.fields.push(Field { key: key.to_string(), value: value.to_string(), line: line_num });
+        }
+
+        Ok(Self { path, sections })
+    }
+
+    fn error(&self, line: usize, field: Option<&str>, message: impl Into<String>) -> SceneError {
+        SceneError::new(self.path, Some(line), field, message)
+    }
+
+    /// reject the fields that the section kind does not know
+    fn check_fields(&self, section: &Section, keys: &[&str]) -> Result<(), SceneError> {
+        for field in section.fields.iter() {
+            if !keys.contains(&field.key.as_str()) {
+                return Err(self.error(field.line, Some(&field.key), format!("unknown field of [{}]", section.kind)));
+            }
+        }
+        Ok(())
+    }
+
+    fn field<'s>(&self, section: &'s Section, key: &str) -> Result<&'s Field, SceneError> {
+        section.fields.iter()
+            .find(|field| field.key == key)
+            .ok_or_else(|| self.error(section.line, Some(key), format!("missing field of [{}]", section.kind)))
+    }
+
+    fn optional<'s>(&self, section: &'s Section, key: &str) -> Option<&'s Field> {
+        section.fields.iter().find(|field| field.key == key)
+    }
+
+    fn float(&self, field: &Field) -> Result<f32, SceneError> {
+        self.number(field, &field.value)
+    }
+
+    /// a finite number of the field, `inf` and `NaN` parse but are no valid value
+    fn number(&self, field: &Field, text: &str) -> Result<f32, SceneError> {
+        let value = text.parse::<f32>()
+            .map_err(|e| self.error(field.line, Some(&field.key), format!("invalid number: {}", e)))?;
+        if value.is_finite() {
+            Ok(value)
+        } else {
+            Err(self.error(field.line, Some(&field.key), format!("{} should be a finite number", value)))
+        }
+    }
+
+    /// a float that `valid` accepts, `expect` tells what a valid value is
+    fn float_where(&self, field: &Field, valid: impl Fn(f32) -> bool, expect: &str) -> Result<f32, SceneError> {
+        let value = self.float(field)?;
+        if value.is_finite() && valid(value) {
+            Ok(value)
+        } else {
+            Err(self.error(field.line, Some(&field.key), format!("{} should be {}", value, expect)))
+        }
+    }
+
+    fn positive(&self, field: &Field) -> Result<f32, SceneError> {
+        self.float_where(field, |v| v > 0.0, "greater than 0")
+    }
+
+    fn usize(&self, field: &Field) -> Result<usize, SceneError> {
+        field.value.parse::<usize>()
+            .map_err(|e| self.error(field.line, Some(&field.key), format!("invalid integer: {}", e)))
+    }
+
+    fn vec3(&self, field: &Field) -> Result<Vec3, SceneError> {
+        let parts: Vec<&str> = field.value
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|part| !part.is_empty())
+            .collect();
+
+        if parts.len() != 3 {
+            return Err(self.error(field.line, Some(&field.key), "expect three numbers"));
+        }
+
+        let mut v = [0f32; 3];
+        for (i, part) in parts.iter().enumerate() {
+            v[i] = self.number(field, part)?;
+        }
+
+        Ok(v.into())
+    }
+
+    fn required_positive(&self, section: &Section, key: &str) -> Result<f32, SceneError> {
+        self.positive(self.field(section, key)?)
+    }
+
+    fn optional_positive(&self, section: &Section, key: &str) -> Result<Option<f32>, SceneError> {
+        self.optional(section, key).map(|field| self.positive(field)).transpose()
+    }
+
+    fn required_vec3(&self, section: &Section, key: &str) -> Result<Vec3, SceneError> {
+        self.vec3(self.field(section, key)?)
+    }
+
+    /// a direction, the zero vector has none
+    fn required_direction(&self, section: &Section, key: &str) -> Result<Vec3, SceneError> {
+        let field = self.field(section, key)?;
+        let v = self.vec3(field)?;
+        if v.length_squared() > 0.0 {
+            Ok(v)
+        } else {
+            Err(self.error(field.line, Some(key), "should not be the zero vector"))
+        }
+    }
+
+    fn optional_float(&self, section: &Section, key: &str) -> Result<Option<f32>, SceneError> {
+        self.optional(section, key).map(|field| self.float(field)).transpose()
+    }
+
+    fn optional_vec3(&self, section: &Section, key: &str) -> Result<Option<Vec3>, SceneError> {
+        self.optional(section, key).map(|field| self.vec3(field)).transpose()
+    }
+
+    fn build(&self) -> Result<Scene, SceneError> {
+        let mut scene = Scene::new();
+
+        // materials first, objects may refer to a material declared after them
+        let mut material_names: Vec<(String, usize)> = Vec::new();
+        for section in self.sections.iter().filter(|section| section.kind == "material") {
+            let name = match &section.name {
+                Some(name) => name.clone(),
+                None => return Err(self.error(section.line, None, "material section needs a name, like [material gold]")),
+            };
+            if material_names.iter().any(|(n, _)| *n == name) {
+                return Err(self.error(section.line, None, format!("material `{}` is declared twice", name)));
+            }
+            let idx = self.build_material(section, &mut scene)?;
+            material_names.push((name, idx));
+        }
+
+        for section in self.sections.iter() {
+            match section.kind.as_str() {
+                "material" => {},
+                "canvas" => {
+                    if scene.canvas.is_some() {
+                        return Err(self.error(section.line, None, "canvas is declared twice"));
+                    }
+                    self.check_fields(section, &["width", "height", "vertical", "horizontal"])?;
+                    let width = self.usize(self.field(section, "width")?)?;
+                    let height = self.usize(self.field(section, "height")?)?;
+                    for (key, size) in [("width", width), ("height", height)] {
+                        if size == 0 {
+                            return Err(self.error(self.field(section, key)?.line, Some(key), "0 should be greater than 0"));
+                        }
+                    }
+                    let vertical = self.optional_float(section, "vertical")?.unwrap_or(1.0);
+                    let horizontal = self.optional_float(section, "horizontal")?.unwrap_or(2.0);
+                    scene.canvas = Some(Canvas::new(width, height, vertical, horizontal));
+                },
+                "camera" | "skybox" => {},
+                "sphere" | "plane" | "cube" | "obj" => {
+                    let material = self.field(section, "material")?;
+                    let material_idx = match material_names.iter().find(|(name, _)| *name == material.value) {
+                        Some((_, idx)) => *idx,
+                        None => return Err(self.error(material.line, Some("material"), format!("unknown material `{}`", material.value))),
+                    };
+                    let object = self.build_object(section, &mut scene)?;
+                    object.material = material_idx;
+                },
+                "point_light" => {
+                    self.check_fields(section, &["origin", "color", "brightness"])?;
+                    let origin = self.required_vec3(section, "origin")?;
+                    let color = self.required_vec3(section, "color")?;
+                    let brightness = self.float_where(self.field(section, "brightness")?, |v| v >= 0.0, "0 or greater")?;
+                    scene.add_light(PointLight::create(origin, color.into(), brightness));
+                },
+                kind => return Err(self.error(section.line, None, format!("unknown section kind `{}`", kind))),
+            }
+        }
+
+        // camera last, the aspect ratio may come from the canvas
+        let mut cameras = self.sections.iter().filter(|section| section.kind == "camera");
+        if let Some(section) = cameras.next() {
+            if let Some(section) = cameras.next() {
+                return Err(self.error(section.line, None, "camera is declared twice"));
+            }
+            let camera = self.build_camera(section, scene.canvas.as_ref())?;
+            scene.set_camera(camera);
+        }
+
+        let mut skyboxes = self.sections.iter().filter(|section| section.kind == "skybox");
+        if let Some(section) = skyboxes.next() {
+            if let Some(section) = skyboxes.next() {
+                return Err(self.error(section.line, None, "skybox is declared twice"));
+            }
+            self.check_fields(section, &["type"])?;
+            let ty = self.field(section, "type")?;
+            match ty.value.as_str() {
+                "background" => scene.skybox = Box::new(Background::new()),
+                other => return Err(self.error(ty.line, Some("type"), format!("unknown skybox type `{}`", other))),
+            }
+        }
+
+        if !scene.objects.is_empty() {
+            scene.build_bvh_tree();
+        }
+
+        Ok(scene)
+    }
+
+    fn build_material(&self, section: &Section, scene: &mut Scene) -> Result<usize, SceneError> {
+        let ty = self.field(section, "type")?;
+        let idx = match ty.value.as_str() {
+            "lambertian" => {
+                self.check_fields(section, &["type", "albedo"])?;
+                let mut material = Lambertian::new();
+                if let Some(albedo) = self.optional_vec3(section, "albedo")? {
+                    material = material.with_albedo(albedo);
+                }
+                scene.add_material(material)
+            },
+            "metal" => {
+                self.check_fields(section, &["type", "albedo", "fuzz"])?;
+                let mut material = Metal::new();
+                if let Some(albedo) = self.optional_vec3(section, "albedo")? {
+                    material = material.with_albedo(albedo);
+                }
+                if let Some(fuzz) = self.optional(section, "fuzz") {
+                    material = material.with_fuzz(self.float_where(fuzz, |v| (0.0..=1.0).contains(&v), "between 0 and 1")?);
+                }
+                scene.add_material(material)
+            },
+            "dielectric" => {
+                self.check_fields(section, &["type", "albedo", "refract_coe"])?;
+                let mut material = Dielectric::new().with_refract_coe(self.required_positive(section, "refract_coe")?);
+                if let Some(albedo) = self.optional_vec3(section, "albedo")? {
+                    material = material.with_albedo(albedo);
+                }
+                scene.add_material(material)
+            },
+            other => return Err(self.error(ty.line, Some("type"), format!("unknown material type `{}`", other))),
+        };
+
+        Ok(idx)
+    }
+
+    fn build_object<'s>(&self, section: &Section, scene: &'s mut Scene) -> Result<&'s mut crate::base::object::Object, SceneError> {
+        let object = match section.kind.as_str() {
+            "sphere" => {
+                self.check_fields(section, &["material", "center", "radius"])?;
+                scene.add_object(
+                    Sphere::new()
+                        .with_center(self.required_vec3(section, "center")?)
+                        .with_radius(self.required_positive(section, "radius")?))
+            },
+            "plane" => {
+                self.check_fields(section, &["material", "center", "normal", "width", "height"])?;
+                scene.add_object(
+                    Plane::new()
+                        .with_center(self.required_vec3(section, "center")?)
+                        .with_normal(self.required_direction(section, "normal")?)
+                        .with_width(self.required_positive(section, "width")?)
+                        .with_height(self.required_positive(section, "height")?))
+            },
+            "cube" => {
+                self.check_fields(section, &["material", "length", "scale", "move"])?;
+                let mut mesh = Mesh::cube(self.required_positive(section, "length")?);
+                self.transform_mesh(section, &mut mesh)?;
+                scene.add_object(mesh)
+            },
+            _ => {
+                self.check_fields(section, &["material", "path", "scale", "move"])?;
+                let path = self.field(section, "path")?;
+                let obj_path = match self.path.parent() {
+                    Some(dir) => dir.join(&path.value),
+                    None => PathBuf::from(&path.value),
+                };
+                let mut mesh = Mesh::load_obj(&obj_path)
+                    .map_err(|e| self.error(path.line, Some("path"), format!("{}: {}", obj_path.display(), e)))?;
+                self.transform_mesh(section, &mut mesh)?;
+                scene.add_object(mesh)
+            },
+        };
+
+        Ok(object)
+    }
+
+    fn transform_mesh(&self, section: &Section, mesh: &mut Mesh) -> Result<(), SceneError> {
+        if let Some(s) = self.optional_positive(section, "scale")? {
+            mesh.scale(s);
+        }
+        if let Some(dir) = self.optional_vec3(section, "move")? {
+            mesh.moved(dir);
+        }
+        // the mesh bvh is built on the loaded vertices, rebuild it after transform
+        if mesh.bvh_opt.is_some() {
+            mesh.build_bvh();
+        }
+        Ok(())
+    }
+
+    fn build_camera(&self, section: &Section, canvas: Option<&Canvas>) -> Result<Camera, SceneError> {
+        self.check_fields(section, &["look_from", "look_at", "vup", "vfov", "aspect", "aperture", "focus_dist"])?;
+        let look_from = self.required_vec3(section, "look_from")?;
+        let look_at = self.required_vec3(section, "look_at")?;
+        let w = look_from - look_at;
+        if w.length_squared() == 0.0 {
+            return Err(self.error(self.field(section, "look_at")?.line, Some("look_at"), "should differ from look_from"));
+        }
+        let vup = self.optional_vec3(section, "vup")?.unwrap_or(Vec3::new(0.0, 1.0, 0.0));
+        // the camera takes its right vector from vup x w, a parallel vup leaves none
+        if Vec3::cross(&vup, &w).length_squared() <= 1e-12 * vup.length_squared() * w.length_squared() {
+            let line = self.optional(section, "vup").map_or(section.line, |field| field.line);
+            return Err(self.error(line, Some("vup"), "should not be zero or parallel to look_at - look_from"));
+        }
+        let vfov = self.optional(section, "vfov")
+            .map(|field| self.float_where(field, |v| v > 0.0 && v < 180.0, "between 0 and 180 degrees")).transpose()?
+            .unwrap_or(90.0);
+        let aspect = match (self.optional_positive(section, "aspect")?, canvas) {
+            (Some(aspect), _) => aspect,
+            (None, Some(canvas)) => canvas.width as f32 / canvas.height as f32,
+            (None, None) => return Err(self.error(section.line, Some("aspect"), "missing field of [camera], or declare a [canvas]")),
+        };
+        let aperture = self.optional(section, "aperture")
+            .map(|field| self.float_where(field, |v| v >= 0.0, "0 or greater")).transpose()?
+            .unwrap_or(0.0);
+        let focus_dist = match self.optional_positive(section, "focus_dist")? {
+            Some(focus_dist) => focus_dist,
+            None => w.length(),
+        };
+
+        Ok(Camera::new(look_from, look_at, vup, vfov, aspect, aperture, focus_dist))
+    }
+}
+
+impl Scene {
+    /// Load a scene from a scene description file, see the module document for the format
+    pub fn load(path: impl AsRef<Path>) -> Result<Scene, SceneError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| SceneError::new(path, None, None, e.to_string()))?;
+        SceneFile::parse(path, &text)?.build()
+    }
+
+    /// Parse a scene from text, `path` is used for error messages and relative obj paths
+    pub fn parse(path: impl AsRef<Path>, text: &str) -> Result<Scene, SceneError> {
+        SceneFile::parse(path.as_ref(), text)?.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = "\
+[canvas]
+width = 16
+height = 8
+
+[camera]
+look_from = 0 1 6
+look_at = 0 0.5 0   # a comment
+vup = 0 1 0
+vfov = 40
+
+[material red]
+type = lambertian
+albedo = 0.7, 0.3, 0.2
+
+[sphere]
+material = red
+center = 0 0.5 0
+radius = 0.5
+
+[plane floor]
+material = red
+center = 0 0 0
+normal = 0 1 0
+width = 10
+height = 10
+
+[point_light]
+origin = 0 4 2
+color = 1 1 1
+brightness = 40
+";
+
+    fn parse(text: &str) -> Result<Scene, SceneError> {
+        Scene::parse("test.scene", text)
+    }
+
+    /// the error of `text` with `line` replaced by `with`
+    fn error_with(line: &str, with: &str) -> SceneError {
+        assert!(VALID.contains(line), "`{}` is not in the scene", line);
+        match parse(&VALID.replacen(line, with, 1)) {
+            Ok(_) => panic!("`{}` is accepted", with),
+            Err(e) => e,
+        }
+    }
+
+    fn assert_error(e: &SceneError, line: Option<usize>, field: Option<&str>) {
+        assert_eq!(e.path, Path::new("test.scene"));
+        assert_eq!(e.line, line, "{}", e);
+        assert_eq!(e.field.as_deref(), field, "{}", e);
+    }
+
+    #[test]
+    fn a_valid_file_is_parsed() {
+        let scene = match parse(VALID) {
+            Ok(scene) => scene,
+            Err(e) => panic!("{}", e),
+        };
+        let canvas = scene.canvas.unwrap();
+        assert_eq!((canvas.width, canvas.height), (16, 8));
+        assert_eq!(scene.camera.aspect.to_bits(), 2f32.to_bits());
+        assert_eq!(scene.camera.vfov.to_bits(), 40f32.to_bits());
+        assert!(scene.camera.has_parameters());
+        assert_eq!(scene.material.len(), 1);
+        assert_eq!(scene.objects.len(), 2);
+        assert!(scene.objects.iter().all(|object| object.material == 0));
+        assert_eq!(scene.lights.len(), 1);
+    }
+
+    #[test]
+    fn the_scene_of_the_repository_is_loaded() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/spheres.scene");
+        if let Err(e) = Scene::load(&path) {
+            panic!("{}", e);
+        }
+    }
+
+    #[test]
+    fn a_missing_file_is_an_error_of_the_path() {
+        let path = std::env::temp_dir().join(format!("solas_missing_{}.scene", std::process::id()));
+        match Scene::load(&path) {
+            Ok(_) => panic!("a missing file is loaded"),
+            Err(e) => assert_eq!((e.path, e.line, e.field), (path, None, None)),
+        }
+    }
+
+    #[test]
+    fn syntax_errors_point_at_the_line() {
+        assert_error(&error_with("[canvas]", "[canvas"), Some(1), None);
+        assert_error(&error_with("[canvas]", "[]"), Some(1), None);
+        assert_error(&error_with("[canvas]", "[canvas a b]"), Some(1), None);
+        assert_error(&error_with("width = 16", "width 16"), Some(2), None);
+        assert_error(&error_with("height = 8", "width = 8"), Some(3), Some("width"));
+        match parse("width = 16\n") {
+            Ok(_) => panic!("a field outside of a section is accepted"),
+            Err(e) => assert_error(&e, Some(1), Some("width")),
+        }
+    }
+
+    #[test]
+    fn unknown_names_are_errors() {
+        assert_error(&error_with("[point_light]", "[spot_light]"), Some(27), None);
+        assert_error(&error_with("vfov = 40", "fov = 40"), Some(9), Some("fov"));
+        assert_error(&error_with("type = lambertian", "type = glossy"), Some(12), Some("type"));
+        assert_error(&error_with("material = red\ncenter = 0 0.5 0", "material = blue\ncenter = 0 0.5 0"), Some(16), Some("material"));
+    }
+
+    #[test]
+    fn a_missing_field_points_at_the_section() {
+        assert_error(&error_with("radius = 0.5\n", ""), Some(15), Some("radius"));
+        assert_error(&error_with("look_at = 0 0.5 0   # a comment\n", ""), Some(5), Some("look_at"));
+    }
+
+    #[test]
+    fn invalid_numbers_are_errors_of_the_field() {
+        assert_error(&error_with("width = 16", "width = sixteen"), Some(2), Some("width"));
+        assert_error(&error_with("vfov = 40", "vfov = 40x"), Some(9), Some("vfov"));
+        assert_error(&error_with("origin = 0 4 2", "origin = 0 4"), Some(28), Some("origin"));
+        assert_error(&error_with("origin = 0 4 2", "origin = 0 four 2"), Some(28), Some("origin"));
+    }
+
+    #[test]
+    fn values_out_of_range_are_errors_of_the_field() {
+        assert_error(&error_with("width = 16", "width = 0"), Some(2), Some("width"));
+        assert_error(&error_with("radius = 0.5", "radius = -0.5"), Some(18), Some("radius"));
+        assert_error(&error_with("vfov = 40", "vfov = 180"), Some(9), Some("vfov"));
+        assert_error(&error_with("brightness = 40", "brightness = -1"), Some(30), Some("brightness"));
+        assert_error(&error_with("width = 10", "width = 0"), Some(24), Some("width"));
+    }
+
+    #[test]
+    fn non_finite_values_are_errors_of_the_field() {
+        assert_error(&error_with("radius = 0.5", "radius = inf"), Some(18), Some("radius"));
+        assert_error(&error_with("vfov = 40", "vfov = NaN"), Some(9), Some("vfov"));
+        assert_error(&error_with("center = 0 0.5 0", "center = 0 NaN 0"), Some(17), Some("center"));
+        assert_error(&error_with("look_from = 0 1 6", "look_from = 0 1 -inf"), Some(6), Some("look_from"));
+        assert_error(&error_with("height = 8\n", "height = 8\nvertical = inf\n"), Some(4), Some("vertical"));
+    }
+
+    #[test]
+    fn degenerate_directions_are_errors_of_the_field() {
+        assert_error(&error_with("look_at = 0 0.5 0", "look_at = 0 1 6"), Some(7), Some("look_at"));
+        assert_error(&error_with("vup = 0 1 0", "vup = 0 -0.5 -6"), Some(8), Some("vup"));
+        assert_error(&error_with("vup = 0 1 0", "vup = 0 0 0"), Some(8), Some("vup"));
+        // the default vup is parallel to a camera that looks straight down
+        let down = "look_from = 0 5 0\nlook_at = 0 0 0\n";
+        assert_error(&error_with("look_from = 0 1 6\nlook_at = 0 0.5 0   # a comment\nvup = 0 1 0\n", down), Some(5), Some("vup"));
+        assert_error(&error_with("normal = 0 1 0", "normal = 0 0 0"), Some(23), Some("normal"));
+    }
+}
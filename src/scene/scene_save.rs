@@ -0,0 +1,216 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use super::scene::*;
+use super::scene_load::SceneError;
+use crate::base::parameter::*;
+
+impl Scene {
+    /// Write the scene to a scene description file that `Scene::load` reads back.
+    /// - materials are named by their index, so `Object::material` keeps pointing at the same material
+    /// - meshes are written as obj files next to the scene file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SceneError> {
+        let path = path.as_ref();
+        let io_error = |path: &Path, e: std::io::Error| SceneError::new(path, None, None, e.to_string());
+
+        if let Some(parent) = path.parent() && !parent.as_os_str().is_empty() && !parent.exists() {
+            fs::create_dir_all(parent).map_err(|e| io_error(parent, e))?;
+        }
+
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_else(|| "scene".to_string());
+        let mut text = String::from("# Solas scene file\n");
+
+        if let Some(canvas) = &self.canvas {
+            let _ = write!(text, "\n[canvas]\nwidth = {}\nheight = {}\nvertical = {}\nhorizontal = {}\n",
+                canvas.width, canvas.height, canvas.vertical, canvas.horizontal);
+        }
+
+        // a scene without camera loads the default camera again
+        let camera = &self.camera;
+        if camera.has_parameters() {
+            let _ = write!(text, "\n[camera]\n");
+            write_field(&mut text, "look_from", &Parameter::Vector(camera.origin));
+            write_field(&mut text, "look_at", &Parameter::Vector(camera.look_at));
+            write_field(&mut text, "vup", &Parameter::Vector(camera.vup));
+            write_field(&mut text, "vfov", &Parameter::Float(camera.vfov));
+            write_field(&mut text, "aspect", &Parameter::Float(camera.aspect));
+            write_field(&mut text, "aperture", &Parameter::Float(camera.lens_radius * 2.0));
+            write_field(&mut text, "focus_dist", &Parameter::Float(camera.focus_dist));
+        }
+
+        let _ = write!(text, "\n[skybox]\ntype = {}\n", self.skybox.kind());
+        for (key, value) in self.skybox.parameters().iter() {
+            write_field(&mut text, key, value);
+        }
+
+        for (idx, material) in self.material.iter().enumerate() {
+            let _ = write!(text, "\n[material material{}]\ntype = {}\n", idx, material.kind());
+            for (key, value) in material.parameters().iter() {
+                write_field(&mut text, key, value);
+            }
+        }
+
+        for object in self.objects.iter() {
+            if object.material >= self.material.len() {
+                return Err(SceneError::new(path, None, None,
+                    format!("object {} refers to material {} that does not exist", object.idx, object.material)));
+            }
+
+            let _ = write!(text, "\n[{}]\nmaterial = material{}\n", object.base.kind(), object.material);
+            for (key, value) in object.base.parameters().iter() {
+                if let Parameter::Obj(obj) = value {
+                    let file_name = format!("{}_{}.obj", stem, object.idx);
+                    let obj_path = path.with_file_name(&file_name);
+                    fs::write(&obj_path, obj).map_err(|e| io_error(&obj_path, e))?;
+                    let _ = writeln!(text, "{} = {}", key, file_name);
+                } else {
+                    write_field(&mut text, key, value);
+                }
+            }
+        }
+
+        for light in self.lights.iter() {
+            let _ = write!(text, "\n[{}]\n", light.kind());
+            for (key, value) in light.parameters().iter() {
+                write_field(&mut text, key, value);
+            }
+        }
+
+        fs::write(path, text).map_err(|e| io_error(path, e))
+    }
+}
+
+fn write_field(text: &mut String, key: &str, value: &Parameter) {
+    let _ = match value {
+        Parameter::Float(x) => writeln!(text, "{} = {}", key, x),
+        Parameter::Vector(v) => writeln!(text, "{} = {} {} {}", key, v.x, v.y, v.z),
+        Parameter::Color(c) => writeln!(text, "{} = {} {} {}", key, c.r, c.g, c.b),
+        Parameter::Obj(_) => Ok(()),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use gk_math::base::f32::Vec3;
+    use gk_math::color::RGB as Color;
+
+    use super::*;
+    use crate::base::camera::Camera;
+    use crate::base::light::PointLight;
+    use crate::material::bsdf::{Dielectric, Lambertian, Metal};
+    use crate::object::{Mesh, Plane, Sphere};
+    use crate::renderer::Renderer;
+    use crate::scene::Canvas;
+
+    // a tetrahedron with texture coordinates and normals
+    const OBJ: &str = "\
+v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\n\
+vt 0 0\nvt 1 0\nvt 0 1\n\
+vn 0 0 -1\nvn 0 -1 0\nvn -1 0 0\nvn 0.577 0.577 0.577\n\
+f 1/1/1 3/3/1 2/2/1\nf 1/1/2 2/2/2 4/3/2\nf 1/1/3 4/3/3 3/2/3\nf 2/1/4 3/2/4 4/3/4\n";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("solas_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn scene(dir: &Path) -> Scene {
+        let mut scene = Scene::new();
+        scene.canvas = Some(Canvas::new(16, 8, 1.0, 2.0));
+        scene.set_camera(Camera::new(Vec3::new(0.0, 1.0, 6.0), Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, 1.0, 0.0), 40.0, 2.0, 0.1, 6.0));
+
+        let lambertian = scene.add_material(Lambertian::new().with_albedo(Vec3::new(0.7, 0.3, 0.2)));
+        let metal = scene.add_material(Metal::new().with_albedo(Vec3::new(0.8, 0.8, 0.9)).with_fuzz(0.25));
+        let dielectric = scene.add_material(Dielectric::new().with_refract_coe(1.5));
+
+        scene.add_object(Sphere::new().with_center(Vec3::new(-1.0, 0.5, 0.0)).with_radius(0.5)).material = dielectric;
+        scene.add_object(Plane::new().with_center(Vec3::new(0.0, 0.0, 0.0)).with_normal(Vec3::new(0.0, 1.0, 0.0))
+            .with_width(10.0).with_height(10.0)).material = lambertian;
+        let obj_path = dir.join("tetrahedron.obj");
+        fs::write(&obj_path, OBJ).unwrap();
+        scene.add_object(Mesh::load_obj(&obj_path).unwrap()).material = metal;
+        scene.add_light(PointLight::create(Vec3::new(0.0, 4.0, 2.0), Color::new(1.0, 0.9, 0.8), 40.0));
+        scene.build_bvh_tree();
+        scene
+    }
+
+    fn same(a: &Parameter, b: &Parameter) -> bool {
+        match (a, b) {
+            (Parameter::Float(a), Parameter::Float(b)) => a.to_bits() == b.to_bits(),
+            (Parameter::Vector(a), Parameter::Vector(b)) => [a.x, a.y, a.z].map(f32::to_bits) == [b.x, b.y, b.z].map(f32::to_bits),
+            (Parameter::Color(a), Parameter::Color(b)) => [a.r, a.g, a.b].map(f32::to_bits) == [b.r, b.g, b.b].map(f32::to_bits),
+            (Parameter::Obj(a), Parameter::Obj(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    fn assert_same_parameters(what: &str, a: &dyn Parameters, b: &dyn Parameters) {
+        assert_eq!(a.kind(), b.kind(), "{}", what);
+        let (a, b) = (a.parameters(), b.parameters());
+        assert_eq!(a.len(), b.len(), "{}", what);
+        for ((key_a, a), (key_b, b)) in a.iter().zip(b.iter()) {
+            assert_eq!(key_a, key_b, "{}", what);
+            assert!(same(a, b), "{}: field `{}` changed", what, key_a);
+        }
+    }
+
+    #[test]
+    fn save_and_load_keep_the_scene() {
+        let dir = temp_dir("save");
+        let original = scene(&dir);
+        let path = dir.join("scene.scene");
+        original.save(&path).unwrap();
+        let loaded = Scene::load(&path).unwrap();
+
+        assert_eq!(original.material.len(), loaded.material.len());
+        for (idx, (a, b)) in original.material.iter().zip(loaded.material.iter()).enumerate() {
+            assert_same_parameters(&format!("material {}", idx), a.as_ref(), b.as_ref());
+        }
+        assert_eq!(original.objects.len(), loaded.objects.len());
+        for (a, b) in original.objects.iter().zip(loaded.objects.iter()) {
+            assert_eq!(a.material, b.material);
+            assert_same_parameters(&format!("object {}", a.idx), a.base.as_ref(), b.base.as_ref());
+        }
+        assert_eq!(original.lights.len(), loaded.lights.len());
+        for (idx, (a, b)) in original.lights.iter().zip(loaded.lights.iter()).enumerate() {
+            assert_same_parameters(&format!("light {}", idx), a.as_ref(), b.as_ref());
+        }
+        assert_same_parameters("skybox", original.skybox.as_ref(), loaded.skybox.as_ref());
+
+        let (a, b) = (&original.camera, &loaded.camera);
+        for (name, a, b) in [("look_from", a.origin, b.origin), ("look_at", a.look_at, b.look_at), ("vup", a.vup, b.vup)] {
+            assert!(same(&Parameter::Vector(a), &Parameter::Vector(b)), "camera {} changed", name);
+        }
+        for (name, a, b) in [("vfov", a.vfov, b.vfov), ("aspect", a.aspect, b.aspect), ("lens_radius", a.lens_radius, b.lens_radius), ("focus_dist", a.focus_dist, b.focus_dist)] {
+            assert_eq!(a.to_bits(), b.to_bits(), "camera {} changed", name);
+        }
+
+        // the same seed renders the same pixels
+        let canvas = Canvas::new(16, 8, 1.0, 2.0);
+        let render = |scene: Scene| Renderer::new(scene, canvas, 4).with_seed(7).render(4);
+        let (a, b) = (render(scene(&dir)), render(loaded));
+        assert!((0..16 * 8).any(|i| a[(i % 16, i / 16)].g > 0.0), "the render is black");
+        for y in 0..8 {
+            for x in 0..16 {
+                let (a, b) = (a[(x, y)], b[(x, y)]);
+                assert_eq!([a.r, a.g, a.b].map(f32::to_bits), [b.r, b.g, b.b].map(f32::to_bits), "pixel ({}, {})", x, y);
+            }
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn the_default_camera_is_kept() {
+        let dir = temp_dir("default_camera");
+        let path = dir.join("scene.scene");
+        Scene::new().save(&path).unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains("[camera]"));
+        assert!(!Scene::load(&path).unwrap().camera.has_parameters());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,46 @@
+// The scene of the sphere example against a committed reference of many samples,
+// a render has to get closer to it with more samples and come within a tolerance.
+// The reference has to be rendered with the real gk_math dependency, by `render_the_sphere_reference`,
+// the convergence test is ignored until it is committed.
+
+#[path = "../examples/sphere/scene.rs"]
+mod scene;
+
+use solas::ImageData;
+use solas::renderer::Renderer;
+use solas::scene::Canvas;
+use solas::tone_mapping::ToneMapping;
+
+use scene::sphere_scene;
+
+const WIDTH: usize = 96;
+const HEIGHT: usize = 54;
+const REFERENCE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/reference/sphere.pfm");
+
+fn render(seed: u64, sample_number: usize) -> ImageData {
+    let canvas = Canvas::new(WIDTH, HEIGHT, 1.0, 2.0);
+    Renderer::new(sphere_scene(WIDTH as f32 / HEIGHT as f32), canvas, 64).with_seed(seed)
+        .multi_thread_render(16, 16, 0, sample_number).unwrap()
+}
+
+#[test]
+#[ignore = "tests/reference/sphere.pfm is not rendered yet, see render_the_sphere_reference"]
+fn the_sphere_example_converges_to_its_reference() {
+    let reference = ImageData::read_from_file(REFERENCE).unwrap();
+    let image = render(1, 64);
+    let coarse = render(1, 4).relative_mse(&reference).unwrap();
+    let fine = image.relative_mse(&reference).unwrap();
+    println!("relative mse: {} at 4 spp, {} at 64 spp", coarse, fine);
+    // the error of the mean falls with the sample number, 16 times the samples at least halve it
+    assert!(fine < coarse * 0.5, "the error went from {} to {}", coarse, fine);
+    assert!(fine < 0.01, "the error {} is above the tolerance", fine);
+    assert!(image.ssim(&reference).unwrap() > 0.9);
+}
+
+/// Render the reference, again after a change that is meant to change the image:
+/// `cargo test --release --test sphere_reference -- --ignored --exact render_the_sphere_reference`
+#[test]
+#[ignore = "renders the reference"]
+fn render_the_sphere_reference() {
+    render(0, 4096).write_to_file(ToneMapping::Linear, REFERENCE).unwrap();
+}